@@ -1,201 +1,4700 @@
-use std::io;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
 
 #[derive(Debug, Clone)]
 enum Token {
     Number(f64),
+    // An integer literal too large to hold exactly in an f64
+    Integer(i128),
+    Identifier(String),
+    Assign,
     Plus,
     Minus,
     Multiply,
     Divide,
+    Modulo,
+    Power,
+    Factorial,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    Comma,
+    // `in` or `to`, converting the result to other units
+    Convert,
     LeftParen,
     RightParen,
     End,
 }
 
+const CONSTANTS: &[(&str, f64)] = &[
+    ("pi", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("tau", std::f64::consts::TAU),
+];
+
+// Names that can't be assigned to
+const RESERVED: &[&str] = &[
+    "ans", "vars", "clear", "quit", "simplify",
+    "mode", "precision", "rounding", "format", "in", "to",
+    "type", "overflow", "base", "xor",
+];
+
+const FUNCTIONS: &[&str] = &[
+    "sqrt", "abs", "ln", "log10", "exp",
+    "sin", "cos", "tan", "asin", "acos", "atan",
+    "floor", "ceil", "round", "min", "max",
+    "arg", "conj", "re", "im",
+    // Symbolic: diff(expr, var) or diff(expr, var, at)
+    "diff",
+    // solve(lhs = rhs, var) or solve(lhs = rhs, var, from, to)
+    "solve",
+];
+
+// Functions that take more than one argument
+const MULTI_ARGUMENT_FUNCTIONS: &[&str] = &["min", "max", "diff", "solve"];
+
+fn call_function(name: &str, args: &[f64]) -> Result<f64, String> {
+    let unary = |f: fn(f64) -> f64| -> Result<f64, String> {
+        match args {
+            [x] => Ok(f(*x)),
+            _ => Err(format!("{}() takes 1 argument, got {}", name, args.len())),
+        }
+    };
+    let domain = |ok: bool, message: &str| -> Result<(), String> {
+        if ok { Ok(()) } else { Err(format!("{}(): {}", name, message)) }
+    };
+
+    match name {
+        "sqrt" => {
+            domain(args.iter().all(|x| *x >= 0.0), "argument must not be negative")?;
+            unary(f64::sqrt)
+        }
+        "ln" | "log10" => {
+            domain(args.iter().all(|x| *x > 0.0), "argument must be positive")?;
+            unary(if name == "ln" { f64::ln } else { f64::log10 })
+        }
+        "asin" | "acos" => {
+            domain(args.iter().all(|x| (-1.0..=1.0).contains(x)), "argument must be between -1 and 1")?;
+            unary(if name == "asin" { f64::asin } else { f64::acos })
+        }
+        "abs" => unary(f64::abs),
+        "exp" => unary(f64::exp),
+        "sin" => unary(f64::sin),
+        "cos" => unary(f64::cos),
+        "tan" => unary(f64::tan),
+        "atan" => unary(f64::atan),
+        "floor" => unary(f64::floor),
+        "ceil" => unary(f64::ceil),
+        "round" => unary(f64::round),
+        "arg" => unary(|x| if x < 0.0 { std::f64::consts::PI } else { 0.0 }),
+        "conj" | "re" => unary(|x| x),
+        "im" => unary(|_| 0.0),
+        "min" | "max" => {
+            if args.is_empty() {
+                return Err(format!("{}() needs at least 1 argument", name));
+            }
+            let pick = if name == "min" { f64::min } else { f64::max };
+            Ok(args[1..].iter().fold(args[0], |acc, x| pick(acc, *x)))
+        }
+        _ => Err(format!("Unknown function: {}", name)),
+    }
+}
+
+fn factorial(n: f64) -> Result<f64, String> {
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(format!("Factorial is only defined for non-negative integers, got {}", n));
+    }
+    // 171! no longer fits in an f64
+    if n > 170.0 {
+        return Err(format!("Factorial overflow: {}!", n));
+    }
+    Ok((1..=n as u64).map(|k| k as f64).product())
+}
+
+// ---------------------------------------------------------------------------
+// Arbitrary-precision integers and rationals for the exact numeric modes
+// ---------------------------------------------------------------------------
+
+const LIMB_BASE: u64 = 1_000_000_000;
+const LIMB_DIGITS: usize = 9;
+
+// Division and gcd are quadratic, so results are capped where reducing a
+// quotient of two of them still takes about a second in an optimized build
+const MAX_EXACT_EXPONENT: u64 = 100_000;
+const MAX_EXACT_DIGITS: u64 = 20_000;
+const MAX_EXACT_FACTORIAL: u64 = 5_000;
+
+// Sign and magnitude, with base-10^9 limbs stored least significant first
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    fn zero() -> Self {
+        BigInt { negative: false, limbs: Vec::new() }
+    }
+
+    fn from_u64(mut n: u64) -> Self {
+        let mut limbs = Vec::new();
+        while n > 0 {
+            limbs.push((n % LIMB_BASE) as u32);
+            n /= LIMB_BASE;
+        }
+        BigInt { negative: false, limbs }
+    }
+
+    // Parses an unsigned string of decimal digits
+    fn parse(digits: &str) -> Option<Self> {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut limbs = Vec::new();
+        let mut end = digits.len();
+        while end > 0 {
+            let start = end.saturating_sub(LIMB_DIGITS);
+            limbs.push(digits[start..end].parse().ok()?);
+            end = start;
+        }
+        Some(Self::normalized(false, limbs))
+    }
+
+    fn normalized(negative: bool, mut limbs: Vec<u32>) -> Self {
+        trim_limbs(&mut limbs);
+        BigInt { negative: negative && !limbs.is_empty(), limbs }
+    }
+
+    fn pow10(exponent: usize) -> Self {
+        let mut limbs = vec![0; exponent / LIMB_DIGITS];
+        limbs.push(10u32.pow((exponent % LIMB_DIGITS) as u32));
+        BigInt { negative: false, limbs }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn is_even(&self) -> bool {
+        self.limbs.first().copied().unwrap_or(0) % 2 == 0
+    }
+
+    fn abs(&self) -> Self {
+        BigInt { negative: false, limbs: self.limbs.clone() }
+    }
+
+    fn digit_count(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => (self.limbs.len() - 1) * LIMB_DIGITS + top.to_string().len(),
+            None => 1,
+        }
+    }
+
+    fn from_i128(n: i128) -> Self {
+        let mut magnitude = n.unsigned_abs();
+        let mut limbs = Vec::new();
+        while magnitude > 0 {
+            limbs.push((magnitude % LIMB_BASE as u128) as u32);
+            magnitude /= LIMB_BASE as u128;
+        }
+        BigInt::normalized(n < 0, limbs)
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        let mut magnitude: i128 = 0;
+        for limb in self.limbs.iter().rev() {
+            magnitude = magnitude.checked_mul(LIMB_BASE as i128)?.checked_add(*limb as i128)?;
+        }
+        Some(if self.negative { -magnitude } else { magnitude })
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        self.to_i128()?.try_into().ok()
+    }
+
+    // Truncating division, like Rust's integer `/` and `%`
+    fn div_rem(&self, divisor: &BigInt) -> (BigInt, BigInt) {
+        let (quotient, remainder) = divide_limbs(&self.limbs, &divisor.limbs);
+        (
+            BigInt::normalized(self.negative != divisor.negative, quotient),
+            BigInt::normalized(self.negative, remainder),
+        )
+    }
+
+    fn pow(&self, mut exponent: u64) -> BigInt {
+        let mut result = BigInt::from_u64(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let remainder = a.div_rem(&b).1;
+            a = b;
+            b = remainder;
+        }
+        a
+    }
+
+    // Floor of the square root of a non-negative integer, by Newton's method
+    fn isqrt(&self) -> BigInt {
+        if self.is_zero() {
+            return BigInt::zero();
+        }
+        let two = BigInt::from_u64(2);
+        let mut x = BigInt::pow10(self.digit_count() / 2 + 1);
+        loop {
+            let next = (&x + &self.div_rem(&x).0).div_rem(&two).0;
+            if next >= x {
+                return x;
+            }
+            x = next;
+        }
+    }
+}
+
+fn trim_limbs(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn compare_limbs(a: &[u32], b: &[u32]) -> std::cmp::Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = carry + *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64;
+        result.push((sum % LIMB_BASE) as u32);
+        carry = sum / LIMB_BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// Requires a >= b
+fn subtract_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut difference = limb as i64 - borrow - *b.get(i).unwrap_or(&0) as i64;
+        borrow = 0;
+        if difference < 0 {
+            difference += LIMB_BASE as i64;
+            borrow = 1;
+        }
+        result.push(difference as u32);
+    }
+    trim_limbs(&mut result);
+    result
+}
+
+fn multiply_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let current = result[i + j] + x as u64 * y as u64 + carry;
+            result[i + j] = current % LIMB_BASE;
+            carry = current / LIMB_BASE;
+        }
+        result[i + b.len()] += carry;
+    }
+    let mut limbs: Vec<u32> = result.into_iter().map(|limb| limb as u32).collect();
+    trim_limbs(&mut limbs);
+    limbs
+}
+
+// Divides by a single limb, returning the quotient and the remainder
+fn divide_limbs_short(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for i in (0..a.len()).rev() {
+        let current = remainder * LIMB_BASE + a[i] as u64;
+        quotient[i] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    trim_limbs(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+// Schoolbook long division (Knuth's algorithm D). Both operands are scaled
+// so the divisor's top limb is at least half the base; each quotient limb is
+// then estimated from the top limbs and is at most one too large.
+fn divide_limbs(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    assert!(!b.is_empty(), "division by zero");
+    if compare_limbs(a, b) == std::cmp::Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = divide_limbs_short(a, b[0]);
+        return (quotient, if remainder == 0 { Vec::new() } else { vec![remainder] });
+    }
+
+    let scale = (LIMB_BASE / (*b.last().unwrap() as u64 + 1)) as u32;
+    let mut u = multiply_limbs(a, &[scale]);
+    u.resize(a.len() + 1, 0);
+    let v = multiply_limbs(b, &[scale]);
+    let n = v.len();
+    let (top, second) = (v[n - 1] as u64, v[n - 2] as u64);
+
+    let mut quotient = vec![0u32; u.len() - n];
+    for j in (0..quotient.len()).rev() {
+        let numerator = u[j + n] as u64 * LIMB_BASE + u[j + n - 1] as u64;
+        let mut estimate = numerator / top;
+        let mut rest = numerator % top;
+        while estimate >= LIMB_BASE || estimate * second > rest * LIMB_BASE + u[j + n - 2] as u64 {
+            estimate -= 1;
+            rest += top;
+            if rest >= LIMB_BASE {
+                break;
+            }
+        }
+
+        // Subtract estimate * v from the window u[j..=j + n]
+        let mut carry = 0u64;
+        let mut borrow = 0i64;
+        for i in 0..n {
+            let product = estimate * v[i] as u64 + carry;
+            carry = product / LIMB_BASE;
+            let mut difference = u[i + j] as i64 - (product % LIMB_BASE) as i64 - borrow;
+            borrow = 0;
+            if difference < 0 {
+                difference += LIMB_BASE as i64;
+                borrow = 1;
+            }
+            u[i + j] = difference as u32;
+        }
+        let difference = u[j + n] as i64 - carry as i64 - borrow;
+        if difference < 0 {
+            // The estimate was one too large: add v back, dropping the carry out
+            estimate -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                u[i + j] = (sum % LIMB_BASE) as u32;
+                carry = sum / LIMB_BASE;
+            }
+            u[j + n] = ((difference + LIMB_BASE as i64 + carry as i64) % LIMB_BASE as i64) as u32;
+        } else {
+            u[j + n] = difference as u32;
+        }
+        quotient[j] = estimate as u32;
+    }
+
+    trim_limbs(&mut quotient);
+    u.truncate(n);
+    trim_limbs(&mut u);
+    (quotient, divide_limbs_short(&u, scale).0)
+}
+
+impl std::ops::Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::normalized(self.negative, add_limbs(&self.limbs, &other.limbs));
+        }
+        match compare_limbs(&self.limbs, &other.limbs) {
+            std::cmp::Ordering::Less => BigInt::normalized(other.negative, subtract_limbs(&other.limbs, &self.limbs)),
+            _ => BigInt::normalized(self.negative, subtract_limbs(&self.limbs, &other.limbs)),
+        }
+    }
+}
+
+impl std::ops::Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::normalized(!self.negative, self.limbs.clone())
+    }
+}
+
+impl std::ops::Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &(-other)
+    }
+}
+
+impl std::ops::Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::normalized(self.negative != other.negative, multiply_limbs(&self.limbs, &other.limbs))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => std::cmp::Ordering::Greater,
+            (true, false) => std::cmp::Ordering::Less,
+            (false, false) => compare_limbs(&self.limbs, &other.limbs),
+            (true, true) => compare_limbs(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some(top) = self.limbs.last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", top)?;
+        for limb in self.limbs.iter().rev().skip(1) {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RoundingMode {
+    HalfEven,
+    HalfUp,
+    HalfDown,
+    Down,
+    Up,
+    Floor,
+    Ceiling,
+}
+
+impl RoundingMode {
+    fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "half-even" | "bankers" => Some(RoundingMode::HalfEven),
+            "half-up" => Some(RoundingMode::HalfUp),
+            "half-down" => Some(RoundingMode::HalfDown),
+            "down" | "truncate" => Some(RoundingMode::Down),
+            "up" => Some(RoundingMode::Up),
+            "floor" => Some(RoundingMode::Floor),
+            "ceiling" => Some(RoundingMode::Ceiling),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            RoundingMode::HalfEven => "half-even",
+            RoundingMode::HalfUp => "half-up",
+            RoundingMode::HalfDown => "half-down",
+            RoundingMode::Down => "down",
+            RoundingMode::Up => "up",
+            RoundingMode::Floor => "floor",
+            RoundingMode::Ceiling => "ceiling",
+        }
+    }
+}
+
+// Always in lowest terms with a positive denominator
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rational {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    fn new(num: BigInt, den: BigInt) -> Self {
+        let divisor = num.gcd(&den);
+        let (mut num, mut den) = (num.div_rem(&divisor).0, den.div_rem(&divisor).0);
+        if den.negative {
+            num = -&num;
+            den = -&den;
+        }
+        Rational { num, den }
+    }
+
+    fn from_integer(n: BigInt) -> Self {
+        Rational { num: n, den: BigInt::from_u64(1) }
+    }
+
+    // Parses "-12.345" exactly
+    fn parse_decimal(text: &str) -> Option<Self> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let mut num = BigInt::parse(&format!("{}{}", whole, fraction))?;
+        num.negative = negative && !num.is_zero();
+        Some(Rational::new(num, BigInt::pow10(fraction.len())))
+    }
+
+    // Exact value of the shortest decimal that round-trips to `value`, so
+    // the literal 0.1 becomes 1/10
+    fn from_f64(value: f64) -> Result<Self, String> {
+        if !value.is_finite() {
+            return Err(format!("{} has no exact value", value));
+        }
+        Rational::parse_decimal(&value.to_string()).ok_or_else(|| format!("Cannot convert {} exactly", value))
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.round_significant(17, RoundingMode::HalfEven)
+            .to_decimal_string()
+            .parse()
+            .unwrap_or(f64::NAN)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.num.is_zero()
+    }
+
+    fn is_integer(&self) -> bool {
+        self.den == BigInt::from_u64(1)
+    }
+
+    fn abs(&self) -> Self {
+        Rational { num: self.num.abs(), den: self.den.clone() }
+    }
+
+    fn checked_div(&self, divisor: &Rational) -> Result<Rational, String> {
+        if divisor.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+        Ok(Rational::new(&self.num * &divisor.den, &self.den * &divisor.num))
+    }
+
+    fn pow(&self, exponent: i64) -> Result<Rational, String> {
+        if exponent < 0 && self.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+        let magnitude = exponent.unsigned_abs();
+        // log10 from the limb count and the top two limbs, close enough to
+        // estimate the digits of the result
+        let log10 = |n: &BigInt| match n.limbs.as_slice() {
+            [] => 0.0,
+            [top] => (*top as f64).log10(),
+            [.., next, top] => {
+                let leading = *top as f64 * LIMB_BASE as f64 + *next as f64;
+                ((n.limbs.len() - 2) * LIMB_DIGITS) as f64 + leading.log10()
+            }
+        };
+        let digits = log10(&self.num).max(log10(&self.den)) * magnitude as f64;
+        if magnitude > MAX_EXACT_EXPONENT || digits > MAX_EXACT_DIGITS as f64 {
+            return Err("Result too large for exact arithmetic".to_string());
+        }
+        let (num, den) = (self.num.pow(magnitude), self.den.pow(magnitude));
+        if exponent >= 0 {
+            return Ok(Rational { num, den });
+        }
+        // Powers of coprime numbers stay coprime, so only the sign moves
+        Ok(Rational { num: BigInt::normalized(num.negative, den.limbs), den: num.abs() })
+    }
+
+    // Rounds to an integer; `HalfUp` rounds halves away from zero like f64::round
+    fn round_with(&self, mode: RoundingMode) -> BigInt {
+        let (quotient, remainder) = self.num.div_rem(&self.den);
+        if remainder.is_zero() {
+            return quotient;
+        }
+
+        let half = (&remainder.abs() * &BigInt::from_u64(2)).cmp(&self.den);
+        let negative = self.num.negative;
+        let away_from_zero = match mode {
+            RoundingMode::Down => false,
+            RoundingMode::Up => true,
+            RoundingMode::Floor => negative,
+            RoundingMode::Ceiling => !negative,
+            RoundingMode::HalfUp => half != std::cmp::Ordering::Less,
+            RoundingMode::HalfDown => half == std::cmp::Ordering::Greater,
+            RoundingMode::HalfEven => half == std::cmp::Ordering::Greater || (half == std::cmp::Ordering::Equal && !quotient.is_even()),
+        };
+
+        if !away_from_zero {
+            quotient
+        } else if negative {
+            &quotient - &BigInt::from_u64(1)
+        } else {
+            &quotient + &BigInt::from_u64(1)
+        }
+    }
+
+    fn scale_pow10(&self, exponent: i64) -> Rational {
+        let factor = BigInt::pow10(exponent.unsigned_abs() as usize);
+        if exponent >= 0 {
+            Rational::new(&self.num * &factor, self.den.clone())
+        } else {
+            Rational::new(self.num.clone(), &self.den * &factor)
+        }
+    }
+
+    // Exponent of the leading digit, so that 10^e <= |self| < 10^(e+1)
+    fn magnitude(&self) -> i64 {
+        let estimate = self.num.digit_count() as i64 - self.den.digit_count() as i64;
+        if self.abs() >= Rational::from_integer(BigInt::from_u64(1)).scale_pow10(estimate) {
+            estimate
+        } else {
+            estimate - 1
+        }
+    }
+
+    fn round_significant(&self, digits: usize, mode: RoundingMode) -> Rational {
+        if self.is_zero() {
+            return self.clone();
+        }
+        let shift = digits as i64 - 1 - self.magnitude();
+        Rational::from_integer(self.scale_pow10(shift).round_with(mode)).scale_pow10(-shift)
+    }
+
+    // Number of decimal places if the decimal expansion terminates
+    fn decimal_places(&self) -> Option<usize> {
+        let mut den = self.den.clone();
+        let mut places = [0usize; 2];
+        for (i, factor) in [2u64, 5].iter().enumerate() {
+            let factor = BigInt::from_u64(*factor);
+            loop {
+                let (quotient, remainder) = den.div_rem(&factor);
+                if !remainder.is_zero() {
+                    break;
+                }
+                den = quotient;
+                places[i] += 1;
+            }
+        }
+        (den == BigInt::from_u64(1)).then(|| places[0].max(places[1]))
+    }
+
+    // Exact decimal digits; only meaningful when the expansion terminates
+    fn to_decimal_string(&self) -> String {
+        let places = self.decimal_places().unwrap_or(0);
+        let scaled = self.scale_pow10(places as i64).round_with(RoundingMode::HalfEven);
+        let mut digits = scaled.abs().to_string();
+        if places > 0 {
+            if digits.len() <= places {
+                digits = format!("{}{}", "0".repeat(places + 1 - digits.len()), digits);
+            }
+            digits.insert(digits.len() - places, '.');
+        }
+        format!("{}{}", if scaled.negative { "-" } else { "" }, digits)
+    }
+
+    // Exact when the expansion terminates within `digits` significant
+    // digits, otherwise rounded and marked with a trailing "..."; very large
+    // and very small values switch to scientific notation
+    fn display_decimal(&self, digits: usize, mode: RoundingMode) -> String {
+        let rounded = self.round_significant(digits, mode);
+        let exponent = if rounded.is_zero() { 0 } else { rounded.magnitude() };
+        let text = if exponent >= digits as i64 || exponent < -(digits as i64) {
+            format!("{}e{}", rounded.scale_pow10(-exponent).to_decimal_string(), exponent)
+        } else {
+            rounded.to_decimal_string()
+        };
+        if rounded == *self { text } else { format!("{}...", text) }
+    }
+
+    fn display_fraction(&self) -> String {
+        if self.is_integer() {
+            self.num.to_string()
+        } else {
+            format!("{}/{}", self.num, self.den)
+        }
+    }
+}
+
+impl std::ops::Add for &Rational {
+    type Output = Rational;
+
+    fn add(self, other: &Rational) -> Rational {
+        Rational::new(&(&self.num * &other.den) + &(&other.num * &self.den), &self.den * &other.den)
+    }
+}
+
+impl std::ops::Sub for &Rational {
+    type Output = Rational;
+
+    fn sub(self, other: &Rational) -> Rational {
+        self + &(-other)
+    }
+}
+
+impl std::ops::Mul for &Rational {
+    type Output = Rational;
+
+    fn mul(self, other: &Rational) -> Rational {
+        Rational::new(&self.num * &other.num, &self.den * &other.den)
+    }
+}
+
+impl std::ops::Neg for &Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational { num: -&self.num, den: self.den.clone() }
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.num * &other.den).cmp(&(&other.num * &self.den))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// pi and e to `digits` significant digits, computed in fixed point with a
+// few guard digits (Machin's formula for pi, the factorial series for e)
+fn exact_constant(name: &str, digits: usize) -> Option<Rational> {
+    let scale = BigInt::pow10(digits + 10);
+    let fixed = match name {
+        "pi" | "tau" => {
+            let pi = &(&BigInt::from_u64(16) * &fixed_arctan_inverse(5, &scale))
+                - &(&BigInt::from_u64(4) * &fixed_arctan_inverse(239, &scale));
+            if name == "tau" { &pi * &BigInt::from_u64(2) } else { pi }
+        }
+        "e" => {
+            let mut sum = BigInt::zero();
+            let mut term = scale.clone();
+            let mut k = 0;
+            while !term.is_zero() {
+                sum = &sum + &term;
+                k += 1;
+                term = term.div_rem(&BigInt::from_u64(k)).0;
+            }
+            sum
+        }
+        _ => return None,
+    };
+    Some(Rational::new(fixed, scale).round_significant(digits, RoundingMode::HalfEven))
+}
+
+// arctan(1/x) * scale
+fn fixed_arctan_inverse(x: u64, scale: &BigInt) -> BigInt {
+    let x_squared = BigInt::from_u64(x * x);
+    let mut power = scale.div_rem(&BigInt::from_u64(x)).0;
+    let mut sum = power.clone();
+    let mut k = 1;
+    loop {
+        power = power.div_rem(&x_squared).0;
+        let term = power.div_rem(&BigInt::from_u64(2 * k + 1)).0;
+        if term.is_zero() {
+            return sum;
+        }
+        sum = if k % 2 == 1 { &sum - &term } else { &sum + &term };
+        k += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NumericMode {
+    Float,
+    // Exact fractions; irrational results are errors
+    Rational,
+    // Every result rounded to the configured significant digits
+    Decimal,
+    // Fixed-width integers with C-like bitwise operators
+    Integer,
+    // Floats with an imaginary part, written with `i`
+    Complex,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Float(f64),
+    Exact(Rational),
+    // A float-mode result with units
+    Measured(Quantity),
+    Integer(i128),
+    // A result with a nonzero imaginary part
+    Complex(Complex),
+}
+
+impl Value {
+    fn to_quantity(&self) -> Quantity {
+        match self {
+            Value::Float(value) => Quantity::number(*value),
+            Value::Exact(value) => Quantity::number(value.to_f64()),
+            Value::Measured(quantity) => quantity.clone(),
+            Value::Integer(value) => Quantity::number(*value as f64),
+            // Only the real part; Environment::get rejects anything else
+            Value::Complex(z) => Quantity::number(z.re),
+        }
+    }
+
+    fn to_exact(&self) -> Result<Rational, String> {
+        match self {
+            Value::Float(value) => Rational::from_f64(*value),
+            Value::Exact(value) => Ok(value.clone()),
+            Value::Measured(quantity) => Err(format!("{} has units, which need 'mode float'", quantity)),
+            Value::Integer(value) => Ok(Rational::from_integer(BigInt::from_i128(*value))),
+            Value::Complex(z) => Err(format!("{} is complex, which needs 'mode complex'", z)),
+        }
+    }
+
+    fn to_complex(&self) -> Result<Complex, String> {
+        match self {
+            Value::Complex(z) => Ok(*z),
+            Value::Measured(quantity) => Err(format!("{} has units, which need 'mode float'", quantity)),
+            value => Ok(Complex::real(value.to_quantity().value)),
+        }
+    }
+}
+
+impl From<Quantity> for Value {
+    fn from(quantity: Quantity) -> Self {
+        if quantity.is_dimensionless() {
+            Value::Float(quantity.value)
+        } else {
+            Value::Measured(quantity)
+        }
+    }
+}
+
+fn single_argument<'a, T>(name: &str, args: &'a [T]) -> Result<&'a T, String> {
+    match args {
+        [x] => Ok(x),
+        _ => Err(format!("{}() takes 1 argument, got {}", name, args.len())),
+    }
+}
+
+fn exact_factorial(n: &Rational) -> Result<Rational, String> {
+    let value = match n.num.to_i64() {
+        Some(value) if n.is_integer() && value >= 0 => value as u64,
+        _ => return Err(format!("Factorial is only defined for non-negative integers, got {}", n.display_fraction())),
+    };
+    if value > MAX_EXACT_FACTORIAL {
+        return Err(format!("Factorial too large for exact arithmetic: {}!", value));
+    }
+    let mut result = BigInt::from_u64(1);
+    for k in 2..=value {
+        result = &result * &BigInt::from_u64(k);
+    }
+    Ok(Rational::from_integer(result))
+}
+
+fn exact_sqrt(x: &Rational, env: &Environment) -> Result<Rational, String> {
+    if x.num.negative {
+        return Err("sqrt(): argument must not be negative".to_string());
+    }
+    let (num_root, den_root) = (x.num.isqrt(), x.den.isqrt());
+    if &num_root * &num_root == x.num && &den_root * &den_root == x.den {
+        return Ok(Rational::new(num_root, den_root));
+    }
+    if env.mode != NumericMode::Decimal {
+        return Err(format!("sqrt({}) is irrational; use 'mode decimal' or 'mode float'", x.display_fraction()));
+    }
+
+    // sqrt(n/d) = sqrt(n*d)/d, with enough extra places for every digit
+    let places = env.precision + 5 + x.den.digit_count();
+    let root = (&(&x.num * &x.den) * &BigInt::pow10(2 * places)).isqrt();
+    Ok(Rational::new(root, &x.den * &BigInt::pow10(places)))
+}
+
+fn exact_power(base: &Rational, exponent: &Rational, env: &Environment) -> Result<Rational, String> {
+    if exponent.is_integer() {
+        let exponent = exponent.num.to_i64().ok_or("Result too large for exact arithmetic")?;
+        return base.pow(exponent);
+    }
+    // Half-integer powers go through the exact square root
+    if exponent.den == BigInt::from_u64(2) {
+        let exponent = exponent.num.to_i64().ok_or("Result too large for exact arithmetic")?;
+        return exact_sqrt(base, env)?.pow(exponent);
+    }
+    match env.mode {
+        NumericMode::Decimal => Rational::from_f64(base.to_f64().powf(exponent.to_f64())),
+        _ => Err(format!(
+            "{}^{} has no exact rational result; use 'mode decimal' or 'mode float'",
+            base.display_fraction(),
+            exponent.display_fraction()
+        )),
+    }
+}
+
+fn call_function_exact(name: &str, args: &[Rational], env: &Environment) -> Result<Rational, String> {
+    match name {
+        "abs" => Ok(single_argument(name, args)?.abs()),
+        "floor" => Ok(Rational::from_integer(single_argument(name, args)?.round_with(RoundingMode::Floor))),
+        "ceil" => Ok(Rational::from_integer(single_argument(name, args)?.round_with(RoundingMode::Ceiling))),
+        "round" => Ok(Rational::from_integer(single_argument(name, args)?.round_with(RoundingMode::HalfUp))),
+        "sqrt" => exact_sqrt(single_argument(name, args)?, env),
+        "conj" | "re" => Ok(single_argument(name, args)?.clone()),
+        "im" => single_argument(name, args).map(|_| Rational::from_integer(BigInt::zero())),
+        "min" | "max" => {
+            let pick = if name == "min" { std::cmp::min } else { std::cmp::max };
+            args.iter()
+                .cloned()
+                .reduce(pick)
+                .ok_or_else(|| format!("{}() needs at least 1 argument", name))
+        }
+        // Transcendental functions are computed in double precision
+        _ if env.mode == NumericMode::Decimal => {
+            let floats: Vec<f64> = args.iter().map(Rational::to_f64).collect();
+            Rational::from_f64(call_function(name, &floats)?)
+        }
+        _ => Err(format!("{}() has no exact rational result; use 'mode decimal' or 'mode float'", name)),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Physical and data units
+// ---------------------------------------------------------------------------
+
+// Exponents of length, mass, time and data
+type Dimensions = [i32; 4];
+
+// Results without named units print in these, like `m/s` or `B/s`
+const BASE_UNITS: [&str; 4] = ["m", "kg", "s", "B"];
+
+// Prefix and scale, like `Gi` for 2^30
+type Prefixes = &'static [(&'static str, f64)];
+
+const METRIC_PREFIXES: Prefixes = &[("k", 1e3), ("c", 1e-2), ("m", 1e-3), ("u", 1e-6), ("n", 1e-9)];
+const TIME_PREFIXES: Prefixes = &[("m", 1e-3), ("u", 1e-6), ("n", 1e-9)];
+const FREQUENCY_PREFIXES: Prefixes = &[("k", 1e3), ("M", 1e6), ("G", 1e9)];
+const RATE_PREFIXES: Prefixes = &[("k", 1e3), ("M", 1e6), ("G", 1e9), ("T", 1e12)];
+const DATA_PREFIXES: Prefixes = &[
+    ("k", 1e3), ("M", 1e6), ("G", 1e9), ("T", 1e12), ("P", 1e15),
+    ("Ki", 1024.0), ("Mi", 1048576.0), ("Gi", 1073741824.0), ("Ti", 1099511627776.0), ("Pi", 1125899906842624.0),
+];
+
+// Symbol, size in base units, dimensions and the prefixes it accepts
+const UNITS: &[(&str, f64, Dimensions, Prefixes)] = &[
+    ("m", 1.0, [1, 0, 0, 0], METRIC_PREFIXES),
+    ("inch", 0.0254, [1, 0, 0, 0], &[]),
+    ("ft", 0.3048, [1, 0, 0, 0], &[]),
+    ("mi", 1609.344, [1, 0, 0, 0], &[]),
+    ("g", 1e-3, [0, 1, 0, 0], METRIC_PREFIXES),
+    ("lb", 0.45359237, [0, 1, 0, 0], &[]),
+    ("oz", 0.028349523125, [0, 1, 0, 0], &[]),
+    ("s", 1.0, [0, 0, 1, 0], TIME_PREFIXES),
+    ("min", 60.0, [0, 0, 1, 0], &[]),
+    ("h", 3600.0, [0, 0, 1, 0], &[]),
+    ("day", 86400.0, [0, 0, 1, 0], &[]),
+    ("week", 604800.0, [0, 0, 1, 0], &[]),
+    ("year", 31557600.0, [0, 0, 1, 0], &[]),
+    ("B", 1.0, [0, 0, 0, 1], DATA_PREFIXES),
+    ("bit", 0.125, [0, 0, 0, 1], DATA_PREFIXES),
+    ("Hz", 1.0, [0, 0, -1, 0], FREQUENCY_PREFIXES),
+    ("bps", 0.125, [0, 0, -1, 1], RATE_PREFIXES),
+    ("mph", 0.44704, [1, 0, -1, 0], &[]),
+];
+
+// Looks up a unit symbol, with or without a prefix, like `km` or `GiB`
+fn find_unit(name: &str) -> Option<(f64, Dimensions)> {
+    if let Some((_, factor, dimensions, _)) = UNITS.iter().find(|(symbol, ..)| *symbol == name) {
+        return Some((*factor, *dimensions));
+    }
+    UNITS.iter().find_map(|(symbol, factor, dimensions, prefixes)| {
+        let prefix = name.strip_suffix(symbol)?;
+        let (_, scale) = prefixes.iter().find(|(candidate, _)| *candidate == prefix)?;
+        Some((scale * factor, *dimensions))
+    })
+}
+
+// A unit raised to a power, like the `s^-2` in `m/s^2`
+type UnitPower = (String, i32);
+
+fn units_factor(units: &[UnitPower]) -> (f64, Dimensions) {
+    let mut factor = 1.0;
+    let mut dimensions = [0; 4];
+    for (name, power) in units {
+        let (size, unit_dimensions) = find_unit(name).unwrap_or((1.0, [0; 4]));
+        factor *= size.powi(*power);
+        for (total, d) in dimensions.iter_mut().zip(unit_dimensions) {
+            *total += d * power;
+        }
+    }
+    (factor, dimensions)
+}
+
+fn format_units(units: &[UnitPower]) -> String {
+    let write = |name: &str, power: i32| if power == 1 { name.to_string() } else { format!("{}^{}", name, power) };
+    let numerator: Vec<String> = units.iter().filter(|(_, p)| *p > 0).map(|(name, p)| write(name, *p)).collect();
+    let denominator: Vec<String> = units.iter().filter(|(_, p)| *p < 0).map(|(name, p)| write(name, -p)).collect();
+
+    // A bare denominator prints as `s^-1` rather than `1/s`
+    if numerator.is_empty() {
+        return units.iter().map(|(name, p)| format!("{}^{}", name, p)).collect::<Vec<_>>().join("*");
+    }
+    let mut text = numerator.join("*");
+    for unit in denominator {
+        text.push('/');
+        text.push_str(&unit);
+    }
+    text
+}
+
+// A number with dimensions. The value is expressed in `units` when it has
+// any, otherwise in the base units, so `40 km/h * 2.5 h` stays in km
+// instead of picking up rounding error from a trip through metres.
+#[derive(Debug, Clone, PartialEq)]
+struct Quantity {
+    value: f64,
+    dimensions: Dimensions,
+    units: Vec<UnitPower>,
+}
+
+impl Quantity {
+    fn number(value: f64) -> Self {
+        Quantity { value, dimensions: [0; 4], units: Vec::new() }
+    }
+
+    fn unit(name: &str) -> Option<Self> {
+        let (_, dimensions) = find_unit(name)?;
+        Some(Quantity { value: 1.0, dimensions, units: vec![(name.to_string(), 1)] })
+    }
+
+    fn is_dimensionless(&self) -> bool {
+        self.dimensions == [0; 4]
+    }
+
+    fn into_number(self) -> Result<f64, String> {
+        if self.is_dimensionless() {
+            Ok(self.value)
+        } else {
+            Err(format!("Expected a plain number, got a quantity in {}", self.unit_name()))
+        }
+    }
+
+    fn base_value(&self) -> f64 {
+        self.value * units_factor(&self.units).0
+    }
+
+    fn unit_name(&self) -> String {
+        if !self.units.is_empty() {
+            return format_units(&self.units);
+        }
+        let base: Vec<UnitPower> = BASE_UNITS
+            .iter()
+            .zip(self.dimensions)
+            .filter(|(_, power)| *power != 0)
+            .map(|(name, power)| (name.to_string(), power))
+            .collect();
+        format_units(&base)
+    }
+
+    fn describe(&self) -> String {
+        if self.is_dimensionless() { "a plain number".to_string() } else { self.unit_name() }
+    }
+
+    // Keeps `units` for display only if they still describe the dimensions
+    // on their own; `GiB/MB*s` mixes two data units, so it becomes `s`
+    fn with_units(value: f64, dimensions: Dimensions, units: Vec<UnitPower>) -> Self {
+        let (factor, unit_dimensions) = units_factor(&units);
+        let overlapping = (0..4).any(|d| {
+            units.iter().filter(|(name, _)| find_unit(name).is_some_and(|(_, dims)| dims[d] != 0)).count() > 1
+        });
+        if unit_dimensions == dimensions && !overlapping {
+            Quantity { value, dimensions, units }
+        } else {
+            Quantity { value: value * factor, dimensions, units: Vec::new() }
+        }
+    }
+
+    fn convert_to(&self, units: &[UnitPower]) -> Result<Quantity, String> {
+        let (factor, dimensions) = units_factor(units);
+        if dimensions != self.dimensions {
+            return Err(format!("Cannot convert {} to {}", self.describe(), format_units(units)));
+        }
+        Ok(Quantity { value: self.base_value() / factor, dimensions, units: units.to_vec() })
+    }
+
+    // The other operand's value in this quantity's units, for + - and %
+    fn matching(&self, other: &Quantity, verb: &str) -> Result<f64, String> {
+        if self.dimensions != other.dimensions {
+            return Err(format!("Cannot {} {} and {}", verb, self.describe(), other.describe()));
+        }
+        Ok(other.base_value() / units_factor(&self.units).0)
+    }
+
+    fn combine(op: &BinaryOp, left: Quantity, right: Quantity) -> Result<Quantity, String> {
+        if left.is_dimensionless() && right.is_dimensionless() {
+            return Ok(Quantity::number(op.apply(left.base_value(), right.base_value())?));
+        }
+        match op {
+            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Modulo => {
+                let verb = match op {
+                    BinaryOp::Add => "add",
+                    BinaryOp::Subtract => "subtract",
+                    _ => "take the remainder of",
+                };
+                let right_value = left.matching(&right, verb)?;
+                Ok(Quantity { value: op.apply(left.value, right_value)?, ..left })
+            }
+            BinaryOp::Multiply | BinaryOp::Divide => {
+                let sign = if matches!(op, BinaryOp::Multiply) { 1 } else { -1 };
+                let mut units = left.units;
+                for (name, power) in right.units {
+                    match units.iter_mut().find(|(existing, _)| *existing == name) {
+                        Some((_, total)) => *total += sign * power,
+                        None => units.push((name, sign * power)),
+                    }
+                }
+                units.retain(|(_, power)| *power != 0);
+                let mut dimensions = left.dimensions;
+                for (total, d) in dimensions.iter_mut().zip(right.dimensions) {
+                    *total += sign * d;
+                }
+                Ok(Quantity::with_units(op.apply(left.value, right.value)?, dimensions, units))
+            }
+            BinaryOp::Power => left.powf(right.into_number().map_err(|_| "Exponent must be a plain number".to_string())?),
+            _ => {
+                let quantity = if left.is_dimensionless() { right } else { left };
+                Err(format!("Bitwise operators need plain numbers, got a quantity in {}", quantity.unit_name()))
+            }
+        }
+    }
+
+    fn powf(self, exponent: f64) -> Result<Quantity, String> {
+        let scaled = |power: i32| {
+            let result = power as f64 * exponent;
+            (result.fract() == 0.0).then_some(result as i32)
+        };
+        let dimensions = self.dimensions.map(scaled);
+        if dimensions.iter().any(Option::is_none) {
+            return Err(format!("Cannot raise {} to the power {}", self.unit_name(), exponent));
+        }
+        let units: Option<Vec<UnitPower>> = self.units.iter().map(|(name, power)| Some((name.clone(), scaled(*power)?))).collect();
+        let dimensions = dimensions.map(|d| d.unwrap_or(0));
+        Ok(match units {
+            Some(units) => Quantity::with_units(self.value.powf(exponent), dimensions, units),
+            None => Quantity::with_units(self.base_value().powf(exponent), dimensions, Vec::new()),
+        })
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_dimensionless() {
+            write!(f, "{}", self.value)
+        } else {
+            write!(f, "{} {}", self.value, self.unit_name())
+        }
+    }
+}
+
+// Functions applied to quantities: abs, sqrt, min and max keep their units,
+// everything else needs plain numbers
+fn call_function_quantity(name: &str, args: Vec<Quantity>) -> Result<Quantity, String> {
+    if args.iter().all(Quantity::is_dimensionless) {
+        let values: Vec<f64> = args.iter().map(Quantity::base_value).collect();
+        return call_function(name, &values).map(Quantity::number);
+    }
+    match name {
+        "abs" | "sqrt" => {
+            let x = single_argument(name, &args)?.clone();
+            if name == "abs" {
+                Ok(Quantity { value: x.value.abs(), ..x })
+            } else if x.value < 0.0 {
+                Err("sqrt(): argument must not be negative".to_string())
+            } else {
+                x.powf(0.5)
+            }
+        }
+        "min" | "max" => {
+            let mut best = args[0].clone();
+            for arg in &args[1..] {
+                let value = best.matching(arg, "compare")?;
+                if (name == "min" && value < best.value) || (name == "max" && value > best.value) {
+                    best = arg.clone();
+                }
+            }
+            Ok(best)
+        }
+        _ => {
+            let quantity = args.iter().find(|arg| !arg.is_dimensionless()).map(Quantity::unit_name).unwrap_or_default();
+            Err(format!("{}() needs plain numbers, got a quantity in {}", name, quantity))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Fixed-width integers for programmer mode
+// ---------------------------------------------------------------------------
+
+// Literals beyond this can't be held exactly by an f64
+const MAX_EXACT_FLOAT_INTEGER: i128 = 1 << 53;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IntegerType {
+    bits: u32,
+    signed: bool,
+}
+
+impl IntegerType {
+    fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let (signed, bits) = match input.strip_prefix('i') {
+            Some(bits) => (true, bits),
+            None => (false, input.strip_prefix('u')?),
+        };
+        match bits.parse() {
+            Ok(bits @ (8 | 16 | 32 | 64)) => Some(IntegerType { bits, signed }),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("{}{}", if self.signed { "i" } else { "u" }, self.bits)
+    }
+
+    fn mask(&self) -> i128 {
+        (1i128 << self.bits) - 1
+    }
+
+    fn range(&self) -> (i128, i128) {
+        if self.signed {
+            (-(1i128 << (self.bits - 1)), (1i128 << (self.bits - 1)) - 1)
+        } else {
+            (0, self.mask())
+        }
+    }
+
+    // Two's complement truncation to the type's width
+    fn wrap(&self, value: i128) -> i128 {
+        let truncated = value & self.mask();
+        if self.signed && truncated > self.range().1 {
+            truncated - (1i128 << self.bits)
+        } else {
+            truncated
+        }
+    }
+
+    fn fit(&self, value: i128, wrapping: bool) -> Result<i128, String> {
+        let (min, max) = self.range();
+        if wrapping {
+            Ok(self.wrap(value))
+        } else if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(format!("Overflow: {} doesn't fit in {}", value, self.name()))
+        }
+    }
+}
+
+// Whole numbers for the bitwise operators outside integer mode
+fn whole_number(value: f64) -> Result<i128, String> {
+    if value.fract() != 0.0 || value.abs() >= i64::MAX as f64 {
+        return Err(format!("Bitwise operators need whole numbers within 64 bits, got {}", value));
+    }
+    Ok(value as i128)
+}
+
+// & | ^ << >> on operands of at most `bits` bits, which can't overflow an i128
+fn apply_bitwise(op: &BinaryOp, left: i128, right: i128, bits: u32) -> Result<i128, String> {
+    if matches!(op, BinaryOp::ShiftLeft | BinaryOp::ShiftRight) && !(0..bits as i128).contains(&right) {
+        return Err(format!("Shift amount must be between 0 and {}, got {}", bits - 1, right));
+    }
+    match op {
+        BinaryOp::BitAnd => Ok(left & right),
+        BinaryOp::BitOr => Ok(left | right),
+        BinaryOp::BitXor => Ok(left ^ right),
+        BinaryOp::ShiftLeft => Ok(left << right),
+        BinaryOp::ShiftRight => Ok(left >> right),
+        _ => Err(format!("{} is not a bitwise operator", op.symbol().trim())),
+    }
+}
+
+fn integer_factorial(n: i128, ty: IntegerType, wrapping: bool) -> Result<i128, String> {
+    if n < 0 {
+        return Err(format!("Factorial is only defined for non-negative integers, got {}", n));
+    }
+    // Past 2*bits the product has more factors of two than the type has bits
+    if wrapping && n >= 2 * ty.bits as i128 {
+        return Ok(0);
+    }
+    (2..=n).try_fold(1i128, |product, k| ty.fit(product * k, wrapping))
+}
+
+// Digits in any base from 2 to 36; 2, 8 and 16 get their literal prefixes
+fn format_integer(value: i128, base: u32) -> String {
+    let mut magnitude = value.unsigned_abs();
+    let mut digits = Vec::new();
+    loop {
+        digits.push(std::char::from_digit((magnitude % base as u128) as u32, base).unwrap_or('?').to_ascii_uppercase());
+        magnitude /= base as u128;
+        if magnitude == 0 {
+            break;
+        }
+    }
+    let digits: String = digits.into_iter().rev().collect();
+    let sign = if value < 0 { "-" } else { "" };
+    match base {
+        2 => format!("{}0b{}", sign, digits),
+        8 => format!("{}0o{}", sign, digits),
+        16 => format!("{}0x{}", sign, digits),
+        10 => format!("{}{}", sign, digits),
+        _ => format!("{}{} (base {})", sign, digits, base),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Complex numbers
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    fn is_real(&self) -> bool {
+        self.im == 0.0
+    }
+
+    fn into_real(self) -> Result<f64, String> {
+        if self.is_real() {
+            Ok(self.re)
+        } else {
+            Err(format!("Expected a real number, got {}", self))
+        }
+    }
+
+    fn abs(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn arg(&self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    fn conj(&self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    fn add(self, other: Complex) -> Self {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+
+    fn sub(self, other: Complex) -> Self {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+
+    fn mul(self, other: Complex) -> Self {
+        Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+    }
+
+    fn div(self, other: Complex) -> Result<Self, String> {
+        if other.re == 0.0 && other.im == 0.0 {
+            return Err("Division by zero".to_string());
+        }
+        let scale = other.re * other.re + other.im * other.im;
+        Ok(Complex::new(
+            (self.re * other.re + self.im * other.im) / scale,
+            (self.im * other.re - self.re * other.im) / scale,
+        ))
+    }
+
+    fn exp(self) -> Self {
+        let magnitude = self.re.exp();
+        Complex::new(magnitude * self.im.cos(), magnitude * self.im.sin())
+    }
+
+    // Principal value, with the argument in (-pi, pi]
+    fn ln(self) -> Result<Self, String> {
+        if self.re == 0.0 && self.im == 0.0 {
+            return Err("ln(): argument must not be zero".to_string());
+        }
+        Ok(Complex::new(self.abs().ln(), self.arg()))
+    }
+
+    // Principal root, with a non-negative real part; exact for negative reals
+    fn sqrt(self) -> Self {
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn powc(self, exponent: Complex) -> Result<Self, String> {
+        if self.re == 0.0 && self.im == 0.0 {
+            return match exponent {
+                _ if exponent.re == 0.0 && exponent.im == 0.0 => Ok(Complex::real(1.0)),
+                _ if exponent.re > 0.0 => Ok(Complex::real(0.0)),
+                _ => Err("Division by zero".to_string()),
+            };
+        }
+        // Repeated multiplication keeps i^2 = -1 exact
+        if exponent.is_real() && exponent.re.fract() == 0.0 && exponent.re.abs() <= 64.0 {
+            let mut result = Complex::real(1.0);
+            for _ in 0..exponent.re.abs() as u32 {
+                result = result.mul(self);
+            }
+            return if exponent.re < 0.0 { Complex::real(1.0).div(result) } else { Ok(result) };
+        }
+        Ok(exponent.mul(self.ln()?).exp())
+    }
+
+    fn sin(self) -> Self {
+        Complex::new(self.re.sin() * self.im.cosh(), self.re.cos() * self.im.sinh())
+    }
+
+    fn cos(self) -> Self {
+        Complex::new(self.re.cos() * self.im.cosh(), -self.re.sin() * self.im.sinh())
+    }
+
+    // asin(z) = -i*ln(iz + sqrt(1 - z^2))
+    fn asin(self) -> Result<Self, String> {
+        let root = Complex::real(1.0).sub(self.mul(self)).sqrt();
+        Ok(Complex::new(0.0, -1.0).mul(Complex::I.mul(self).add(root).ln()?))
+    }
+
+    // atan(z) = i/2 * ln((i + z)/(i - z))
+    fn atan(self) -> Result<Self, String> {
+        if self == Complex::I || self == Complex::new(0.0, -1.0) {
+            return Err("atan(): argument must not be i or -i".to_string());
+        }
+        Ok(Complex::new(0.0, 0.5).mul(Complex::I.add(self).div(Complex::I.sub(self))?.ln()?))
+    }
+
+    // r*exp(ti), which reads back in as the same number
+    fn to_polar_string(self) -> String {
+        let (r, t) = (self.abs(), self.arg());
+        match () {
+            _ if r == 0.0 => "0".to_string(),
+            _ if t == 0.0 => r.to_string(),
+            _ if r == 1.0 => format!("exp({}i)", t),
+            _ => format!("{}*exp({}i)", r, t),
+        }
+    }
+}
+
+// Rectangular form, like 3 - 4i. A part that's rounding noise next to the
+// other, as in exp(i*pi) = -1 + 1.2e-16i, is left out.
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let noise = |part: f64, other: f64| part.abs() < 1e-15 * other.abs();
+        let (re, im) = (
+            if noise(self.re, self.im) { 0.0 } else { self.re },
+            if noise(self.im, self.re) { 0.0 } else { self.im },
+        );
+        let imaginary = |im: f64| if im == 1.0 { "i".to_string() } else { format!("{}i", im) };
+        match (re, im) {
+            (re, 0.0) => write!(f, "{}", re),
+            (0.0, -1.0) => write!(f, "-i"),
+            (0.0, im) => write!(f, "{}", imaginary(im)),
+            (re, im) if im < 0.0 => write!(f, "{} - {}", re, imaginary(-im)),
+            (re, im) => write!(f, "{} + {}", re, imaginary(im)),
+        }
+    }
+}
+
+impl From<Complex> for Value {
+    fn from(z: Complex) -> Self {
+        if z.is_real() {
+            Value::Float(z.re)
+        } else {
+            Value::Complex(z)
+        }
+    }
+}
+
+// Real arguments go through the ordinary functions, so complex mode agrees
+// with float mode wherever a real result exists
+fn call_function_complex(name: &str, args: &[Complex]) -> Result<Complex, String> {
+    if args.iter().all(Complex::is_real) {
+        let reals: Vec<f64> = args.iter().map(|z| z.re).collect();
+        if let Ok(result) = call_function(name, &reals) {
+            return Ok(Complex::real(result));
+        }
+    }
+    match name {
+        "abs" => Ok(Complex::real(single_argument(name, args)?.abs())),
+        "arg" => Ok(Complex::real(single_argument(name, args)?.arg())),
+        "conj" => Ok(single_argument(name, args)?.conj()),
+        "re" => Ok(Complex::real(single_argument(name, args)?.re)),
+        "im" => Ok(Complex::real(single_argument(name, args)?.im)),
+        "sqrt" => Ok(single_argument(name, args)?.sqrt()),
+        "exp" => Ok(single_argument(name, args)?.exp()),
+        "ln" => single_argument(name, args)?.ln(),
+        "log10" => single_argument(name, args)?.ln()?.div(Complex::real(std::f64::consts::LN_10)),
+        "sin" => Ok(single_argument(name, args)?.sin()),
+        "cos" => Ok(single_argument(name, args)?.cos()),
+        "tan" => {
+            let z = single_argument(name, args)?;
+            z.sin().div(z.cos())
+        }
+        "asin" => single_argument(name, args)?.asin(),
+        "acos" => Ok(Complex::real(std::f64::consts::FRAC_PI_2).sub(single_argument(name, args)?.asin()?)),
+        "atan" => single_argument(name, args)?.atan(),
+        _ => {
+            let reals = args.iter().map(|z| z.into_real()).collect::<Result<Vec<f64>, String>>()?;
+            call_function(name, &reals).map(Complex::real)
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Expression tree
+// ---------------------------------------------------------------------------
+
+// Binding strength, used both by the parser's grammar and when printing
+// The bitwise levels follow C: | below ^ below & below shifts below +
+const BIT_OR: u8 = 1;
+const BIT_XOR: u8 = 2;
+const BIT_AND: u8 = 3;
+const SHIFT: u8 = 4;
+const ADDITIVE: u8 = 5;
+const MULTIPLICATIVE: u8 = 6;
+const UNARY: u8 = 7;
+const POWER: u8 = 8;
+const POSTFIX: u8 = 9;
+const ATOM: u8 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl BinaryOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => " + ",
+            BinaryOp::Subtract => " - ",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Power => "^",
+            BinaryOp::BitAnd => " & ",
+            BinaryOp::BitOr => " | ",
+            // `^` means power outside integer mode, so print the keyword
+            BinaryOp::BitXor => " xor ",
+            BinaryOp::ShiftLeft => " << ",
+            BinaryOp::ShiftRight => " >> ",
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Add | BinaryOp::Subtract => ADDITIVE,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => MULTIPLICATIVE,
+            BinaryOp::Power => POWER,
+            BinaryOp::BitAnd => BIT_AND,
+            BinaryOp::BitOr => BIT_OR,
+            BinaryOp::BitXor => BIT_XOR,
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => SHIFT,
+        }
+    }
+
+    fn apply(&self, left: f64, right: f64) -> Result<f64, String> {
+        match self {
+            BinaryOp::Add => Ok(left + right),
+            BinaryOp::Subtract => Ok(left - right),
+            BinaryOp::Multiply => Ok(left * right),
+            BinaryOp::Divide if right == 0.0 => Err("Division by zero".to_string()),
+            BinaryOp::Divide => Ok(left / right),
+            BinaryOp::Modulo if right == 0.0 => Err("Modulo by zero".to_string()),
+            BinaryOp::Modulo => Ok(left % right),
+            BinaryOp::Power => Ok(left.powf(right)),
+            _ => Ok(apply_bitwise(self, whole_number(left)?, whole_number(right)?, 64)? as f64),
+        }
+    }
+
+    fn apply_exact(&self, left: &Rational, right: &Rational, env: &Environment) -> Result<Rational, String> {
+        match self {
+            BinaryOp::Add => Ok(left + right),
+            BinaryOp::Subtract => Ok(left - right),
+            BinaryOp::Multiply => Ok(left * right),
+            BinaryOp::Divide => left.checked_div(right),
+            BinaryOp::Modulo if right.is_zero() => Err("Modulo by zero".to_string()),
+            // Takes the sign of the dividend, like f64's %
+            BinaryOp::Modulo => {
+                let quotient = Rational::from_integer(left.checked_div(right)?.round_with(RoundingMode::Down));
+                Ok(left - &(right * &quotient))
+            }
+            BinaryOp::Power => exact_power(left, right, env),
+            _ => {
+                let whole = |x: &Rational| {
+                    x.num
+                        .to_i64()
+                        .filter(|_| x.is_integer())
+                        .ok_or_else(|| format!("Bitwise operators need whole numbers within 64 bits, got {}", x.display_fraction()))
+                };
+                let result = apply_bitwise(self, whole(left)? as i128, whole(right)? as i128, 64)?;
+                Ok(Rational::from_integer(BigInt::from_i128(result)))
+            }
+        }
+    }
+
+    fn apply_complex(&self, left: Complex, right: Complex) -> Result<Complex, String> {
+        match self {
+            BinaryOp::Add => Ok(left.add(right)),
+            BinaryOp::Subtract => Ok(left.sub(right)),
+            BinaryOp::Multiply => Ok(left.mul(right)),
+            BinaryOp::Divide => left.div(right),
+            // A negative base with a fractional exponent has no real power
+            BinaryOp::Power if left.is_real() && right.is_real() && (left.re >= 0.0 || right.re.fract() == 0.0) => {
+                self.apply(left.re, right.re).map(Complex::real)
+            }
+            BinaryOp::Power => left.powc(right),
+            _ => match (left.into_real(), right.into_real()) {
+                (Ok(left), Ok(right)) => self.apply(left, right).map(Complex::real),
+                _ => Err(format!("'{}' needs real numbers", self.symbol().trim())),
+            },
+        }
+    }
+
+    fn apply_integer(&self, left: i128, right: i128, ty: IntegerType, wrapping: bool) -> Result<i128, String> {
+        let overflow = || format!("Overflow: result doesn't fit in {}", ty.name());
+        let result = match self {
+            BinaryOp::Add => left + right,
+            BinaryOp::Subtract => left - right,
+            BinaryOp::Multiply if wrapping => left.wrapping_mul(right),
+            BinaryOp::Multiply => left.checked_mul(right).ok_or_else(overflow)?,
+            BinaryOp::Divide if right == 0 => return Err("Division by zero".to_string()),
+            BinaryOp::Divide => left / right,
+            BinaryOp::Modulo if right == 0 => return Err("Modulo by zero".to_string()),
+            BinaryOp::Modulo => left % right,
+            BinaryOp::Power => {
+                let exponent = u32::try_from(right).map_err(|_| format!("Exponent must be between 0 and {} in integer mode", u32::MAX))?;
+                if wrapping {
+                    left.wrapping_pow(exponent)
+                } else {
+                    left.checked_pow(exponent).ok_or_else(overflow)?
+                }
+            }
+            _ => apply_bitwise(self, left, right, ty.bits)?,
+        };
+        ty.fit(result, wrapping)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Integer(i128),
+    Variable(String),
+    Negate(Box<Expr>),
+    BitNot(Box<Expr>),
+    Factorial(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+        Expr::Binary(op, Box::new(left), Box::new(right))
+    }
+
+    fn eval(&self, env: &Environment) -> Result<Quantity, String> {
+        match self {
+            Expr::Number(n) => Ok(Quantity::number(*n)),
+            Expr::Integer(n) => Ok(Quantity::number(*n as f64)),
+            Expr::Variable(name) => env.get(name),
+            Expr::Negate(operand) => {
+                let quantity = operand.eval(env)?;
+                Ok(Quantity { value: -quantity.value, ..quantity })
+            }
+            Expr::BitNot(operand) => Ok(Quantity::number(!whole_number(operand.eval(env)?.into_number()?)? as f64)),
+            Expr::Factorial(operand) => factorial(operand.eval(env)?.into_number()?).map(Quantity::number),
+            Expr::Binary(op, left, right) => Quantity::combine(op, left.eval(env)?, right.eval(env)?),
+            Expr::Call(name, _) if name == "diff" => self.expand_derivatives()?.eval(env),
+            Expr::Call(name, _) if name == "solve" => Err(SOLVE_ALONE.to_string()),
+            Expr::Call(name, args) => {
+                let values = args.iter().map(|arg| arg.eval(env)).collect::<Result<Vec<Quantity>, String>>()?;
+                call_function_quantity(name, values)
+            }
+        }
+    }
+
+    // Evaluation for the rational and decimal modes; in decimal mode every
+    // intermediate result is rounded to the configured precision
+    fn eval_exact(&self, env: &Environment) -> Result<Rational, String> {
+        let value = match self {
+            Expr::Number(n) => Rational::from_f64(*n)?,
+            Expr::Integer(n) => Rational::from_integer(BigInt::from_i128(*n)),
+            Expr::Variable(name) => return env.get_exact(name),
+            Expr::Negate(operand) => -&operand.eval_exact(env)?,
+            // ~x is -x - 1 in two's complement, at any width
+            Expr::BitNot(operand) => match operand.eval_exact(env)? {
+                x if x.is_integer() => Rational::from_integer(&(-&x.num) - &BigInt::from_u64(1)),
+                x => return Err(format!("Bitwise operators need whole numbers, got {}", x.display_fraction())),
+            },
+            Expr::Factorial(operand) => exact_factorial(&operand.eval_exact(env)?)?,
+            Expr::Binary(op, left, right) => op.apply_exact(&left.eval_exact(env)?, &right.eval_exact(env)?, env)?,
+            Expr::Call(name, _) if name == "diff" => return self.expand_derivatives()?.eval_exact(env),
+            Expr::Call(name, _) if name == "solve" => return Err(SOLVE_ALONE.to_string()),
+            Expr::Call(name, args) => {
+                let values = args.iter().map(|arg| arg.eval_exact(env)).collect::<Result<Vec<Rational>, String>>()?;
+                call_function_exact(name, &values, env)?
+            }
+        };
+        Ok(env.round_result(value))
+    }
+
+    // Evaluation for integer mode, wrapping or failing on overflow of the
+    // configured width after every operation
+    fn eval_integer(&self, env: &Environment) -> Result<i128, String> {
+        let (ty, wrapping) = (env.integer_type, env.wrapping);
+        match self {
+            Expr::Number(n) if n.fract() != 0.0 => Err(format!("Integer mode only accepts whole numbers, got {}", n)),
+            Expr::Number(n) => ty.fit(*n as i128, wrapping),
+            Expr::Integer(n) => ty.fit(*n, wrapping),
+            Expr::Variable(name) => env.get_integer(name),
+            Expr::Negate(operand) => ty.fit(-operand.eval_integer(env)?, wrapping),
+            Expr::BitNot(operand) => Ok(ty.wrap(!operand.eval_integer(env)?)),
+            Expr::Factorial(operand) => integer_factorial(operand.eval_integer(env)?, ty, wrapping),
+            Expr::Binary(op, left, right) => op.apply_integer(left.eval_integer(env)?, right.eval_integer(env)?, ty, wrapping),
+            Expr::Call(name, _) if name == "diff" => self.expand_derivatives()?.eval_integer(env),
+            Expr::Call(name, _) if name == "solve" => Err(SOLVE_ALONE.to_string()),
+            Expr::Call(name, args) => {
+                let values = args.iter().map(|arg| arg.eval_integer(env)).collect::<Result<Vec<i128>, String>>()?;
+                match (name.as_str(), values.as_slice()) {
+                    ("abs", [x]) => ty.fit(x.abs(), wrapping),
+                    ("abs", _) => Err(format!("abs() takes 1 argument, got {}", values.len())),
+                    ("min", _) => values.into_iter().min().ok_or_else(|| "min() needs at least 1 argument".to_string()),
+                    ("max", _) => values.into_iter().max().ok_or_else(|| "max() needs at least 1 argument".to_string()),
+                    _ => Err(format!("{}() is not available in integer mode", name)),
+                }
+            }
+        }
+    }
+
+    // Evaluation for complex mode, where `i` is the imaginary unit
+    fn eval_complex(&self, env: &Environment) -> Result<Complex, String> {
+        match self {
+            Expr::Number(n) => Ok(Complex::real(*n)),
+            Expr::Integer(n) => Ok(Complex::real(*n as f64)),
+            Expr::Variable(name) => env.get_complex(name),
+            Expr::Negate(operand) => Ok(Complex::real(0.0).sub(operand.eval_complex(env)?)),
+            Expr::BitNot(operand) => Ok(Complex::real(!whole_number(operand.eval_complex(env)?.into_real()?)? as f64)),
+            Expr::Factorial(operand) => factorial(operand.eval_complex(env)?.into_real()?).map(Complex::real),
+            Expr::Binary(op, left, right) => op.apply_complex(left.eval_complex(env)?, right.eval_complex(env)?),
+            Expr::Call(name, _) if name == "diff" => self.expand_derivatives()?.eval_complex(env),
+            Expr::Call(name, _) if name == "solve" => Err(SOLVE_ALONE.to_string()),
+            Expr::Call(name, args) => {
+                let values = args.iter().map(|arg| arg.eval_complex(env)).collect::<Result<Vec<Complex>, String>>()?;
+                call_function_complex(name, &values)
+            }
+        }
+    }
+
+    fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Number(_) | Expr::Integer(_) | Expr::Variable(_) => Vec::new(),
+            Expr::Negate(operand) | Expr::BitNot(operand) | Expr::Factorial(operand) => vec![operand],
+            Expr::Binary(_, left, right) => vec![left, right],
+            Expr::Call(_, args) => args.iter().collect(),
+        }
+    }
+
+    fn map_children(&self, f: impl Fn(&Expr) -> Expr) -> Expr {
+        match self {
+            Expr::Number(_) | Expr::Integer(_) | Expr::Variable(_) => self.clone(),
+            Expr::Negate(operand) => Expr::Negate(Box::new(f(operand))),
+            Expr::BitNot(operand) => Expr::BitNot(Box::new(f(operand))),
+            Expr::Factorial(operand) => Expr::Factorial(Box::new(f(operand))),
+            Expr::Binary(op, left, right) => Expr::binary(*op, f(left), f(right)),
+            Expr::Call(name, args) => Expr::Call(name.clone(), args.iter().map(f).collect()),
+        }
+    }
+
+    // Evaluates a node whose operands are all numbers. Nodes that would fail
+    // (like 1/0) are kept so the error surfaces on evaluation.
+    fn fold_node(self) -> Expr {
+        if matches!(self, Expr::Number(_) | Expr::Integer(_)) || !self.children().iter().all(|child| matches!(child, Expr::Number(_))) {
+            return self;
+        }
+        match self.eval(&Environment::new()).and_then(Quantity::into_number) {
+            Ok(value) if value.is_finite() => Expr::Number(value),
+            _ => self,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            // A negative literal prints with its sign, like a negation
+            Expr::Number(n) if *n < 0.0 => UNARY,
+            Expr::Number(_) | Expr::Integer(_) | Expr::Variable(_) | Expr::Call(_, _) => ATOM,
+            Expr::Negate(_) | Expr::BitNot(_) => UNARY,
+            Expr::Factorial(_) => POSTFIX,
+            Expr::Binary(op, _, _) => op.precedence(),
+        }
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter, operand: &Expr, parenthesize: bool) -> fmt::Result {
+    if parenthesize {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
+}
+
+// Prints only the parentheses needed to parse back to the same tree
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Integer(n) => write!(f, "{}", n),
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::Negate(operand) => {
+                write!(f, "-")?;
+                write_operand(f, operand, operand.precedence() <= UNARY)
+            }
+            Expr::BitNot(operand) => {
+                write!(f, "~")?;
+                write_operand(f, operand, operand.precedence() < UNARY)
+            }
+            Expr::Factorial(operand) => {
+                write_operand(f, operand, operand.precedence() < POSTFIX)?;
+                write!(f, "!")
+            }
+            // Right-associative, and the exponent may carry its own sign
+            Expr::Binary(BinaryOp::Power, base, exponent) => {
+                write_operand(f, base, base.precedence() <= POWER)?;
+                write!(f, "^")?;
+                write_operand(f, exponent, exponent.precedence() < UNARY)
+            }
+            Expr::Binary(op, left, right) => {
+                write_operand(f, left, left.precedence() < op.precedence())?;
+                write!(f, "{}", op.symbol())?;
+                write_operand(f, right, right.precedence() <= op.precedence())
+            }
+            Expr::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Symbolic differentiation and simplification
+// ---------------------------------------------------------------------------
+
+impl Expr {
+    fn plus(self, rhs: Expr) -> Expr {
+        Expr::binary(BinaryOp::Add, self, rhs)
+    }
+
+    fn minus(self, rhs: Expr) -> Expr {
+        Expr::binary(BinaryOp::Subtract, self, rhs)
+    }
+
+    fn times(self, rhs: Expr) -> Expr {
+        Expr::binary(BinaryOp::Multiply, self, rhs)
+    }
+
+    fn over(self, rhs: Expr) -> Expr {
+        Expr::binary(BinaryOp::Divide, self, rhs)
+    }
+
+    fn pow(self, rhs: Expr) -> Expr {
+        Expr::binary(BinaryOp::Power, self, rhs)
+    }
+
+    fn call(name: &str, arg: Expr) -> Expr {
+        Expr::Call(name.to_string(), vec![arg])
+    }
+
+    fn is_number(&self, value: f64) -> bool {
+        matches!(self, Expr::Number(n) if *n == value)
+    }
+
+    fn depends_on(&self, var: &str) -> bool {
+        match self {
+            Expr::Variable(name) => name == var,
+            _ => self.children().iter().any(|child| child.depends_on(var)),
+        }
+    }
+
+    fn substitute(&self, var: &str, value: &Expr) -> Expr {
+        match self {
+            Expr::Variable(name) if name == var => value.clone(),
+            _ => self.map_children(|child| child.substitute(var, value)),
+        }
+    }
+
+    // Variables the environment can't resolve, in order of appearance
+    fn unbound_variables(&self, env: &Environment) -> Vec<String> {
+        let mut unbound = Vec::new();
+        self.collect_unbound(env, &mut unbound);
+        unbound
+    }
+
+    fn collect_unbound(&self, env: &Environment, unbound: &mut Vec<String>) {
+        match self {
+            Expr::Variable(name) if env.get(name).is_err() && !(env.mode == NumericMode::Complex && name == "i") => {
+                if !unbound.contains(name) {
+                    unbound.push(name.clone());
+                }
+            }
+            _ => self.children().iter().for_each(|child| child.collect_unbound(env, unbound)),
+        }
+    }
+
+    fn contains_derivative(&self) -> bool {
+        match self {
+            Expr::Call(name, _) if name == "diff" => true,
+            _ => self.children().iter().any(|child| child.contains_derivative()),
+        }
+    }
+
+    // Replaces every diff(expr, var) with its simplified derivative, and
+    // diff(expr, var, at) with the derivative evaluated at that point
+    fn expand_derivatives(&self) -> Result<Expr, String> {
+        match self {
+            Expr::Number(_) | Expr::Integer(_) | Expr::Variable(_) => Ok(self.clone()),
+            Expr::Negate(operand) => Ok(Expr::Negate(Box::new(operand.expand_derivatives()?))),
+            Expr::BitNot(operand) => Ok(Expr::BitNot(Box::new(operand.expand_derivatives()?))),
+            Expr::Factorial(operand) => Ok(Expr::Factorial(Box::new(operand.expand_derivatives()?))),
+            Expr::Binary(op, left, right) => {
+                Ok(Expr::binary(*op, left.expand_derivatives()?, right.expand_derivatives()?))
+            }
+            Expr::Call(name, args) => {
+                let args = args.iter().map(Expr::expand_derivatives).collect::<Result<Vec<Expr>, String>>()?;
+                if name != "diff" {
+                    return Ok(Expr::Call(name.clone(), args));
+                }
+
+                let (function, var, at) = match args.as_slice() {
+                    [function, Expr::Variable(var)] => (function, var, None),
+                    [function, Expr::Variable(var), at] => (function, var, Some(at)),
+                    [_, _] | [_, _, _] => return Err("diff() expects a variable as its second argument, e.g. diff(x^2, x)".to_string()),
+                    _ => return Err(format!("diff() takes 2 or 3 arguments, got {}", args.len())),
+                };
+                let derivative = function.derivative(var)?.simplify();
+                Ok(match at {
+                    Some(at) => derivative.substitute(var, at),
+                    None => derivative,
+                })
+            }
+        }
+    }
+
+    fn derivative(&self, var: &str) -> Result<Expr, String> {
+        let d = |expr: &Expr| expr.derivative(var);
+
+        Ok(match self {
+            Expr::Number(_) | Expr::Integer(_) => Expr::Number(0.0),
+            Expr::Variable(name) => Expr::Number(if name == var { 1.0 } else { 0.0 }),
+            Expr::BitNot(_) => return Err("Cannot differentiate a bitwise operator".to_string()),
+            Expr::Negate(operand) => Expr::Negate(Box::new(d(operand)?)),
+            Expr::Factorial(_) => return Err("Cannot differentiate a factorial".to_string()),
+            Expr::Binary(op, u, v) => {
+                let (u, v) = (u.as_ref().clone(), v.as_ref().clone());
+                match op {
+                    BinaryOp::Add => d(&u)?.plus(d(&v)?),
+                    BinaryOp::Subtract => d(&u)?.minus(d(&v)?),
+                    BinaryOp::Multiply => d(&u)?.times(v.clone()).plus(u.clone().times(d(&v)?)),
+                    BinaryOp::Divide => {
+                        d(&u)?.times(v.clone()).minus(u.clone().times(d(&v)?)).over(v.pow(Expr::Number(2.0)))
+                    }
+                    // Power rule, exponential rule, or the general form
+                    BinaryOp::Power if !v.depends_on(var) => {
+                        v.clone().times(u.clone().pow(v.minus(Expr::Number(1.0)))).times(d(&u)?)
+                    }
+                    BinaryOp::Power if !u.depends_on(var) => {
+                        self.clone().times(Expr::call("ln", u)).times(d(&v)?)
+                    }
+                    BinaryOp::Power => self.clone().times(
+                        d(&v)?.times(Expr::call("ln", u.clone())).plus(v.times(d(&u)?).over(u)),
+                    ),
+                    BinaryOp::Modulo => return Err("Cannot differentiate the modulo operator".to_string()),
+                    _ => return Err("Cannot differentiate a bitwise operator".to_string()),
+                }
+            }
+            Expr::Call(name, _) if name == "diff" => return self.expand_derivatives()?.derivative(var),
+            Expr::Call(name, args) => {
+                let u = match args.as_slice() {
+                    [u] => u.clone(),
+                    _ => return Err(format!("Cannot differentiate {}() with {} arguments", name, args.len())),
+                };
+                let one = || Expr::Number(1.0);
+                let square = |e: Expr| e.pow(Expr::Number(2.0));
+                let outer = match name.as_str() {
+                    "sqrt" => one().over(Expr::Number(2.0).times(Expr::call("sqrt", u.clone()))),
+                    "abs" => u.clone().over(Expr::call("abs", u.clone())),
+                    "ln" => one().over(u.clone()),
+                    "log10" => one().over(u.clone().times(Expr::call("ln", Expr::Number(10.0)))),
+                    "exp" => Expr::call("exp", u.clone()),
+                    "sin" => Expr::call("cos", u.clone()),
+                    "cos" => Expr::Negate(Box::new(Expr::call("sin", u.clone()))),
+                    "tan" => one().over(square(Expr::call("cos", u.clone()))),
+                    "asin" => one().over(Expr::call("sqrt", one().minus(square(u.clone())))),
+                    "acos" => Expr::Negate(Box::new(one().over(Expr::call("sqrt", one().minus(square(u.clone())))))),
+                    "atan" => one().over(one().plus(square(u.clone()))),
+                    // Piecewise constant, so zero wherever it's defined
+                    "floor" | "ceil" | "round" => Expr::Number(0.0),
+                    _ => return Err(format!("Cannot differentiate {}()", name)),
+                };
+                outer.times(d(&u)?)
+            }
+        })
+    }
+
+    // Constant folding plus algebraic identities: x*1, x+0, x^1, combining
+    // constants and like terms in sums and repeated factors in products
+    fn simplify(&self) -> Expr {
+        let node = self.map_children(Expr::simplify).fold_node();
+
+        match node {
+            Expr::Binary(BinaryOp::Add | BinaryOp::Subtract, _, _) | Expr::Negate(_) => node.simplify_sum(),
+            // Left alone so the division by zero is reported on evaluation
+            Expr::Binary(BinaryOp::Divide, _, ref v) if v.is_number(0.0) => node,
+            Expr::Binary(BinaryOp::Multiply | BinaryOp::Divide, _, _) => node.simplify_product(),
+            Expr::Binary(BinaryOp::Power, u, v) => match (*u, *v) {
+                (_, v) if v.is_number(0.0) => Expr::Number(1.0),
+                (u, v) if v.is_number(1.0) => u,
+                (u, _) if u.is_number(1.0) => Expr::Number(1.0),
+                (u, v) => u.pow(v),
+            },
+            other => other,
+        }
+    }
+
+    fn simplify_sum(self) -> Expr {
+        let mut terms: Vec<(f64, Expr)> = Vec::new();
+        collect_terms(&self, 1.0, &mut terms);
+        terms.retain(|(coefficient, _)| *coefficient != 0.0);
+
+        // Constant term last, as in 3*x^2 + 2*x + 1
+        let (constants, mut terms): (Vec<_>, Vec<_>) = terms.into_iter().partition(|(_, term)| term.is_number(1.0));
+        terms.extend(constants);
+
+        let mut result: Option<Expr> = None;
+        for (coefficient, term) in terms {
+            // Merged coefficients may cancel against a quotient, as in 2*(1/2)*x
+            let scaled = |coefficient: f64, term: Expr| match scaled(coefficient, term) {
+                product @ Expr::Binary(BinaryOp::Multiply, _, _) => product.simplify_product(),
+                other => other,
+            };
+            result = Some(match result {
+                None => scaled(coefficient, term),
+                Some(sum) if coefficient < 0.0 => sum.minus(scaled(-coefficient, term)),
+                Some(sum) => sum.plus(scaled(coefficient, term)),
+            });
+        }
+        result.unwrap_or(Expr::Number(0.0))
+    }
+
+    // Products and quotients become coefficient * factors / factors, with
+    // repeated bases merged and cancelled through their exponents
+    fn simplify_product(self) -> Expr {
+        let (mut numerator, mut denominator) = (1.0, 1.0);
+        let mut factors: Vec<(Expr, Expr)> = Vec::new();
+        collect_factors(&self, false, &mut numerator, &mut denominator, &mut factors);
+
+        if numerator == 0.0 {
+            return Expr::Number(0.0);
+        }
+        // Reduce 6*x/4 to 3*x/2 rather than printing 1.5*x
+        if numerator.fract() == 0.0 && denominator.fract() == 0.0 {
+            let divisor = gcd(numerator.abs(), denominator.abs());
+            numerator /= divisor;
+            denominator /= divisor;
+        } else {
+            numerator /= denominator;
+            denominator = 1.0;
+        }
+
+        let (mut above, mut below) = (Vec::new(), Vec::new());
+        for (base, exponent) in factors {
+            if exponent.is_number(0.0) {
+                continue;
+            }
+            match exponent {
+                Expr::Number(n) if n < 0.0 => below.push(raised(base, Expr::Number(-n))),
+                exponent => above.push(raised(base, exponent)),
+            }
+        }
+
+        let numerator = match above.into_iter().reduce(Expr::times) {
+            Some(product) => scaled(numerator, product),
+            None => Expr::Number(numerator),
+        };
+        match below.into_iter().reduce(Expr::times) {
+            Some(product) => numerator.over(scaled(denominator, product)),
+            None if denominator != 1.0 => numerator.over(Expr::Number(denominator)),
+            None => numerator,
+        }
+    }
+}
+
+// Flattens a sum into (coefficient, term) pairs, merging like terms.
+// Constants are collected under the term `1`.
+fn collect_terms(expr: &Expr, sign: f64, terms: &mut Vec<(f64, Expr)>) {
+    match expr {
+        Expr::Binary(BinaryOp::Add, left, right) => {
+            collect_terms(left, sign, terms);
+            collect_terms(right, sign, terms);
+        }
+        Expr::Binary(BinaryOp::Subtract, left, right) => {
+            collect_terms(left, sign, terms);
+            collect_terms(right, -sign, terms);
+        }
+        Expr::Negate(operand) => collect_terms(operand, -sign, terms),
+        _ => {
+            let (coefficient, term) = split_coefficient(expr);
+            match terms.iter_mut().find(|(_, existing)| *existing == term) {
+                Some((existing, _)) => *existing += sign * coefficient,
+                None => terms.push((sign * coefficient, term)),
+            }
+        }
+    }
+}
+
+fn split_coefficient(expr: &Expr) -> (f64, Expr) {
+    if let Expr::Number(n) = expr {
+        return (*n, Expr::Number(1.0));
+    }
+    let mut factors = flatten_product(expr);
+    match factors.first() {
+        Some(Expr::Number(n)) if factors.len() > 1 => {
+            let coefficient = *n;
+            factors.remove(0);
+            (coefficient, factors.into_iter().reduce(Expr::times).unwrap())
+        }
+        _ => (1.0, expr.clone()),
+    }
+}
+
+fn flatten_product(expr: &Expr) -> Vec<Expr> {
+    match expr {
+        Expr::Binary(BinaryOp::Multiply, left, right) => {
+            let mut factors = flatten_product(left);
+            factors.extend(flatten_product(right));
+            factors
+        }
+        _ => vec![expr.clone()],
+    }
+}
+
+// Flattens a product or quotient into numeric coefficients and (base,
+// exponent) pairs, adding up the exponents of repeated bases. Factors of a
+// divisor count with negated exponents.
+fn collect_factors(
+    expr: &Expr,
+    inverted: bool,
+    numerator: &mut f64,
+    denominator: &mut f64,
+    factors: &mut Vec<(Expr, Expr)>,
+) {
+    let (base, exponent) = match expr {
+        Expr::Binary(BinaryOp::Multiply, left, right) => {
+            collect_factors(left, inverted, numerator, denominator, factors);
+            collect_factors(right, inverted, numerator, denominator, factors);
+            return;
+        }
+        Expr::Binary(BinaryOp::Divide, left, right) if !right.is_number(0.0) => {
+            collect_factors(left, inverted, numerator, denominator, factors);
+            collect_factors(right, !inverted, numerator, denominator, factors);
+            return;
+        }
+        Expr::Number(n) if *n != 0.0 || !inverted => {
+            if inverted {
+                *denominator *= n;
+            } else {
+                *numerator *= n;
+            }
+            return;
+        }
+        Expr::Negate(operand) => {
+            *numerator = -*numerator;
+            collect_factors(operand, inverted, numerator, denominator, factors);
+            return;
+        }
+        Expr::Binary(BinaryOp::Power, base, exponent) => (base.as_ref().clone(), exponent.as_ref().clone()),
+        _ => (expr.clone(), Expr::Number(1.0)),
+    };
+    let exponent = if inverted { Expr::Negate(Box::new(exponent)).simplify() } else { exponent };
+
+    match factors.iter_mut().find(|(existing, _)| *existing == base) {
+        Some((_, existing)) => *existing = existing.clone().plus(exponent).simplify(),
+        None => factors.push((base, exponent)),
+    }
+}
+
+fn raised(base: Expr, exponent: Expr) -> Expr {
+    if exponent.is_number(1.0) {
+        base
+    } else {
+        base.pow(exponent)
+    }
+}
+
+fn gcd(a: f64, b: f64) -> f64 {
+    if b == 0.0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// coefficient * term, keeping products flat so they print without parentheses
+fn scaled(coefficient: f64, term: Expr) -> Expr {
+    if term.is_number(1.0) {
+        return Expr::Number(coefficient);
+    }
+    if coefficient == 1.0 {
+        return term;
+    }
+    if coefficient == -1.0 {
+        return Expr::Negate(Box::new(term));
+    }
+    flatten_product(&term).into_iter().fold(Expr::Number(coefficient), Expr::times)
+}
+
+// ---------------------------------------------------------------------------
+// Equation solving
+// ---------------------------------------------------------------------------
+
+// Default search range for equations without a closed-form solution
+const SOLVE_RANGE: f64 = 1e6;
+// Samples per decade when scanning the default range, and across an
+// explicit interval
+const SOLVE_SAMPLES_PER_DECADE: usize = 200;
+const SOLVE_SAMPLES: usize = 4000;
+const SOLVE_ITERATIONS: usize = 200;
+const SOLVE_ALONE: &str = "solve() lists every solution, so it can't be part of a larger expression";
+
+// A solution to an equation: its value in the current mode, plus the
+// closed form when that's irrational, like (5 + sqrt(13))/2
+struct Root {
+    value: Value,
+    closed_form: Option<Expr>,
+}
+
+// solve(lhs = rhs, var) or solve(lhs = rhs, var, from, to). The parser
+// turns the equation into `lhs - rhs`, so this finds the zeros of the first
+// argument: exactly for linear and quadratic equations, numerically
+// otherwise.
+fn solve(args: &[Expr], env: &Environment) -> Result<(String, Vec<Root>), String> {
+    let (equation, var, interval) = match args {
+        [equation, Expr::Variable(var)] => (equation, var, None),
+        [equation, Expr::Variable(var), from, to] => {
+            let from = from.eval(env)?.into_number()?;
+            let to = to.eval(env)?.into_number()?;
+            if from.partial_cmp(&to) != Some(std::cmp::Ordering::Less) {
+                return Err(format!("solve(): the interval {} to {} is empty", from, to));
+            }
+            (equation, var, Some((from, to)))
+        }
+        [_, _] | [_, _, _, _] => {
+            return Err("solve() expects a variable as its second argument, e.g. solve(x^2 = 2, x)".to_string())
+        }
+        _ => return Err(format!("solve() takes 2 or 4 arguments, got {}", args.len())),
+    };
+    if env.mode == NumericMode::Integer {
+        return Err("solve() needs 'mode float', 'mode rational' or 'mode decimal'".to_string());
+    }
+    let equation = equation.expand_derivatives()?;
+
+    let roots = match polynomial(&equation, var, env) {
+        Some(coefficients) => solve_polynomial(&coefficients, var, env)?,
+        None => find_roots(&equation, var, interval, env)?,
+    };
+    let roots = match interval {
+        Some((from, to)) => {
+            roots.into_iter().filter(|root| (from..=to).contains(&root.value.to_quantity().value)).collect()
+        }
+        None => roots,
+    };
+    Ok((var.clone(), roots))
+}
+
+// Coefficients of `expr` as a polynomial in `var` of degree at most 2,
+// constant term first, or None if it isn't one
+fn polynomial(expr: &Expr, var: &str, env: &Environment) -> Option<Vec<Rational>> {
+    let mut coefficients = match expr {
+        _ if !expr.depends_on(var) => vec![constant_value(expr, env).ok()?],
+        Expr::Variable(_) => vec![Rational::from_integer(BigInt::zero()), Rational::from_integer(BigInt::from_u64(1))],
+        Expr::Negate(operand) => polynomial(operand, var, env)?.iter().map(|c| -c).collect(),
+        Expr::Binary(op @ (BinaryOp::Add | BinaryOp::Subtract), left, right) => {
+            let (left, right) = (polynomial(left, var, env)?, polynomial(right, var, env)?);
+            let zero = Rational::from_integer(BigInt::zero());
+            (0..left.len().max(right.len()))
+                .map(|i| {
+                    let (a, b) = (left.get(i).unwrap_or(&zero), right.get(i).unwrap_or(&zero));
+                    if *op == BinaryOp::Add { a + b } else { a - b }
+                })
+                .collect()
+        }
+        Expr::Binary(BinaryOp::Multiply, left, right) => {
+            multiply_polynomials(&polynomial(left, var, env)?, &polynomial(right, var, env)?)?
+        }
+        Expr::Binary(BinaryOp::Divide, left, right) if !right.depends_on(var) => {
+            let divisor = constant_value(right, env).ok()?;
+            polynomial(left, var, env)?.iter().map(|c| c.checked_div(&divisor)).collect::<Result<_, _>>().ok()?
+        }
+        Expr::Binary(BinaryOp::Power, base, exponent) if !exponent.depends_on(var) => {
+            let exponent = constant_value(exponent, env).ok()?.num.to_i64().filter(|n| (0..=2).contains(n))?;
+            let base = polynomial(base, var, env)?;
+            let mut result = vec![Rational::from_integer(BigInt::from_u64(1))];
+            for _ in 0..exponent {
+                result = multiply_polynomials(&result, &base)?;
+            }
+            result
+        }
+        _ => return None,
+    };
+    while coefficients.len() > 1 && coefficients.last().is_some_and(Rational::is_zero) {
+        coefficients.pop();
+    }
+    Some(coefficients)
+}
+
+fn multiply_polynomials(left: &[Rational], right: &[Rational]) -> Option<Vec<Rational>> {
+    let zero = Rational::from_integer(BigInt::zero());
+    let mut product = vec![zero; left.len() + right.len() - 1];
+    for (i, a) in left.iter().enumerate() {
+        for (j, b) in right.iter().enumerate() {
+            product[i + j] = &product[i + j] + &(a * b);
+        }
+    }
+    while product.len() > 1 && product.last().is_some_and(Rational::is_zero) {
+        product.pop();
+    }
+    (product.len() <= 3).then_some(product)
+}
+
+// A subexpression without the unknown, evaluated in the current mode
+fn constant_value(expr: &Expr, env: &Environment) -> Result<Rational, String> {
+    match env.mode {
+        NumericMode::Float => Rational::from_f64(expr.eval(env)?.into_number()?),
+        NumericMode::Complex => Rational::from_f64(expr.eval_complex(env)?.into_real()?),
+        _ => expr.eval_exact(env),
+    }
+}
+
+fn solve_polynomial(coefficients: &[Rational], var: &str, env: &Environment) -> Result<Vec<Root>, String> {
+    let rational_root = |value: Rational| Root {
+        value: match env.mode {
+            NumericMode::Float | NumericMode::Complex => Value::Float(value.to_f64()),
+            _ => Value::Exact(env.round_result(value)),
+        },
+        closed_form: None,
+    };
+
+    match coefficients {
+        [c] if c.is_zero() => Err(format!("Every value of {} is a solution", var)),
+        [_] => Ok(Vec::new()),
+        [c, b] => Ok(vec![rational_root((-c).checked_div(b)?)]),
+        [c, b, a] => {
+            // x = p +- sqrt(r) with p = -b/2a and r = (b^2 - 4ac)/4a^2
+            let two_a = &Rational::from_integer(BigInt::from_u64(2)) * a;
+            let p = (-b).checked_div(&two_a)?;
+            let r = &(&p * &p) - &c.checked_div(a)?;
+            if r.is_zero() {
+                return Ok(vec![rational_root(p)]);
+            }
+            // A negative r gives a conjugate pair p +- sqrt(-r)*i
+            let imaginary = r.num.negative;
+            if imaginary && env.mode != NumericMode::Complex {
+                return Ok(Vec::new());
+            }
+            let r = r.abs();
+            let complex_root = |q: f64| Root { value: Value::Complex(Complex::new(p.to_f64(), q)), closed_form: None };
+
+            let (num_root, den_root) = (r.num.isqrt(), r.den.isqrt());
+            if &num_root * &num_root == r.num && &den_root * &den_root == r.den {
+                let q = Rational::new(num_root, den_root);
+                return Ok(if imaginary {
+                    vec![complex_root(-q.to_f64()), complex_root(q.to_f64())]
+                } else {
+                    vec![rational_root(&p - &q), rational_root(&p + &q)]
+                });
+            }
+
+            let mut roots = Vec::new();
+            for sign in [-1.0, 1.0] {
+                let closed_form = surd(&p, &r, sign, imaginary);
+                let value = match (&closed_form, env.mode) {
+                    (Some(form), NumericMode::Decimal) => Value::Exact(form.eval_exact(env)?),
+                    (Some(form), NumericMode::Complex) => Value::from(form.eval_complex(env)?),
+                    (Some(form), _) => Value::from(form.eval(env)?),
+                    (None, _) if imaginary => complex_root(sign * r.to_f64().sqrt()).value,
+                    (None, _) => Value::Float(p.to_f64() + sign * r.to_f64().sqrt()),
+                };
+                roots.push(Root { value, closed_form });
+            }
+            Ok(roots)
+        }
+        _ => unreachable!("polynomial() stops at degree 2"),
+    }
+}
+
+// p + sign*sqrt(r) as an expression, times i if imaginary, with square
+// factors taken out of the root, or None if the numbers are too large to
+// print exactly
+fn surd(p: &Rational, r: &Rational, sign: f64, imaginary: bool) -> Option<Expr> {
+    // sqrt(n/d) = sqrt(n*d)/d = k*sqrt(s)/d
+    let radicand = u64::try_from((&r.num * &r.den).to_i64()?).ok()?;
+    let (k, s) = split_square(radicand);
+    let den = u64::try_from(r.den.to_i64()?).ok().filter(|d| *d <= MAX_EXACT_FLOAT_INTEGER as u64)?;
+    let divisor = gcd(k as f64, den as f64);
+    let (k, den) = (k as f64 / divisor, den as f64 / divisor);
+
+    // With no rational part the sign goes on the coefficient, as in -2*sqrt(3)
+    let k = if p.is_zero() { sign * k } else { k };
+    let sqrt = Expr::call("sqrt", Expr::Number(s as f64));
+    let mut root = match k {
+        1.0 => sqrt,
+        -1.0 => Expr::Negate(Box::new(sqrt)),
+        k => Expr::Number(k).times(sqrt),
+    };
+    if den != 1.0 {
+        root = root.over(Expr::Number(den));
+    }
+    if imaginary {
+        root = root.times(Expr::Variable("i".to_string()));
+    }
+
+    Some(match (p.is_zero(), sign < 0.0) {
+        (true, _) => root,
+        (false, true) => rational_expr(p)?.minus(root),
+        (false, false) => rational_expr(p)?.plus(root),
+    })
+}
+
+// n = k^2 * s, taking out square factors of primes below 100000
+fn split_square(mut n: u64) -> (u64, u64) {
+    let mut k = 1;
+    let mut factor = 2;
+    while factor < 100_000 && factor * factor <= n {
+        while n.is_multiple_of(factor * factor) {
+            n /= factor * factor;
+            k *= factor;
+        }
+        factor += 1;
+    }
+    (k, n)
+}
+
+fn rational_expr(value: &Rational) -> Option<Expr> {
+    let exact = |n: &BigInt| n.to_i64().filter(|n| n.unsigned_abs() <= MAX_EXACT_FLOAT_INTEGER as u64).map(|n| n as f64);
+    let num = Expr::Number(exact(&value.num)?);
+    Some(if value.is_integer() { num } else { num.over(Expr::Number(exact(&value.den)?)) })
+}
+
+// Scans for sign changes and near-misses that touch zero, then refines
+// each with Newton's method, falling back to bisection whenever a step
+// leaves the bracket
+fn find_roots(expr: &Expr, var: &str, interval: Option<(f64, f64)>, env: &Environment) -> Result<Vec<Root>, String> {
+    if let Some(name) = expr.unbound_variables(env).into_iter().find(|name| name != var) {
+        return Err(format!("Unknown variable: {}", name));
+    }
+    let f = |x: f64| -> Result<f64, String> {
+        let value = expr.substitute(var, &Expr::Number(x)).eval(env)?.base_value();
+        if value.is_finite() { Ok(value) } else { Err(format!("No value at {} = {}", var, x)) }
+    };
+    // Newton steps need the derivative; without one they fall back to bisection
+    let slope = expr.derivative(var).ok().map(|d| d.simplify());
+    let df = |x: f64| slope.as_ref().and_then(|d| d.substitute(var, &Expr::Number(x)).eval(env).ok()).map(|q| q.base_value());
+
+    let points: Vec<f64> = match interval {
+        Some((from, to)) => (0..=SOLVE_SAMPLES).map(|i| from + (to - from) * i as f64 / SOLVE_SAMPLES as f64).collect(),
+        // Logarithmic on each side of zero, so roots near 0.001 and 100000
+        // are both found
+        None => {
+            let decades = 2 * SOLVE_RANGE.log10() as usize;
+            let count = decades * SOLVE_SAMPLES_PER_DECADE;
+            let positive: Vec<f64> = (0..=count)
+                .map(|i| SOLVE_RANGE * 10f64.powf(-(decades as f64) * (1.0 - i as f64 / count as f64)))
+                .collect();
+            positive.iter().rev().map(|x| -x).chain(std::iter::once(0.0)).chain(positive.iter().copied()).collect()
+        }
+    };
+    let mut first_error = None;
+    let samples: Vec<(f64, Option<f64>)> = points
+        .iter()
+        .map(|&x| match f(x) {
+            Ok(y) => (x, Some(y)),
+            Err(e) => {
+                first_error.get_or_insert(e);
+                (x, None)
+            }
+        })
+        .collect();
+    if samples.iter().all(|(_, y)| y.is_none()) {
+        return Err(first_error.unwrap_or_else(|| "solve(): nothing to search".to_string()));
+    }
+
+    let mut found: Vec<f64> = Vec::new();
+    for (i, window) in samples.windows(2).enumerate() {
+        let ((a, fa), (b, fb)) = (window[0], window[1]);
+        let (Some(fa), Some(fb)) = (fa, fb) else { continue };
+        if fa == 0.0 {
+            found.push(a);
+        } else if fa.signum() != fb.signum() && fb != 0.0 {
+            if let Some(root) = refine(&f, &df, a, b, fa) {
+                // A pole like 1/x also changes sign; a root makes f small
+                if f(root).is_ok_and(|y| y.abs() <= 1e-6 * (1.0 + fa.abs().min(fb.abs()))) {
+                    found.push(root);
+                }
+            }
+        } else if let Some((_, Some(fc))) = samples.get(i + 2) {
+            // |f| dips towards zero without crossing it, as at a double root
+            if fb.abs() < fa.abs() && fb.abs() <= fc.abs() && fb.signum() == fc.signum() {
+                if let Some(root) = touch_down(&f, &df, b, a, samples[i + 2].0) {
+                    found.push(root);
+                }
+            }
+        }
+    }
+    if let Some((x, Some(y))) = samples.last() {
+        if *y == 0.0 {
+            found.push(*x);
+        }
+    }
+
+    found.sort_by(|a, b| a.total_cmp(b));
+    found.dedup_by(|a, b| (*a - *b).abs() <= 1e-9 * b.abs().max(1.0));
+    Ok(found
+        .into_iter()
+        // Print -0 as 0
+        .map(|x| if x == 0.0 { 0.0 } else { x })
+        .map(|x| Root { value: Value::Float(x), closed_form: None })
+        .collect())
+}
+
+// Safeguarded Newton's method on a bracket [a, b] where f changes sign
+fn refine(f: &impl Fn(f64) -> Result<f64, String>, df: &impl Fn(f64) -> Option<f64>, mut a: f64, mut b: f64, fa: f64) -> Option<f64> {
+    let mut x = (a + b) / 2.0;
+    for _ in 0..SOLVE_ITERATIONS {
+        let fx = f(x).ok()?;
+        if fx == 0.0 {
+            return Some(x);
+        }
+        if fx.signum() == fa.signum() {
+            a = x;
+        } else {
+            b = x;
+        }
+        let newton = df(x).filter(|d| *d != 0.0).map(|d| x - fx / d);
+        let next = match newton {
+            Some(next) if next > a.min(b) && next < a.max(b) => next,
+            _ => (a + b) / 2.0,
+        };
+        if (next - x).abs() <= f64::EPSILON * x.abs() || next == a || next == b {
+            return Some(next);
+        }
+        x = next;
+    }
+    Some(x)
+}
+
+// Newton's method from a local minimum of |f|, kept between its
+// neighbouring samples; succeeds only if f actually reaches zero
+fn touch_down(f: &impl Fn(f64) -> Result<f64, String>, df: &impl Fn(f64) -> Option<f64>, start: f64, low: f64, high: f64) -> Option<f64> {
+    let scale = f(low).ok()?.abs().max(f(high).ok()?.abs());
+    let mut x = start;
+    for _ in 0..SOLVE_ITERATIONS {
+        let fx = f(x).ok()?;
+        if fx == 0.0 {
+            return Some(x);
+        }
+        let next = x - fx / df(x).filter(|d| *d != 0.0)?;
+        if !(low..=high).contains(&next) {
+            return None;
+        }
+        // Converges slowly at a double root, so run until the steps vanish
+        if (next - x).abs() <= f64::EPSILON * x.abs() {
+            break;
+        }
+        x = next;
+    }
+    (f(x).ok()?.abs() <= 1e-12 * scale.max(1.0)).then_some(x)
+}
+
+// `name = expression` or a bare expression, optionally followed by
+// `in <units>`
+struct Statement {
+    target: Option<String>,
+    expr: Expr,
+    conversion: Option<Vec<UnitPower>>,
+}
+
+enum Evaluation {
+    Computed(Value),
+    Assigned(String, Value),
+    // Still depends on unbound variables, like the result of diff(x^2, x)
+    Symbolic(Expr),
+    // The solutions of solve(), smallest first
+    Solved(String, Vec<Root>),
+}
+
+const DEFAULT_PRECISION: usize = 28;
+const MAX_PRECISION: usize = 1000;
+
+// Variables that live for the whole session, plus `ans`, the last result,
+// and the numeric mode used to evaluate new expressions
+struct Environment {
+    variables: HashMap<String, Value>,
+    ans: Option<Value>,
+    mode: NumericMode,
+    // Significant digits kept in decimal mode
+    precision: usize,
+    rounding: RoundingMode,
+    // Print exact results as fractions rather than decimals
+    fractions: bool,
+    integer_type: IntegerType,
+    // Integer mode wraps on overflow instead of reporting an error
+    wrapping: bool,
+    // Radix for printing whole-number results
+    base: u32,
+    // Print complex results as r*exp(ti) rather than a + bi
+    polar: bool,
+}
+
+impl Environment {
+    fn new() -> Self {
+        Environment {
+            variables: HashMap::new(),
+            ans: None,
+            mode: NumericMode::Float,
+            precision: DEFAULT_PRECISION,
+            rounding: RoundingMode::HalfEven,
+            fractions: false,
+            integer_type: IntegerType { bits: 64, signed: true },
+            wrapping: false,
+            base: 10,
+            polar: false,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Result<&Value, String>> {
+        if name == "ans" {
+            return Some(self.ans.as_ref().ok_or_else(|| "No previous result for 'ans'".to_string()));
+        }
+        self.variables.get(name).map(Ok)
+    }
+
+    fn get(&self, name: &str) -> Result<Quantity, String> {
+        if let Some(value) = self.lookup(name) {
+            return match value? {
+                Value::Complex(z) => Err(format!("{} = {} is complex; use 'mode complex'", name, z)),
+                value => Ok(value.to_quantity()),
+            };
+        }
+        if name == "i" {
+            return Err("i is imaginary; use 'mode complex'".to_string());
+        }
+        CONSTANTS
+            .iter()
+            .find(|(constant, _)| *constant == name)
+            .map(|(_, value)| Quantity::number(*value))
+            .or_else(|| Quantity::unit(name))
+            .ok_or_else(|| format!("Unknown variable: {}", name))
+    }
+
+    // `i` is the imaginary unit unless a variable of that name shadows it
+    fn get_complex(&self, name: &str) -> Result<Complex, String> {
+        match self.lookup(name) {
+            Some(value) => value?.to_complex(),
+            None if name == "i" => Ok(Complex::I),
+            None if find_unit(name).is_some() => Err(format!("Units like {} need 'mode float'", name)),
+            None => self.get(name).map(|quantity| Complex::real(quantity.value)),
+        }
+    }
+
+    fn get_exact(&self, name: &str) -> Result<Rational, String> {
+        if let Some(value) = self.lookup(name) {
+            return Ok(self.round_result(value?.to_exact()?));
+        }
+        if find_unit(name).is_some() {
+            return Err(format!("Units like {} need 'mode float'", name));
+        }
+        if !CONSTANTS.iter().any(|(constant, _)| *constant == name) {
+            return Err(format!("Unknown variable: {}", name));
+        }
+        match self.mode {
+            NumericMode::Decimal => exact_constant(name, self.precision).ok_or_else(|| format!("Unknown variable: {}", name)),
+            _ => Err(format!("{} is irrational; use 'mode decimal' or 'mode float'", name)),
+        }
+    }
+
+    fn get_integer(&self, name: &str) -> Result<i128, String> {
+        let value = match self.lookup(name) {
+            Some(value) => value?,
+            None if find_unit(name).is_some() => return Err(format!("Units like {} need 'mode float'", name)),
+            None if CONSTANTS.iter().any(|(constant, _)| *constant == name) => {
+                return Err(format!("{} is not an integer", name))
+            }
+            None => return Err(format!("Unknown variable: {}", name)),
+        };
+        let whole = match value {
+            Value::Integer(value) => Some(*value),
+            Value::Float(value) if value.fract() == 0.0 => Some(*value as i128),
+            Value::Exact(value) if value.is_integer() => value.num.to_i128(),
+            _ => None,
+        };
+        match whole {
+            Some(whole) => self.integer_type.fit(whole, self.wrapping),
+            None => Err(format!("{} = {} is not an integer", name, self.format_value(value))),
+        }
+    }
+
+    fn round_result(&self, value: Rational) -> Rational {
+        match self.mode {
+            NumericMode::Decimal => value.round_significant(self.precision, self.rounding),
+            _ => value,
+        }
+    }
+
+    fn format_value(&self, value: &Value) -> String {
+        if self.base != 10 {
+            let whole = match value {
+                // Integer mode shows the bit pattern, so -1 in i8 is 0xFF
+                Value::Integer(value) => Some(value & self.integer_type.mask()),
+                Value::Float(value) if value.fract() == 0.0 && value.abs() <= MAX_EXACT_FLOAT_INTEGER as f64 => {
+                    Some(*value as i128)
+                }
+                Value::Exact(value) if value.is_integer() => value.num.to_i128(),
+                _ => None,
+            };
+            if let Some(whole) = whole {
+                return format_integer(whole, self.base);
+            }
+        }
+        match value {
+            Value::Integer(value) => value.to_string(),
+            Value::Float(value) => value.to_string(),
+            Value::Measured(quantity) => quantity.to_string(),
+            Value::Exact(value) if self.fractions => value.display_fraction(),
+            Value::Exact(value) => value.display_decimal(self.precision, self.rounding),
+            Value::Complex(z) if self.polar => z.to_polar_string(),
+            Value::Complex(z) => z.to_string(),
+        }
+    }
+
+    // Handles `mode`, `precision`, `rounding` and `format`, returning None
+    // for any other input
+    fn apply_setting(&mut self, input: &str) -> Option<Result<String, String>> {
+        let (command, argument) = input.split_once(' ').unwrap_or((input, ""));
+        let argument = argument.trim();
+        let result = match (command, argument) {
+            ("mode", "") => Ok(()),
+            ("mode", mode) => match mode {
+                "float" => Ok(NumericMode::Float),
+                "rational" => Ok(NumericMode::Rational),
+                "decimal" => Ok(NumericMode::Decimal),
+                "integer" | "int" => Ok(NumericMode::Integer),
+                "complex" => Ok(NumericMode::Complex),
+                _ => Err(format!("Unknown mode '{}'; expected float, rational, decimal, integer or complex", mode)),
+            }
+            .map(|mode| {
+                self.mode = mode;
+                self.fractions = mode == NumericMode::Rational;
+            }),
+            ("precision", "") => Ok(()),
+            ("precision", digits) => match digits.parse::<usize>() {
+                Ok(digits) if (1..=MAX_PRECISION).contains(&digits) => {
+                    self.precision = digits;
+                    Ok(())
+                }
+                _ => Err(format!("Precision must be a whole number from 1 to {}", MAX_PRECISION)),
+            },
+            ("rounding", "") => Ok(()),
+            ("rounding", mode) => match RoundingMode::parse(mode) {
+                Some(mode) => {
+                    self.rounding = mode;
+                    Ok(())
+                }
+                None => Err(format!(
+                    "Unknown rounding mode '{}'; expected half-even, half-up, half-down, down, up, floor or ceiling",
+                    mode
+                )),
+            },
+            ("format", "") => Ok(()),
+            ("format", "fraction") => {
+                self.fractions = true;
+                Ok(())
+            }
+            ("format", "decimal") => {
+                self.fractions = false;
+                Ok(())
+            }
+            ("format", "polar" | "rectangular") => {
+                self.polar = argument == "polar";
+                Ok(())
+            }
+            ("format", format) => {
+                Err(format!("Unknown format '{}'; expected fraction, decimal, rectangular or polar", format))
+            }
+            ("type", "") => Ok(()),
+            ("type", name) => match IntegerType::parse(name) {
+                Some(ty) => {
+                    self.integer_type = ty;
+                    Ok(())
+                }
+                None => Err(format!("Unknown integer type '{}'; expected i8, u8, i16, u16, i32, u32, i64 or u64", name)),
+            },
+            ("overflow", "") => Ok(()),
+            ("overflow", "wrap") => {
+                self.wrapping = true;
+                Ok(())
+            }
+            ("overflow", "error") => {
+                self.wrapping = false;
+                Ok(())
+            }
+            ("overflow", mode) => Err(format!("Unknown overflow mode '{}'; expected wrap or error", mode)),
+            ("base", "") => Ok(()),
+            ("base", base) => match base.parse::<u32>() {
+                Ok(base @ 2..=36) => {
+                    self.base = base;
+                    Ok(())
+                }
+                _ => Err("Base must be a whole number from 2 to 36".to_string()),
+            },
+            _ => return None,
+        };
+        Some(result.map(|()| self.settings()))
+    }
+
+    fn settings(&self) -> String {
+        let mode = match self.mode {
+            NumericMode::Float => "float",
+            NumericMode::Rational => "rational",
+            NumericMode::Decimal => "decimal",
+            NumericMode::Integer => {
+                return format!(
+                    "Mode: integer, type: {}, overflow: {}, base: {}",
+                    self.integer_type.name(),
+                    if self.wrapping { "wrap" } else { "error" },
+                    self.base
+                )
+            }
+            NumericMode::Complex => {
+                return format!("Mode: complex, format: {}", if self.polar { "polar" } else { "rectangular" })
+            }
+        };
+        format!(
+            "Mode: {}, precision: {} digits, rounding: {}, format: {}, base: {}",
+            mode,
+            self.precision,
+            self.rounding.name(),
+            if self.fractions { "fraction" } else { "decimal" },
+            self.base
+        )
+    }
+
+    fn set(&mut self, name: &str, value: Value) -> Result<(), String> {
+        if RESERVED.contains(&name)
+            || FUNCTIONS.contains(&name)
+            || CONSTANTS.iter().any(|(constant, _)| *constant == name)
+        {
+            return Err(format!("Cannot assign to '{}'", name));
+        }
+        if find_unit(name).is_some() {
+            return Err(format!("Cannot assign to '{}': it is a unit", name));
+        }
+        self.variables.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.variables.clear();
+        self.ans = None;
+    }
+
+    // Evaluates an expression or assignment and remembers the result as `ans`
+    fn execute(&mut self, input: &str) -> Result<Evaluation, CalcError> {
+        let mut calculator = match self.mode {
+            NumericMode::Integer => Calculator::new_integer(input)?,
+            _ => Calculator::new(input)?,
+        };
+        let statement = calculator.parse_statement()?;
+
+        if let Expr::Call(name, args) = &statement.expr {
+            if name == "solve" {
+                if statement.conversion.is_some() {
+                    return Err(CalcError::new("solve() results can't be converted to other units"));
+                }
+                let (var, roots) = solve(args, self)?;
+                // Only a unique solution becomes `ans` or can be assigned
+                let unique = match roots.as_slice() {
+                    [root] => Some(root.value.clone()),
+                    _ => None,
+                };
+                if let Some(name) = statement.target {
+                    let value = unique.ok_or_else(|| format!("Cannot assign to '{}': the equation has {} solutions", name, roots.len()))?;
+                    self.set(&name, value.clone())?;
+                    self.ans = Some(value.clone());
+                    return Ok(Evaluation::Assigned(name, value));
+                }
+                if unique.is_some() {
+                    self.ans = unique;
+                }
+                return Ok(Evaluation::Solved(var, roots));
+            }
+        }
+
+        if statement.expr.contains_derivative() {
+            let expanded = statement.expr.expand_derivatives()?.simplify();
+            let unbound = expanded.unbound_variables(self);
+            if !unbound.is_empty() {
+                return match statement.target {
+                    Some(name) => Err(format!("Cannot assign to '{}': result depends on {}", name, unbound.join(", ")).into()),
+                    None => Ok(Evaluation::Symbolic(expanded)),
+                };
+            }
+        }
+
+        let value = match (self.mode, &statement.conversion) {
+            (NumericMode::Float, None) => Value::from(statement.expr.eval(self)?),
+            (NumericMode::Float, Some(units)) => Value::from(statement.expr.eval(self)?.convert_to(units)?),
+            (NumericMode::Integer, None) => Value::Integer(statement.expr.eval_integer(self)?),
+            (NumericMode::Complex, None) => Value::from(statement.expr.eval_complex(self)?),
+            (_, None) => Value::Exact(statement.expr.eval_exact(self)?),
+            (_, Some(_)) => return Err(CalcError::new("Unit conversion needs 'mode float'")),
+        };
+        self.ans = Some(value.clone());
+        match statement.target {
+            Some(name) => {
+                self.set(&name, value.clone())?;
+                Ok(Evaluation::Assigned(name, value))
+            }
+            None => Ok(Evaluation::Computed(value)),
+        }
+    }
+
+    fn display(&self) {
+        if self.variables.is_empty() && self.ans.is_none() {
+            println!("No variables defined.");
+        }
+        let mut names: Vec<&String> = self.variables.keys().collect();
+        names.sort();
+        for name in names {
+            println!("  {} = {}", name, self.format_value(&self.variables[name]));
+        }
+        if let Some(ans) = &self.ans {
+            println!("  ans = {}", self.format_value(ans));
+        }
+        let constants: Vec<&str> = CONSTANTS.iter().map(|(name, _)| *name).collect();
+        println!("Constants: {}", constants.join(", "));
+    }
+}
+
+// Character columns covered by a token, for underlining errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+// An error with the part of the input it points at, when known, and a
+// suggested fix
+#[derive(Debug)]
+struct CalcError {
+    message: String,
+    span: Option<Span>,
+    help: Option<String>,
+}
+
+impl CalcError {
+    fn new(message: impl Into<String>) -> Self {
+        CalcError { message: message.into(), span: None, help: None }
+    }
+
+    fn at(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    // The message, then the input with the span underlined:
+    //
+    //   Error: Missing operator before '('
+    //     2(3+4)
+    //      ^
+    //     help: use '*' to multiply: 2*(3+4)
+    fn render(&self, input: &str) -> String {
+        let mut text = format!("Error: {}", self.message);
+        if let Some(span) = self.span {
+            let width = span.end.saturating_sub(span.start).max(1);
+            text.push_str(&format!("\n  {}\n  {}{}", input, " ".repeat(span.start), "^".repeat(width)));
+        }
+        if let Some(help) = &self.help {
+            text.push_str(&format!("\n  help: {}", help));
+        }
+        text
+    }
+}
+
+impl From<String> for CalcError {
+    fn from(message: String) -> Self {
+        CalcError::new(message)
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// Recursive descent parser producing an `Expr` tree
 struct Calculator {
+    source: String,
     tokens: Vec<Token>,
+    spans: Vec<Span>,
     position: usize,
 }
 
-impl Calculator {
-    fn new(input: &str) -> Result<Self, String> {
-        let tokens = Self::tokenize(input)?;
-        Ok(Calculator { tokens, position: 0 })
+impl Calculator {
+    fn new(input: &str) -> Result<Self, CalcError> {
+        Self::with_tokens(input, false)
+    }
+
+    // Integer mode reads `^` as exclusive or, as in C; `**` is still power
+    fn new_integer(input: &str) -> Result<Self, CalcError> {
+        Self::with_tokens(input, true)
+    }
+
+    fn with_tokens(input: &str, caret_is_xor: bool) -> Result<Self, CalcError> {
+        let (tokens, spans) = Self::tokenize(input, caret_is_xor)?;
+        Ok(Calculator { source: input.to_string(), tokens, spans, position: 0 })
+    }
+
+    fn tokenize(input: &str, caret_is_xor: bool) -> Result<(Vec<Token>, Vec<Span>), CalcError> {
+        let length = input.chars().count();
+        let offset = |chars: &std::iter::Peekable<std::str::Chars>| length - chars.clone().count();
+        let mut tokens = Vec::new();
+        let mut spans = Vec::new();
+        let mut chars = input.chars().peekable();
+
+        while let Some(&ch) = chars.peek() {
+            let start = offset(&chars);
+            let error_span = Span { start, end: start + 1 };
+            match ch {
+                ' ' | '\t' => {
+                    chars.next();
+                }
+                '+' => {
+                    tokens.push(Token::Plus);
+                    chars.next();
+                }
+                '-' => {
+                    tokens.push(Token::Minus);
+                    chars.next();
+                }
+                '*' => {
+                    chars.next();
+                    if chars.next_if_eq(&'*').is_some() {
+                        tokens.push(Token::Power);
+                    } else {
+                        tokens.push(Token::Multiply);
+                    }
+                }
+                '/' => {
+                    tokens.push(Token::Divide);
+                    chars.next();
+                }
+                '%' => {
+                    tokens.push(Token::Modulo);
+                    chars.next();
+                }
+                '^' => {
+                    tokens.push(if caret_is_xor { Token::BitXor } else { Token::Power });
+                    chars.next();
+                }
+                '&' => {
+                    tokens.push(Token::BitAnd);
+                    chars.next();
+                }
+                '|' => {
+                    tokens.push(Token::BitOr);
+                    chars.next();
+                }
+                '~' => {
+                    tokens.push(Token::BitNot);
+                    chars.next();
+                }
+                '<' | '>' => {
+                    chars.next();
+                    if chars.next_if_eq(&ch).is_none() {
+                        return Err(CalcError::new(format!("Unexpected character: {}", ch))
+                            .at(error_span)
+                            .with_help(format!("shifts are written {}{}; there are no comparison operators", ch, ch)));
+                    }
+                    tokens.push(if ch == '<' { Token::ShiftLeft } else { Token::ShiftRight });
+                }
+                '!' => {
+                    tokens.push(Token::Factorial);
+                    chars.next();
+                }
+                ',' => {
+                    tokens.push(Token::Comma);
+                    chars.next();
+                }
+                '=' => {
+                    tokens.push(Token::Assign);
+                    chars.next();
+                }
+                '(' => {
+                    tokens.push(Token::LeftParen);
+                    chars.next();
+                }
+                ')' => {
+                    tokens.push(Token::RightParen);
+                    chars.next();
+                }
+                '0'..='9' | '.' => {
+                    let mut lookahead = chars.clone();
+                    lookahead.next();
+                    let radix = match (ch, lookahead.peek()) {
+                        ('0', Some('x' | 'X')) => 16,
+                        ('0', Some('o' | 'O')) => 8,
+                        ('0', Some('b' | 'B')) => 2,
+                        _ => 10,
+                    };
+                    if radix != 10 {
+                        chars.next();
+                        chars.next();
+                    }
+
+                    // Hex digits include letters; `_` separates digit groups
+                    let mut number_str = String::new();
+                    while let Some(&ch) = chars.peek() {
+                        if ch.is_ascii_digit() || ch == '_' || (radix == 10 && ch == '.') || (radix == 16 && ch.is_ascii_hexdigit()) {
+                            number_str.push(ch);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+
+                    // An exponent as in 1e-3 or 2.5E2; a bare `e` is left for 2e = 2*e
+                    let mut lookahead = chars.clone();
+                    if let Some(e) = lookahead.next_if(|&ch| radix == 10 && (ch == 'e' || ch == 'E')) {
+                        let sign = lookahead.next_if(|&ch| ch == '+' || ch == '-');
+                        if lookahead.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+                            number_str.push(e);
+                            number_str.extend(sign);
+                            while let Some(digit) = lookahead.next_if(|ch| ch.is_ascii_digit()) {
+                                number_str.push(digit);
+                            }
+                            chars = lookahead;
+                        }
+                    }
+                    let span = Span { start, end: offset(&chars) };
+                    tokens.push(Self::number_token(&number_str, radix).map_err(|e| e.at(span))?);
+                }
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    let mut name = String::new();
+                    while let Some(&ch) = chars.peek() {
+                        if ch.is_ascii_alphanumeric() || ch == '_' {
+                            name.push(ch);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    tokens.push(match name.as_str() {
+                        "in" | "to" => Token::Convert,
+                        "xor" => Token::BitXor,
+                        _ => Token::Identifier(name),
+                    });
+                }
+                _ => return Err(CalcError::new(format!("Unexpected character: {}", ch)).at(error_span)),
+            }
+            if spans.len() < tokens.len() {
+                spans.push(Span { start, end: offset(&chars) });
+            }
+        }
+
+        tokens.push(Token::End);
+        spans.push(Span { start: length, end: length + 1 });
+        Ok((tokens, spans))
+    }
+
+    // Integers that an f64 can't hold exactly keep all their digits
+    fn number_token(text: &str, radix: u32) -> Result<Token, CalcError> {
+        let prefix = match radix {
+            16 => "0x",
+            8 => "0o",
+            2 => "0b",
+            _ => "",
+        };
+        let invalid = || CalcError::new(format!("Invalid number: {}{}", prefix, text));
+        if text.is_empty() {
+            return Err(invalid().with_help(format!("write at least one digit after {}", prefix)));
+        }
+        if text.starts_with('_') || text.ends_with('_') || ["__", "_.", "._", "_e", "_E"].iter().any(|s| text.contains(s)) {
+            return Err(invalid().with_help("'_' may only separate two digits, as in 1_000_000"));
+        }
+        if text.matches('.').count() > 1 {
+            return Err(invalid().with_help("a number can have only one decimal point"));
+        }
+        let digits = text.replace('_', "");
+
+        if radix == 10 && digits.contains(['.', 'e', 'E']) {
+            return match digits.parse::<f64>() {
+                Ok(value) if value.is_finite() => Ok(Token::Number(value)),
+                Ok(_) => Err(invalid().with_help("the value is too large")),
+                Err(_) => Err(invalid()),
+            };
+        }
+        let value = i128::from_str_radix(&digits, radix).map_err(|_| invalid().with_help("the value is too large"))?;
+        if value > MAX_EXACT_FLOAT_INTEGER {
+            Ok(Token::Integer(value))
+        } else {
+            Ok(Token::Number(value as f64))
+        }
+    }
+
+    fn current_token(&self) -> &Token {
+        self.tokens.get(self.position).unwrap_or(&Token::End)
+    }
+
+    fn current_span(&self) -> Span {
+        self.spans[self.position.min(self.spans.len() - 1)]
+    }
+
+    fn token_text(&self, span: Span) -> String {
+        self.source.chars().skip(span.start).take(span.end - span.start).collect()
+    }
+
+    fn error_here(&self, message: impl Into<String>) -> CalcError {
+        CalcError::new(message).at(self.current_span())
+    }
+
+    // An error at a token the grammar can't accept here, with a suggested fix
+    // for the common slips: a missing `*` (or `,` between arguments), a
+    // missing `)` or a stray `)`
+    fn unexpected(&self, message: &str, missing_comma: bool) -> CalcError {
+        let starts_operand = matches!(
+            self.current_token(),
+            Token::Number(_) | Token::Integer(_) | Token::Identifier(_) | Token::LeftParen
+        );
+        let previous = self.position.checked_sub(1).map(|i| &self.tokens[i]);
+        let ends_operand = matches!(
+            previous,
+            Some(Token::Number(_) | Token::Integer(_) | Token::Identifier(_) | Token::RightParen | Token::Factorial)
+        );
+        let count = |tokens: &[Token], wanted: &Token| tokens.iter().filter(|token| std::mem::discriminant(*token) == std::mem::discriminant(wanted)).count();
+        let open = count(&self.tokens, &Token::LeftParen);
+        let closed = count(&self.tokens, &Token::RightParen);
+        let span = self.current_span();
+
+        if starts_operand && ends_operand {
+            // 2(3+4) or 2 pi: join the operands with `*`
+            let before: String = self.source.chars().take(self.spans[self.position - 1].end).collect();
+            let after: String = self.source.chars().skip(span.start).collect();
+            let help = if missing_comma {
+                format!("separate arguments with a comma: {}, {}", before, after)
+            } else {
+                format!("use '*' to multiply: {}*{}", before, after)
+            };
+            return CalcError::new(format!("Missing operator before '{}'", self.token_text(span))).at(span).with_help(help);
+        }
+        match self.current_token() {
+            Token::End if open > closed => CalcError::new(message).at(span).with_help(format!(
+                "add {} at the end: {}{}",
+                if open - closed == 1 { "')'".to_string() } else { format!("{} ')'", open - closed) },
+                self.source.trim_end(),
+                ")".repeat(open - closed)
+            )),
+            Token::RightParen if count(&self.tokens[..=self.position], &Token::RightParen) > count(&self.tokens[..self.position], &Token::LeftParen) => {
+                CalcError::new("Unmatched ')'").at(span).with_help("remove it, or add the '(' it should close")
+            }
+            _ => CalcError::new(message).at(span),
+        }
+    }
+
+    // Index of the first token of the operand that ends just before `end`,
+    // like the `f` of `f(a, b)` or the `(` of `(a + b)!`
+    fn operand_start(&self, end: usize) -> usize {
+        let mut i = end - 1;
+        while i > 0 && matches!(self.tokens[i], Token::Factorial) {
+            i -= 1;
+        }
+        if matches!(self.tokens[i], Token::RightParen) {
+            let mut depth = 0;
+            loop {
+                match self.tokens[i] {
+                    Token::RightParen => depth += 1,
+                    Token::LeftParen => depth -= 1,
+                    _ => {}
+                }
+                if depth == 0 || i == 0 {
+                    break;
+                }
+                i -= 1;
+            }
+            if i > 0 && matches!(self.tokens[i - 1], Token::Identifier(_)) {
+                i -= 1;
+            }
+        }
+        i
+    }
+
+    fn advance(&mut self) {
+        if self.position < self.tokens.len() - 1 {
+            self.position += 1;
+        }
+    }
+
+    // Bitwise operators bind looser than arithmetic, as in C
+    fn parse_expression(&mut self) -> Result<Expr, CalcError> {
+        let mut result = self.parse_bit_xor()?;
+
+        while let Token::BitOr = self.current_token() {
+            self.advance();
+            result = Expr::binary(BinaryOp::BitOr, result, self.parse_bit_xor()?);
+        }
+
+        Ok(result)
+    }
+
+    fn parse_bit_xor(&mut self) -> Result<Expr, CalcError> {
+        let mut result = self.parse_bit_and()?;
+
+        while let Token::BitXor = self.current_token() {
+            self.advance();
+            result = Expr::binary(BinaryOp::BitXor, result, self.parse_bit_and()?);
+        }
+
+        Ok(result)
+    }
+
+    fn parse_bit_and(&mut self) -> Result<Expr, CalcError> {
+        let mut result = self.parse_shift()?;
+
+        while let Token::BitAnd = self.current_token() {
+            self.advance();
+            result = Expr::binary(BinaryOp::BitAnd, result, self.parse_shift()?);
+        }
+
+        Ok(result)
+    }
+
+    fn parse_shift(&mut self) -> Result<Expr, CalcError> {
+        let mut result = self.parse_sum()?;
+
+        loop {
+            let op = match self.current_token() {
+                Token::ShiftLeft => BinaryOp::ShiftLeft,
+                Token::ShiftRight => BinaryOp::ShiftRight,
+                _ => break,
+            };
+            self.advance();
+            result = Expr::binary(op, result, self.parse_sum()?);
+        }
+
+        Ok(result)
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, CalcError> {
+        let mut result = self.parse_term()?;
+
+        loop {
+            let op = match self.current_token() {
+                Token::Plus => BinaryOp::Add,
+                Token::Minus => BinaryOp::Subtract,
+                _ => break,
+            };
+            self.advance();
+            result = Expr::binary(op, result, self.parse_term()?);
+        }
+
+        Ok(result)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, CalcError> {
+        let mut result = self.parse_factor()?;
+
+        loop {
+            let op = match self.current_token() {
+                Token::Multiply => BinaryOp::Multiply,
+                Token::Divide => BinaryOp::Divide,
+                Token::Modulo => BinaryOp::Modulo,
+                _ => break,
+            };
+            self.advance();
+            result = Expr::binary(op, result, self.parse_factor()?);
+        }
+
+        Ok(result)
+    }
+
+    // Unary minus binds looser than `^`, so -2^2 is -4
+    fn parse_factor(&mut self) -> Result<Expr, CalcError> {
+        if matches!(self.current_token(), Token::Minus) {
+            self.advance();
+            return Ok(Expr::Negate(Box::new(self.parse_factor()?)));
+        }
+        if matches!(self.current_token(), Token::BitNot) {
+            self.advance();
+            return Ok(Expr::BitNot(Box::new(self.parse_factor()?)));
+        }
+        self.parse_power()
+    }
+
+    // Right-associative: 2^3^2 is 2^9. The exponent may carry its own sign.
+    fn parse_power(&mut self) -> Result<Expr, CalcError> {
+        let base = self.parse_postfix()?;
+        if matches!(self.current_token(), Token::Power) {
+            self.advance();
+            let exponent = self.parse_factor()?;
+            return Ok(Expr::binary(BinaryOp::Power, base, exponent));
+        }
+        Ok(base)
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, CalcError> {
+        let mut result = self.parse_primary()?;
+        while matches!(self.current_token(), Token::Factorial) {
+            self.advance();
+            result = Expr::Factorial(Box::new(result));
+        }
+        Ok(result)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, CalcError> {
+        match self.current_token() {
+            Token::Number(n) => {
+                let value = *n;
+                self.advance();
+                // An imaginary literal like 3i
+                if matches!(self.current_token(), Token::Identifier(name) if name == "i")
+                    && !matches!(self.tokens.get(self.position + 1), Some(Token::LeftParen))
+                {
+                    self.advance();
+                    return Ok(Expr::Number(value).times(Expr::Variable("i".to_string())));
+                }
+                if self.at_unit() {
+                    return self.parse_units().map(|units| Self::attach_units(Expr::Number(value), units));
+                }
+                // A coefficient written against a variable, as in 5x or 3x^2
+                let adjacent = self.spans[self.position - 1].end == self.current_span().start;
+                if adjacent && matches!(self.current_token(), Token::Identifier(_)) {
+                    return Ok(Expr::Number(value).times(self.parse_power()?));
+                }
+                Ok(Expr::Number(value))
+            }
+            Token::Integer(n) => {
+                let value = *n;
+                self.advance();
+                Ok(Expr::Integer(value))
+            }
+            Token::Identifier(name) if FUNCTIONS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.advance();
+                if !matches!(self.current_token(), Token::LeftParen) {
+                    let argument = match self.current_token() {
+                        Token::Number(_) | Token::Integer(_) | Token::Identifier(_) => self.token_text(self.current_span()),
+                        _ => "...".to_string(),
+                    };
+                    return Err(CalcError::new(format!("Expected '(' after function {}", name))
+                        .at(self.spans[self.position - 1])
+                        .with_help(format!("call it as {}({})", name, argument)));
+                }
+                self.advance();
+                let args = self.parse_arguments(&name)?;
+                Ok(Expr::Call(name, args))
+            }
+            Token::Identifier(name) => {
+                let name = name.clone();
+                self.advance();
+                Ok(Expr::Variable(name))
+            }
+            Token::LeftParen => {
+                self.advance();
+                let result = self.parse_expression()?;
+                if !matches!(self.current_token(), Token::RightParen) {
+                    return Err(self.unexpected("Expected closing parenthesis", false));
+                }
+                self.advance();
+                Ok(result)
+            }
+            Token::End => Err(self.error_here("Unexpected end of input; expected a number, variable or opening parenthesis")),
+            Token::RightParen => Err(self.unexpected("Expected number, variable or opening parenthesis", false)),
+            _ => Err(self.error_here("Expected number, variable or opening parenthesis")),
+        }
+    }
+
+    // Comma-separated arguments after the opening parenthesis. The first
+    // argument of solve() may be an equation, `lhs = rhs`, which becomes
+    // `lhs - rhs`.
+    fn parse_arguments(&mut self, function: &str) -> Result<Vec<Expr>, CalcError> {
+        let equation = function == "solve";
+        let mut args = Vec::new();
+        if matches!(self.current_token(), Token::RightParen) {
+            self.advance();
+            return Ok(args);
+        }
+
+        loop {
+            let mut start = self.position;
+            while matches!(self.tokens[start], Token::Minus | Token::BitNot) {
+                start += 1;
+            }
+            let mut arg = self.parse_expression()?;
+            if equation && args.is_empty() && matches!(self.current_token(), Token::Assign) {
+                self.advance();
+                arg = arg.minus(self.parse_expression()?);
+            }
+            args.push(arg);
+            match self.current_token() {
+                Token::Comma => self.advance(),
+                Token::RightParen => {
+                    self.advance();
+                    return Ok(args);
+                }
+                _ => {
+                    // max(a b) is missing a comma, but sin(2 x) and max(a + 2 b) a `*`
+                    let missing_comma = MULTI_ARGUMENT_FUNCTIONS.contains(&function)
+                        && self.operand_start(self.position) == start;
+                    return Err(self.unexpected("Expected ',' or closing parenthesis in function call", missing_comma));
+                }
+            }
+        }
+    }
+
+    fn at_unit(&self) -> bool {
+        match (self.current_token(), self.tokens.get(self.position + 1)) {
+            (Token::Identifier(_), Some(Token::LeftParen)) => false,
+            (Token::Identifier(name), _) => find_unit(name).is_some(),
+            _ => false,
+        }
+    }
+
+    // Units written after a number, like `km/h` or `m/s^2`, or after `in`
+    fn parse_units(&mut self) -> Result<Vec<UnitPower>, CalcError> {
+        let mut units = Vec::new();
+        let mut sign = 1;
+        loop {
+            let name = match self.current_token() {
+                Token::Identifier(name) if find_unit(name).is_some() => name.clone(),
+                Token::Identifier(name) => return Err(self.error_here(format!("Unknown unit: {}", name))),
+                _ => return Err(self.error_here("Expected a unit")),
+            };
+            self.advance();
+
+            let mut power = 1;
+            if matches!(self.current_token(), Token::Power) {
+                self.advance();
+                let negative = matches!(self.current_token(), Token::Minus);
+                if negative {
+                    self.advance();
+                }
+                power = match self.current_token() {
+                    Token::Number(n) if n.fract() == 0.0 => *n as i32,
+                    _ => return Err(self.error_here(format!("Expected a whole-number power after {}^", name))),
+                };
+                self.advance();
+                if negative {
+                    power = -power;
+                }
+            }
+            units.push((name, sign * power));
+
+            // A `*` or `/` continues the units only when another unit follows
+            sign = match (self.current_token(), self.tokens.get(self.position + 1)) {
+                (Token::Multiply, Some(Token::Identifier(next))) if find_unit(next).is_some() => 1,
+                (Token::Divide, Some(Token::Identifier(next))) if find_unit(next).is_some() => -1,
+                _ => return Ok(units),
+            };
+            self.advance();
+        }
+    }
+
+    // `40 km/h` becomes the tree for 40*km/h
+    fn attach_units(number: Expr, units: Vec<UnitPower>) -> Expr {
+        units.into_iter().fold(number, |result, (name, power)| {
+            let unit = match power.abs() {
+                1 => Expr::Variable(name),
+                p => Expr::binary(BinaryOp::Power, Expr::Variable(name), Expr::Number(p as f64)),
+            };
+            let op = if power > 0 { BinaryOp::Multiply } else { BinaryOp::Divide };
+            Expr::binary(op, result, unit)
+        })
+    }
+
+    fn expect_end(&self) -> Result<(), CalcError> {
+        if !matches!(self.current_token(), Token::End) {
+            return Err(self.unexpected("Unexpected token after expression", false));
+        }
+        Ok(())
+    }
+
+    fn parse(&mut self) -> Result<Expr, CalcError> {
+        let result = self.parse_expression()?;
+        self.expect_end()?;
+        Ok(result)
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, CalcError> {
+        let target = match (self.tokens.first(), self.tokens.get(1)) {
+            (Some(Token::Identifier(name)), Some(Token::Assign)) => Some(name.clone()),
+            _ => None,
+        };
+        if target.is_some() {
+            self.position = 2;
+        }
+        let expr = self.parse_expression()?;
+        let conversion = if matches!(self.current_token(), Token::Convert) {
+            self.advance();
+            Some(self.parse_units()?)
+        } else {
+            None
+        };
+        self.expect_end()?;
+        Ok(Statement { target, expr, conversion })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// REPL: history, line editing and script files
+// ---------------------------------------------------------------------------
+
+const HISTORY_FILE: &str = ".calc_history";
+const MAX_HISTORY: usize = 1000;
+// Guards against a script that loads itself
+const MAX_LOAD_DEPTH: usize = 8;
+
+// Entered lines, persisted to ~/.calc_history (or $CALC_HISTORY) so they
+// survive across sessions
+struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    fn load() -> Self {
+        let path = std::env::var_os("CALC_HISTORY").map(PathBuf::from).or_else(|| {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+        });
+        let mut entries: Vec<String> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+
+        // Trim the file once it has grown well past the limit
+        if entries.len() > MAX_HISTORY {
+            entries.drain(..entries.len() - MAX_HISTORY);
+            if let Some(path) = &path {
+                let _ = fs::write(path, entries.join("\n") + "\n");
+            }
+        }
+        History { entries, path }
+    }
+
+    fn add(&mut self, line: &str) {
+        if line.is_empty() || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+
+        // Appended right away so a crash doesn't lose the session
+        if let Some(path) = &self.path {
+            if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+}
+
+// Puts the terminal into character-at-a-time mode with `stty`, restoring
+// the saved settings when dropped
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<Self> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return None;
+        }
+        let stty = |args: &[&str]| Command::new("stty").args(args).stdin(Stdio::inherit()).output().ok().filter(|output| output.status.success());
+        let saved = String::from_utf8(stty(&["-g"])?.stdout).ok()?.trim().to_string();
+        stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"])?;
+        Some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = Command::new("stty").arg(&self.saved).stdin(Stdio::inherit()).status();
     }
+}
 
-    fn tokenize(input: &str) -> Result<Vec<Token>, String> {
-        let mut tokens = Vec::new();
-        let mut chars = input.chars().peekable();
+// Reads one line, with arrow keys, Home/End, Ctrl-A/E/U/K and history
+// recall when the terminal allows it. None means end of input.
+fn read_line(prompt: &str, history: &[String]) -> io::Result<Option<String>> {
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", prompt)?;
+    stdout.flush()?;
 
-        while let Some(&ch) = chars.peek() {
-            match ch {
-                ' ' | '\t' => {
-                    chars.next();
-                }
-                '+' => {
-                    tokens.push(Token::Plus);
-                    chars.next();
-                }
-                '-' => {
-                    tokens.push(Token::Minus);
-                    chars.next();
-                }
-                '*' => {
-                    tokens.push(Token::Multiply);
-                    chars.next();
-                }
-                '/' => {
-                    tokens.push(Token::Divide);
-                    chars.next();
-                }
-                '(' => {
-                    tokens.push(Token::LeftParen);
-                    chars.next();
+    let Some(_raw) = RawMode::enable() else {
+        let mut input = String::new();
+        return Ok(match io::stdin().read_line(&mut input)? {
+            0 => None,
+            _ => Some(input),
+        });
+    };
+    edit_line(prompt, history, &mut io::stdin().lock(), &mut io::stdout())
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    Ok(match input.read(&mut byte)? {
+        0 => None,
+        _ => Some(byte[0]),
+    })
+}
+
+fn edit_line(prompt: &str, history: &[String], stdin: &mut impl Read, stdout: &mut impl Write) -> io::Result<Option<String>> {
+    let mut line: Vec<char> = Vec::new();
+    let mut cursor = 0;
+    // Position in the history; history.len() is the line being typed
+    let mut recalled = history.len();
+    let mut draft: Vec<char> = Vec::new();
+
+    loop {
+        let Some(byte) = read_byte(stdin)? else {
+            return Ok(None);
+        };
+        match byte {
+            b'\r' | b'\n' => {
+                write!(stdout, "\r\n")?;
+                return Ok(Some(line.into_iter().collect()));
+            }
+            // Ctrl-C abandons the line, Ctrl-D on an empty line ends input
+            0x03 => {
+                write!(stdout, "^C\r\n")?;
+                return Ok(Some(String::new()));
+            }
+            0x04 if line.is_empty() => {
+                write!(stdout, "\r\n")?;
+                return Ok(None);
+            }
+            0x04 if cursor < line.len() => {
+                line.remove(cursor);
+            }
+            0x7f | 0x08 if cursor > 0 => {
+                cursor -= 1;
+                line.remove(cursor);
+            }
+            // Backspace does nothing at the start of the line
+            0x7f | 0x08 => {}
+            0x01 => cursor = 0,
+            0x05 => cursor = line.len(),
+            0x15 => {
+                line.drain(..cursor);
+                cursor = 0;
+            }
+            0x0b => line.truncate(cursor),
+            0x1b => {
+                let (Some(b'[' | b'O'), Some(key)) = (read_byte(stdin)?, read_byte(stdin)?) else {
+                    continue;
+                };
+                match key {
+                    b'A' if recalled > 0 => {
+                        if recalled == history.len() {
+                            draft = line.clone();
+                        }
+                        recalled -= 1;
+                        line = history[recalled].chars().collect();
+                        cursor = line.len();
+                    }
+                    b'B' if recalled < history.len() => {
+                        recalled += 1;
+                        line = match history.get(recalled) {
+                            Some(entry) => entry.chars().collect(),
+                            None => draft.clone(),
+                        };
+                        cursor = line.len();
+                    }
+                    b'C' if cursor < line.len() => cursor += 1,
+                    b'D' if cursor > 0 => cursor -= 1,
+                    b'H' => cursor = 0,
+                    b'F' => cursor = line.len(),
+                    // Delete sends ESC [ 3 ~
+                    b'3' => {
+                        read_byte(stdin)?;
+                        if cursor < line.len() {
+                            line.remove(cursor);
+                        }
+                    }
+                    _ => {}
                 }
-                ')' => {
-                    tokens.push(Token::RightParen);
-                    chars.next();
+            }
+            byte if byte >= 0x20 => {
+                // Gather the continuation bytes of a multi-byte character
+                let mut bytes = vec![byte];
+                for _ in 1..byte.leading_ones().clamp(1, 4) {
+                    bytes.extend(read_byte(stdin)?);
                 }
-                '0'..='9' | '.' => {
-                    let mut number_str = String::new();
-                    while let Some(&ch) = chars.peek() {
-                        if ch.is_ascii_digit() || ch == '.' {
-                            number_str.push(ch);
-                            chars.next();
-                        } else {
-                            break;
-                        }
+                if let Ok(text) = std::str::from_utf8(&bytes) {
+                    for ch in text.chars() {
+                        line.insert(cursor, ch);
+                        cursor += 1;
                     }
-                    let number: f64 = number_str.parse()
-                        .map_err(|_| format!("Invalid number: {}", number_str))?;
-                    tokens.push(Token::Number(number));
                 }
-                _ => return Err(format!("Unexpected character: {}", ch)),
             }
+            _ => {}
         }
 
-        tokens.push(Token::End);
-        Ok(tokens)
+        let text: String = line.iter().collect();
+        write!(stdout, "\r{}{}\x1b[K", prompt, text)?;
+        if cursor < line.len() {
+            write!(stdout, "\x1b[{}D", line.len() - cursor)?;
+        }
+        stdout.flush()?;
     }
+}
 
-    fn current_token(&self) -> &Token {
-        self.tokens.get(self.position).unwrap_or(&Token::End)
+enum Flow {
+    Continue,
+    Quit,
+}
+
+#[derive(Clone, Copy)]
+struct RunOptions {
+    // Print bare results, one per line, and stay quiet about settings
+    batch: bool,
+    // Stop a script at its first error
+    strict: bool,
+    // How many `:load`s deep we are
+    depth: usize,
+}
+
+// Runs one line of input: a command, a setting or a calculation
+fn run_line(env: &mut Environment, input: &str, options: RunOptions) -> Result<Flow, CalcError> {
+    let batch = options.batch;
+    match input {
+        "quit" => {
+            if !batch {
+                println!("Goodbye!");
+            }
+            return Ok(Flow::Quit);
+        }
+        "vars" => {
+            env.display();
+            return Ok(Flow::Continue);
+        }
+        "clear" => {
+            env.clear();
+            if !batch {
+                println!("Variables cleared.");
+            }
+            return Ok(Flow::Continue);
+        }
+        _ => {}
     }
 
-    fn advance(&mut self) {
-        if self.position < self.tokens.len() - 1 {
-            self.position += 1;
+    if let Some(path) = input.strip_prefix(":load") {
+        return load_script(env, path.trim(), options);
+    }
+
+    if let Some(result) = env.apply_setting(input) {
+        let settings = result?;
+        if !batch {
+            println!("{}", settings);
         }
+        return Ok(Flow::Continue);
     }
 
-    fn parse_expression(&mut self) -> Result<f64, String> {
-        let mut result = self.parse_term()?;
+    if let Some(expression) = input.strip_prefix("simplify ") {
+        let expr = Calculator::new(expression).and_then(|mut calc| calc.parse()).map_err(|e| {
+            // Point into the whole line, not just the expression
+            let offset = input.chars().count() - expression.chars().count();
+            CalcError { span: e.span.map(|span| Span { start: span.start + offset, end: span.end + offset }), ..e }
+        })?;
+        println!("{}", expr.expand_derivatives()?.simplify());
+        return Ok(Flow::Continue);
+    }
 
-        while matches!(self.current_token(), Token::Plus | Token::Minus) {
-            match self.current_token() {
-                Token::Plus => {
-                    self.advance();
-                    result += self.parse_term()?;
-                }
-                Token::Minus => {
-                    self.advance();
-                    result -= self.parse_term()?;
+    let prefix = if batch { "" } else { "Result: " };
+    match env.execute(input)? {
+        Evaluation::Assigned(name, value) => println!("{} = {}", name, env.format_value(&value)),
+        Evaluation::Computed(result) => println!("{}{}", prefix, env.format_value(&result)),
+        Evaluation::Symbolic(expr) => println!("{}{}", prefix, expr),
+        Evaluation::Solved(var, roots) if roots.is_empty() => println!("No real solutions for {}", var),
+        Evaluation::Solved(var, roots) => {
+            for root in roots {
+                match root.closed_form {
+                    Some(form) => println!("{} = {} = {}", var, form, env.format_value(&root.value)),
+                    None => println!("{} = {}", var, env.format_value(&root.value)),
                 }
-                _ => break,
             }
         }
+    }
+    Ok(Flow::Continue)
+}
 
-        Ok(result)
+fn load_script(env: &mut Environment, path: &str, options: RunOptions) -> Result<Flow, CalcError> {
+    if path.is_empty() {
+        return Err(CalcError::new("Usage: :load <file.calc>"));
+    }
+    if options.depth >= MAX_LOAD_DEPTH {
+        return Err(CalcError::new(format!("Cannot load {}: scripts are nested too deeply", path)));
     }
+    let file = fs::File::open(path).map_err(|e| CalcError::new(format!("Cannot read {}: {}", path, e)))?;
 
-    fn parse_term(&mut self) -> Result<f64, String> {
-        let mut result = self.parse_factor()?;
+    let (flow, errors) = run_script(env, io::BufReader::new(file), path, RunOptions { depth: options.depth + 1, ..options });
+    if errors > 0 {
+        return Err(CalcError::new(format!("{} had {} error(s)", path, errors)));
+    }
+    if !options.batch {
+        println!("Loaded {}", path);
+    }
+    Ok(flow)
+}
 
-        while matches!(self.current_token(), Token::Multiply | Token::Divide) {
-            match self.current_token() {
-                Token::Multiply => {
-                    self.advance();
-                    result *= self.parse_factor()?;
-                }
-                Token::Divide => {
-                    self.advance();
-                    let divisor = self.parse_factor()?;
-                    if divisor == 0.0 {
-                        return Err("Division by zero".to_string());
-                    }
-                    result /= divisor;
-                }
-                _ => break,
+// Runs every line of a script, skipping blank lines and `#` comments, and
+// reports errors with their line number
+fn run_script(env: &mut Environment, script: impl BufRead, name: &str, options: RunOptions) -> (Flow, usize) {
+    let mut errors = 0;
+    for (number, line) in script.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                return (Flow::Continue, errors + 1);
             }
+        };
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
         }
 
-        Ok(result)
+        match run_line(env, input, options) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => return (Flow::Quit, errors),
+            Err(e) => {
+                eprintln!("{}:{}: {}", name, number + 1, e.render(input));
+                errors += 1;
+                if options.strict {
+                    break;
+                }
+            }
+        }
     }
+    (Flow::Continue, errors)
+}
 
-    fn parse_factor(&mut self) -> Result<f64, String> {
-        match self.current_token() {
-            Token::Number(n) => {
-                let value = *n;
-                self.advance();
-                Ok(value)
-            }
-            Token::LeftParen => {
-                self.advance();
-                let result = self.parse_expression()?;
-                if !matches!(self.current_token(), Token::RightParen) {
-                    return Err("Expected closing parenthesis".to_string());
-                }
-                self.advance();
-                Ok(result)
+fn print_usage() {
+    println!("Usage: calculator [--strict] [FILE]");
+    println!();
+    println!("Starts an interactive session when run in a terminal. Given a FILE, or");
+    println!("input that isn't a terminal, evaluates each line and prints the results");
+    println!("one per line; use '-' to read from stdin explicitly.");
+    println!();
+    println!("  --strict    stop and exit with status 1 at the first error");
+}
+
+fn print_banner() {
+    println!("Advanced Calculator");
+    println!("Supports: +, -, *, /, % (modulo), ^ (power), ! (factorial), parentheses, and decimal numbers");
+    println!("Functions: {}", FUNCTIONS.join(", "));
+    println!("Variables: 'rate = 0.07', 'ans' for the last result, constants pi, e, tau");
+    println!("Derivatives: 'diff(x^3 + sin(x), x)', or 'diff(x^3, x, 2)' to evaluate at x = 2");
+    println!("Equations: 'solve(x^2 - 5x + 6 = 0, x)', or 'solve(cos(x) = x, x, 0, 2)' to search an interval");
+    println!("Enter 'simplify <expr>' to simplify an expression without evaluating it");
+    println!("Units: '3 GiB / 150 MB/s', '40 km/h * 2.5 h in mi', prefixes like k, M, Gi; '5 ft to cm' converts");
+    println!("Programmer: 'mode integer', 'type u8'..'i64', 'overflow wrap|error', 'base 16'; 0xFF, 0o17, 0b1010, 1_000_000, & | ^ ~ << >>");
+    println!("Precision: 'mode float|rational|decimal', 'precision 50', 'rounding half-up', 'format fraction|decimal'");
+    println!("Complex: 'mode complex', then 'sqrt(-4)', '(3 + 4i)*(1 - 2i)', 'exp(i*pi)', abs/arg/conj/re/im; 'format polar|rectangular'");
+    println!("Enter ':load file.calc' to run a script; up and down arrows recall earlier lines");
+    println!("Enter 'vars' to list variables, 'clear' to reset, 'quit' to exit\n");
+}
+
+fn main() {
+    let mut strict = false;
+    let mut script: Option<String> = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--strict" => strict = true,
+            "-h" | "--help" => {
+                print_usage();
+                return;
             }
-            Token::Minus => {
-                self.advance();
-                Ok(-self.parse_factor()?)
+            option if option.starts_with("--") => {
+                eprintln!("Unknown option: {}", option);
+                print_usage();
+                process::exit(2);
             }
-            _ => Err("Expected number or opening parenthesis".to_string()),
+            _ => script = Some(arg),
         }
     }
 
-    fn evaluate(&mut self) -> Result<f64, String> {
-        let result = self.parse_expression()?;
-        if !matches!(self.current_token(), Token::End) {
-            return Err("Unexpected token after expression".to_string());
+    let mut env = Environment::new();
+
+    // Batch mode: a script file, or piped input
+    if script.is_some() || !io::stdin().is_terminal() {
+        let options = RunOptions { batch: true, strict, depth: 0 };
+        let path = script.unwrap_or_else(|| "-".to_string());
+        let (_, errors) = if path == "-" {
+            run_script(&mut env, io::stdin().lock(), "<stdin>", options)
+        } else {
+            match fs::File::open(&path) {
+                Ok(file) => run_script(&mut env, io::BufReader::new(file), &path, options),
+                Err(e) => {
+                    eprintln!("Cannot read {}: {}", path, e);
+                    process::exit(1);
+                }
+            }
+        };
+        if strict && errors > 0 {
+            process::exit(1);
         }
-        Ok(result)
+        return;
     }
-}
 
-fn main() {
-    println!("Advanced Calculator");
-    println!("Supports: +, -, *, /, parentheses, and decimal numbers");
-    println!("Enter 'quit' to exit\n");
+    print_banner();
+    let mut history = History::load();
 
     loop {
-        print!("calc> ");
-        io::Write::flush(&mut io::stdout()).unwrap();
+        let line = match read_line("calc> ", &history.entries) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Failed to read input: {}", e);
+                break;
+            }
+        };
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).expect("Failed to read input");
-        
-        let input = input.trim();
-        
+        let input = line.trim();
         if input.is_empty() {
             continue;
         }
-        
-        if input == "quit" {
-            println!("Goodbye!");
-            break;
+        history.add(input);
+
+        match run_line(&mut env, input, RunOptions { batch: false, strict: false, depth: 0 }) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => break,
+            Err(e) => println!("{}", e.render(input)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Expr {
+        Calculator::new(input).and_then(|mut calc| calc.parse()).unwrap()
+    }
+
+    // Runs a line the way the REPL does and returns what it would print
+    fn run(env: &mut Environment, input: &str) -> Result<String, String> {
+        if let Some(result) = env.apply_setting(input) {
+            return result;
+        }
+        Ok(match env.execute(input).map_err(|e| e.message)? {
+            Evaluation::Computed(value) | Evaluation::Assigned(_, value) => env.format_value(&value),
+            Evaluation::Symbolic(expr) => expr.to_string(),
+            Evaluation::Solved(var, roots) => roots
+                .iter()
+                .map(|root| match &root.closed_form {
+                    Some(form) => format!("{} = {} = {}", var, form, env.format_value(&root.value)),
+                    None => format!("{} = {}", var, env.format_value(&root.value)),
+                })
+                .collect::<Vec<_>>()
+                .join("; "),
+        })
+    }
+
+    fn calc(input: &str) -> String {
+        run(&mut Environment::new(), input).unwrap()
+    }
+
+    fn calc_err(input: &str) -> String {
+        run(&mut Environment::new(), input).unwrap_err()
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        assert_eq!(parse("1 + 2 * 3"), Expr::Number(1.0).plus(Expr::Number(2.0).times(Expr::Number(3.0))));
+        assert_eq!(parse("2^3^2"), Expr::Number(2.0).pow(Expr::Number(3.0).pow(Expr::Number(2.0))));
+        assert_eq!(parse("-2^2"), Expr::Negate(Box::new(Expr::Number(2.0).pow(Expr::Number(2.0)))));
+        assert_eq!(calc("1 + 2 * 3"), "7");
+        assert_eq!(calc("(1 + 2) * 3"), "9");
+        assert_eq!(calc("2^3^2"), "512");
+        assert_eq!(calc("-2^2"), "-4");
+        assert_eq!(calc("10 - 4 - 3"), "3");
+        assert_eq!(calc("3!"), "6");
+        assert_eq!(calc("7 % 4"), "3");
+    }
+
+    #[test]
+    fn test_display_minimal_parentheses() {
+        for input in ["1 + 2*3", "(1 + 2)*3", "a - (b - c)", "a - b - c", "(a^b)^c", "a^b^c", "-(a + b)", "(-a)^2", "a^-b", "(n + 1)!", "max(a, b + 1)"] {
+            let printed = parse(input).to_string();
+            assert_eq!(parse(&printed), parse(input), "{} printed as {}", input, printed);
+            assert_eq!(printed, parse(&printed).to_string());
+        }
+        assert_eq!(parse("((a)) + ((b * c))").to_string(), "a + b*c");
+        assert_eq!(parse("a - (b - c)").to_string(), "a - (b - c)");
+        assert_eq!(parse("(a^b)^c").to_string(), "(a^b)^c");
+    }
+
+    #[test]
+    fn test_reevaluate_with_variables() {
+        let expr = parse("x^2 + 1");
+        let mut env = Environment::new();
+        for (x, expected) in [(0.0, 1.0), (2.0, 5.0), (-3.0, 10.0)] {
+            env.set("x", Value::Float(x)).unwrap();
+            assert_eq!(expr.eval(&env).unwrap().into_number().unwrap(), expected);
+        }
+
+        assert_eq!(run(&mut env, "y = 4").unwrap(), "4");
+        assert_eq!(run(&mut env, "y * 2").unwrap(), "8");
+        assert_eq!(run(&mut env, "ans + 1").unwrap(), "9");
+        assert_eq!(run(&mut env, "z + 1").unwrap_err(), "Unknown variable: z");
+        assert!(run(&mut env, "pi = 3").is_err());
+    }
+
+    fn simplified(input: &str) -> String {
+        parse(input).expand_derivatives().unwrap().simplify().to_string()
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        assert_eq!(simplified("2^10 + y"), "y + 1024");
+        assert_eq!(simplified("sin(0) + x"), "x");
+        assert_eq!(simplified("1/4 * x"), "0.25*x");
+        assert_eq!(simplified("1/3 + x"), format!("x + {}", 1.0 / 3.0));
+        assert_eq!(simplified("sqrt(2) * x"), format!("{}*x", 2f64.sqrt()));
+        // Left unfolded so evaluation reports the error
+        assert_eq!(simplified("x/0"), "x/0");
+        assert_eq!(simplified("sqrt(-1) + x"), "sqrt(-1) + x");
+    }
+
+    #[test]
+    fn test_simplify_identities() {
+        assert_eq!(simplified("(x + 0)*1"), "x");
+        assert_eq!(simplified("x^1 + 0*y"), "x");
+        assert_eq!(simplified("y^0"), "1");
+        assert_eq!(simplified("2*x + 3*x - x"), "4*x");
+        assert_eq!(simplified("x - x"), "0");
+        assert_eq!(simplified("x*x^2/x"), "x^2");
+        assert_eq!(simplified("6*x/4"), "3*x/2");
+        assert_eq!(simplified("-(-x)"), "x");
+    }
+
+    #[test]
+    fn test_derivative() {
+        assert_eq!(simplified("diff(x^3 + sin(x), x)"), "3*x^2 + cos(x)");
+        assert_eq!(simplified("diff(1/x, x)"), "-1/x^2");
+        assert_eq!(simplified("diff(sqrt(x), x)"), "1/(2*sqrt(x))");
+        assert_eq!(simplified("diff(sin(x)*cos(x), x)"), "cos(x)^2 - sin(x)^2");
+        assert_eq!(simplified("diff(x^x, x)"), "x^x*(ln(x) + 1)");
+        assert_eq!(simplified("diff(x^2*exp(2*x), x)"), "2*x*exp(2*x) + 2*x^2*exp(2*x)");
+        assert_eq!(simplified("diff(a*x + b, x)"), "a");
+        assert_eq!(simplified("diff(diff(x^4, x), x)"), "12*x^2");
+
+        assert_eq!(calc("diff(x^3, x, 2)"), "12");
+        assert_eq!(calc("diff(x^3 + sin(x), x)"), "3*x^2 + cos(x)");
+        let mut env = Environment::new();
+        run(&mut env, "x = 2").unwrap();
+        assert_eq!(run(&mut env, "diff(x^3, x)").unwrap(), "12");
+
+        assert!(calc_err("diff(x!, x)").contains("factorial"));
+        assert!(calc_err("diff(x^2, 2)").contains("expects a variable"));
+        assert!(calc_err("d = diff(x^2, x)").contains("depends on x"));
+    }
+
+    fn big(digits: &str) -> BigInt {
+        match digits.strip_prefix('-') {
+            Some(rest) => -&BigInt::parse(rest).unwrap(),
+            None => BigInt::parse(digits).unwrap(),
+        }
+    }
+
+    fn ratio(num: i128, den: i128) -> Rational {
+        Rational::new(BigInt::from_i128(num), BigInt::from_i128(den))
+    }
+
+    #[test]
+    fn test_bigint_div_rem() {
+        // Truncating, with the remainder taking the dividend's sign
+        for (a, b) in [(7, 2), (-7, 2), (7, -2), (-7, -2), (6, 3), (0, 5), (5, 7)] {
+            let (quotient, remainder) = BigInt::from_i128(a).div_rem(&BigInt::from_i128(b));
+            assert_eq!((quotient.to_i128(), remainder.to_i128()), (Some(a / b), Some(a % b)), "{} / {}", a, b);
+        }
+
+        // Across several limbs, checked against i128
+        let a: i128 = 123_456_789_012_345_678_901_234_567_890;
+        for b in [1_000_000_007i128, 999_999_999_999_999_989, -98_765_432_109_876_543_210, a + 1] {
+            let (quotient, remainder) = BigInt::from_i128(a).div_rem(&BigInt::from_i128(b));
+            assert_eq!((quotient.to_i128(), remainder.to_i128()), (Some(a / b), Some(a % b)), "{} / {}", a, b);
         }
 
-        match Calculator::new(input) {
-            Ok(mut calc) => {
-                match calc.evaluate() {
-                    Ok(result) => println!("Result: {}", result),
-                    Err(e) => println!("Error: {}", e),
+        let huge = big("1000000000000000000000000000000000000000000000000007");
+        let (quotient, remainder) = huge.div_rem(&BigInt::pow10(25));
+        assert_eq!(quotient.to_string(), "100000000000000000000000000");
+        assert_eq!(remainder.to_string(), "7");
+        assert_eq!(big("-123456789012345678901234567890").to_string(), "-123456789012345678901234567890");
+        assert_eq!(big("000").to_string(), "0");
+
+        // Long operands, including limbs at both ends of the range, checked
+        // by multiplying back
+        let mut seed = 12345u64;
+        let mut limbs = |count: usize| -> BigInt {
+            let limbs = (0..count).map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                match seed >> 61 {
+                    0 => 0,
+                    1 => LIMB_BASE as u32 - 1,
+                    _ => ((seed >> 20) % LIMB_BASE) as u32,
+                }
+            });
+            BigInt::normalized(false, limbs.collect())
+        };
+        for (a_len, b_len) in [(2, 2), (5, 2), (12, 3), (40, 17), (60, 59), (100, 1)] {
+            for _ in 0..50 {
+                let (a, b) = (limbs(a_len), limbs(b_len));
+                if b.is_zero() {
+                    continue;
                 }
+                let (quotient, remainder) = a.div_rem(&b);
+                assert!(remainder < b);
+                assert_eq!(&(&quotient * &b) + &remainder, a);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bigint_gcd() {
+        let gcd = |a: i128, b: i128| BigInt::from_i128(a).gcd(&BigInt::from_i128(b)).to_i128().unwrap();
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(-12, 18), 6);
+        assert_eq!(gcd(0, 5), 5);
+        assert_eq!(gcd(17, 5), 1);
+
+        let two = BigInt::from_u64(2);
+        let a = &two.pow(100) * &BigInt::from_u64(3);
+        let b = &two.pow(90) * &BigInt::from_u64(9);
+        assert_eq!(a.gcd(&b), &two.pow(90) * &BigInt::from_u64(3));
+
+        assert_eq!(big("1000000000000000000000").isqrt().to_string(), "31622776601");
+        assert_eq!(BigInt::from_u64(144).isqrt().to_string(), "12");
+    }
+
+    #[test]
+    fn test_rational_rounding_modes() {
+        use RoundingMode::*;
+        let modes = [HalfEven, HalfUp, HalfDown, Down, Up, Floor, Ceiling];
+        let cases = [
+            (ratio(5, 2), [2, 3, 2, 2, 3, 2, 3]),
+            (ratio(-5, 2), [-2, -3, -2, -2, -3, -3, -2]),
+            (ratio(7, 2), [4, 4, 3, 3, 4, 3, 4]),
+            (ratio(-13, 5), [-3, -3, -3, -2, -3, -3, -2]),
+            (ratio(12, 5), [2, 2, 2, 2, 3, 2, 3]),
+            (ratio(4, 1), [4, 4, 4, 4, 4, 4, 4]),
+        ];
+        for (value, expected) in cases {
+            for (mode, expected) in modes.iter().zip(expected) {
+                assert_eq!(value.round_with(*mode).to_i128(), Some(expected), "{} rounded {}", value.display_fraction(), mode.name());
             }
-            Err(e) => println!("Parse Error: {}", e),
         }
+
+        assert_eq!(ratio(2, 3).display_decimal(5, HalfUp), "0.66667...");
+        assert_eq!(ratio(2, 3).display_decimal(5, Down), "0.66666...");
+        assert_eq!(ratio(-1, 8).display_decimal(2, HalfEven), "-0.12...");
+        assert_eq!(ratio(-1, 8).display_decimal(3, HalfEven), "-0.125");
+        assert_eq!(ratio(123_456, 1).display_decimal(3, HalfEven), "1.23e5...");
+        assert_eq!(Rational::parse_decimal("-12.50").unwrap(), ratio(-25, 2));
+        assert_eq!(Rational::from_f64(0.1).unwrap(), ratio(1, 10));
+    }
+
+    #[test]
+    fn test_exact_modes() {
+        assert_eq!(calc("0.1 + 0.2"), (0.1 + 0.2).to_string());
+
+        let mut env = Environment::new();
+        run(&mut env, "mode rational").unwrap();
+        assert_eq!(run(&mut env, "0.1 + 0.2").unwrap(), "3/10");
+        assert_eq!(run(&mut env, "1/3 + 1/6").unwrap(), "1/2");
+        assert_eq!(run(&mut env, "(2/3)^-2").unwrap(), "9/4");
+        assert_eq!(run(&mut env, "2^100").unwrap(), "1267650600228229401496703205376");
+        assert!(run(&mut env, "sqrt(2)").unwrap_err().contains("irrational"));
+
+        run(&mut env, "mode decimal").unwrap();
+        assert_eq!(run(&mut env, "0.1 + 0.2").unwrap(), "0.3");
+        // Every result is rounded to the precision, so it is exact from then on
+        assert_eq!(run(&mut env, "1/3").unwrap(), "0.3333333333333333333333333333");
+        run(&mut env, "precision 4").unwrap();
+        run(&mut env, "rounding ceiling").unwrap();
+        assert_eq!(run(&mut env, "1/3").unwrap(), "0.3334");
+        run(&mut env, "format fraction").unwrap();
+        assert_eq!(run(&mut env, "0.25 * 3").unwrap(), "3/4");
+        assert!(run(&mut env, "rounding sideways").is_err());
+    }
+
+    #[test]
+    fn test_exact_size_limits() {
+        let mut env = Environment::new();
+        run(&mut env, "mode rational").unwrap();
+        assert!(run(&mut env, "x = 3^20000 / 7^9000").unwrap().starts_with("26613034272174197919"));
+        assert_eq!(run(&mut env, "x * 7^9000 - 3^20000").unwrap(), "0");
+        assert_eq!(run(&mut env, "(-2/3)^-3").unwrap(), "-27/8");
+
+        // 2^66000 has 19,868 digits and 2^67000 has 20,169
+        assert!(run(&mut env, "2^66000").is_ok());
+        assert!(run(&mut env, "2^67000").unwrap_err().contains("too large"));
+        assert!(run(&mut env, "(1/99999999999)^2000").unwrap_err().contains("too large"));
+        assert!(run(&mut env, "5000!").is_ok());
+        assert!(run(&mut env, "5001!").unwrap_err().contains("too large"));
+    }
+
+    #[test]
+    fn test_unit_lookup() {
+        assert_eq!(find_unit("km"), Some((1e3, [1, 0, 0, 0])));
+        assert_eq!(find_unit("GiB"), Some((1073741824.0, [0, 0, 0, 1])));
+        assert_eq!(find_unit("Mbps"), Some((125_000.0, [0, 0, -1, 1])));
+        assert_eq!(find_unit("ms"), Some((1e-3, [0, 0, 1, 0])));
+        // Prefixes only apply where they make sense
+        assert_eq!(find_unit("kh"), None);
+        assert_eq!(find_unit("Gim"), None);
+        assert_eq!(find_unit("parsec"), None);
+    }
+
+    #[test]
+    fn test_unit_conversion() {
+        assert_eq!(calc("40 km/h * 2.5 h"), "100 km");
+        assert_eq!(calc("3 GiB / 150 MB/s"), "21.47483648 s");
+        assert_eq!(calc("1 mi in km"), "1.609344 km");
+        assert_eq!(calc("100 km/h to m/s"), format!("{} m/s", 100_000.0 / 3600.0));
+        assert_eq!(calc("1 h in min"), "60 min");
+        assert_eq!(calc("10 m * 3 m"), "30 m^2");
+        assert_eq!(calc("sqrt(16 m^2)"), "4 m");
+        assert_eq!(calc("2 kB + 1 KiB"), "3.024 kB");
+        assert_eq!(calc("(6 m) / (2 m)"), "3");
+
+        let mut env = Environment::new();
+        run(&mut env, "speed = 90 km/h").unwrap();
+        assert_eq!(run(&mut env, "speed * 2 h in km").unwrap(), "180 km");
+    }
+
+    #[test]
+    fn test_incompatible_units() {
+        assert_eq!(calc_err("5 m + 3 s"), "Cannot add m and s");
+        assert_eq!(calc_err("5 m + 3"), "Cannot add m and a plain number");
+        assert_eq!(calc_err("5 m in s"), "Cannot convert m to s");
+        assert!(calc_err("sin(3 m)").contains("m"));
+
+        let mut env = Environment::new();
+        run(&mut env, "mode rational").unwrap();
+        assert_eq!(run(&mut env, "1 km + 1").unwrap_err(), "Units like km need 'mode float'");
+        assert_eq!(run(&mut env, "1 in m").unwrap_err(), "Unit conversion needs 'mode float'");
+    }
+
+    fn integer_env(settings: &[&str]) -> Environment {
+        let mut env = Environment::new();
+        for setting in ["mode integer"].iter().chain(settings) {
+            run(&mut env, setting).unwrap();
+        }
+        env
+    }
+
+    #[test]
+    fn test_integer_literals_and_operators() {
+        let mut env = integer_env(&[]);
+        assert_eq!(run(&mut env, "0xFF & 0b1010").unwrap(), "10");
+        assert_eq!(run(&mut env, "0o17 + 1_000_000").unwrap(), "1000015");
+        // C precedence: shifts bind tighter than &, then ^, then |
+        assert_eq!(run(&mut env, "1 << 4 | 1").unwrap(), "17");
+        assert_eq!(run(&mut env, "6 ^ 3 & 1").unwrap(), "7");
+        assert_eq!(run(&mut env, "~0").unwrap(), "-1");
+        assert_eq!(run(&mut env, "2 ** 10").unwrap(), "1024");
+        assert_eq!(run(&mut env, "-7 / 2").unwrap(), "-3");
+        assert_eq!(run(&mut env, "1.5").unwrap_err(), "Integer mode only accepts whole numbers, got 1.5");
+        assert_eq!(run(&mut env, "1 << 64").unwrap_err(), "Shift amount must be between 0 and 63, got 64");
+
+        assert_eq!(calc("0xFF & 0x0F"), "15");
+        assert_eq!(calc("6 xor 3"), "5");
+        assert_eq!(calc_err("0.5 & 1"), "Bitwise operators need whole numbers within 64 bits, got 0.5");
+        assert!(calc_err("2^63 | 0").contains("within 64 bits"));
+        assert!(whole_number(-(2f64.powi(62))).is_ok());
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        let mut env = integer_env(&["type u8"]);
+        assert_eq!(run(&mut env, "255 + 1").unwrap_err(), "Overflow: 256 doesn't fit in u8");
+        assert_eq!(run(&mut env, "0 - 1").unwrap_err(), "Overflow: -1 doesn't fit in u8");
+        assert_eq!(run(&mut env, "6!").unwrap_err(), "Overflow: 720 doesn't fit in u8");
+
+        let mut env = integer_env(&["type i64"]);
+        assert_eq!(run(&mut env, "9223372036854775807 + 1").unwrap_err(), "Overflow: 9223372036854775808 doesn't fit in i64");
+        assert_eq!(run(&mut env, "-9223372036854775807 - 1").unwrap(), "-9223372036854775808");
+    }
+
+    #[test]
+    fn test_integer_wrapping() {
+        let mut env = integer_env(&["type u8", "overflow wrap"]);
+        assert_eq!(run(&mut env, "255 + 1").unwrap(), "0");
+        assert_eq!(run(&mut env, "~0").unwrap(), "255");
+        assert_eq!(run(&mut env, "-1").unwrap(), "255");
+        assert_eq!(run(&mut env, "16 * 17").unwrap(), "16");
+        assert_eq!(run(&mut env, "20!").unwrap(), "0");
+
+        let mut env = integer_env(&["type i8", "overflow wrap"]);
+        assert_eq!(run(&mut env, "127 + 1").unwrap(), "-128");
+        assert_eq!(run(&mut env, "-128 - 1").unwrap(), "127");
+        assert_eq!(run(&mut env, "1 << 7").unwrap(), "-128");
+
+        let ty = IntegerType::parse("i16").unwrap();
+        assert_eq!(ty.wrap(40_000), 40_000 - 65_536);
+        assert_eq!(ty.fit(40_000, false), Err("Overflow: 40000 doesn't fit in i16".to_string()));
+        assert_eq!(IntegerType::parse("u128"), None);
+    }
+
+    #[test]
+    fn test_integer_output_bases() {
+        assert_eq!(format_integer(255, 16), "0xFF");
+        assert_eq!(format_integer(-10, 2), "-0b1010");
+        assert_eq!(format_integer(8, 8), "0o10");
+        assert_eq!(format_integer(35, 36), "Z (base 36)");
+        assert_eq!(format_integer(0, 2), "0b0");
+
+        // Integer mode shows the bit pattern of negative numbers
+        let mut env = integer_env(&["type i8", "base 16"]);
+        assert_eq!(run(&mut env, "-1").unwrap(), "0xFF");
+        run(&mut env, "mode float").unwrap();
+        assert_eq!(run(&mut env, "-1").unwrap(), "-0x1");
+        assert_eq!(run(&mut env, "0.5").unwrap(), "0.5");
+    }
+
+    fn parse_error(input: &str) -> CalcError {
+        match Calculator::new(input).and_then(|mut calc| calc.parse()) {
+            Ok(expr) => panic!("{} parsed as {}", input, expr),
+            Err(e) => e,
+        }
+    }
+
+    fn help(input: &str) -> String {
+        parse_error(input).help.unwrap_or_default()
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let e = parse_error("1.2.3");
+        assert_eq!(e.span, Some(Span { start: 0, end: 5 }));
+        assert_eq!(e.render("1.2.3").lines().nth(2), Some("  ^^^^^"));
+
+        let e = parse_error("2 + * 3");
+        assert_eq!(e.span, Some(Span { start: 4, end: 5 }));
+        let e = parse_error("1 + $");
+        assert_eq!(e.span, Some(Span { start: 4, end: 5 }));
+        assert!(e.message.contains('$'));
+    }
+
+    #[test]
+    fn test_error_suggestions() {
+        assert_eq!(help("2(3+4)"), "use '*' to multiply: 2*(3+4)");
+        assert_eq!(help("(1 + 2"), "add ')' at the end: (1 + 2)");
+        assert_eq!(help("((1 + 2"), "add 2 ')' at the end: ((1 + 2))");
+        assert_eq!(parse_error("1 + 2)").message, "Unmatched ')'");
+        assert_eq!(help("sqrt 4"), "call it as sqrt(4)");
+
+        // A comma only where another argument could start
+        assert_eq!(help("max(a b)"), "separate arguments with a comma: max(a, b)");
+        assert_eq!(help("max(-a b)"), "separate arguments with a comma: max(-a, b)");
+        assert_eq!(help("min(sqrt(2) 3)"), "separate arguments with a comma: min(sqrt(2), 3)");
+        assert_eq!(help("max((a + 1) b)"), "separate arguments with a comma: max((a + 1), b)");
+        assert_eq!(help("sin(2 x)"), "use '*' to multiply: sin(2*x)");
+        assert_eq!(help("sin(x y)"), "use '*' to multiply: sin(x*y)");
+        assert_eq!(help("sin(2(x + 1))"), "use '*' to multiply: sin(2*(x + 1))");
+        assert_eq!(help("max(1 + a b)"), "use '*' to multiply: max(1 + a*b)");
+        assert_eq!(help("max(a, 2^3 b)"), "use '*' to multiply: max(a, 2^3*b)");
+    }
+
+    fn edit(keys: &[u8], history: &[&str]) -> Option<String> {
+        let history: Vec<String> = history.iter().map(|entry| entry.to_string()).collect();
+        edit_line("calc> ", &history, &mut &keys[..], &mut Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn test_edit_line() {
+        assert_eq!(edit(b"1+2\r", &[]), Some("1+2".to_string()));
+        assert_eq!(edit(b"12\x7f3\r", &[]), Some("13".to_string()));
+        assert_eq!(edit(b"12\x083\r", &[]), Some("13".to_string()));
+        // Backspace at the start of the line is ignored, not inserted
+        assert_eq!(edit(b"\x7f\x7f1\x01\x7f\x08\r", &[]), Some("1".to_string()));
+        // Left arrow, then insert in the middle
+        assert_eq!(edit(b"13\x1b[D2\r", &[]), Some("123".to_string()));
+        assert_eq!(edit(b"\x1b[A\x1b[A\x7f9\r", &["1+1", "2*3"]), Some("1+9".to_string()));
+        assert_eq!(edit(b"x\x1b[A\x1b[B\r", &["1+1"]), Some("x".to_string()));
+        assert_eq!(edit(b"abc\x01\x0b\r", &[]), Some(String::new()));
+        assert_eq!(edit("π\r".as_bytes(), &[]), Some("π".to_string()));
+        assert_eq!(edit(b"\x04", &[]), None);
+        assert_eq!(edit(b"", &[]), None);
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        assert_eq!(parse("5x"), Expr::Number(5.0).times(Expr::Variable("x".to_string())));
+        assert_eq!(parse("3x^2").to_string(), "3*x^2");
+        assert_eq!(parse("10/2x").to_string(), "10/(2*x)");
+        assert_eq!(parse("-5x!").to_string(), "-(5*x!)");
+        assert_eq!(calc("2pi"), (2.0 * std::f64::consts::PI).to_string());
+        assert_eq!(calc("2sqrt(16)"), "8");
+        // Units still take precedence, and a space still needs an operator
+        assert_eq!(calc("5m"), "5 m");
+        assert_eq!(help("2 x"), "use '*' to multiply: 2*x");
+
+        // Scientific notation is a single number, not a coefficient times e
+        assert_eq!(calc("1e-3"), "0.001");
+        assert_eq!(calc("2e3"), "2000");
+        assert_eq!(calc("2.5e2"), "250");
+        assert_eq!(calc("1E+2 + 1"), "101");
+        assert_eq!(parse("2e3x"), Expr::Number(2000.0).times(Expr::Variable("x".to_string())));
+        assert_eq!(calc("2e"), (2.0 * std::f64::consts::E).to_string());
+        assert_eq!(calc("2e^2"), (2.0 * std::f64::consts::E.powi(2)).to_string());
+        assert!(parse_error("1_e3").message.contains("Invalid number"));
+        assert!(calc_err("1e400").contains("Invalid number"));
+        let mut env = Environment::new();
+        run(&mut env, "mode rational").unwrap();
+        assert_eq!(run(&mut env, "1e-3").unwrap(), "1/1000");
+    }
+
+    #[test]
+    fn test_solve_exact() {
+        assert_eq!(calc("solve(x^2 - 5x + 6 = 0, x)"), "x = 2; x = 3");
+        assert_eq!(calc("solve(2x = 4, x)"), "x = 2");
+        assert_eq!(calc("solve(3*(y - 1) = y, y)"), "y = 1.5");
+        assert_eq!(calc("solve(x^2 = 2, x)"), format!("x = -sqrt(2) = {}; x = sqrt(2) = {}", -2f64.sqrt(), 2f64.sqrt()));
+        assert_eq!(calc("solve(x^2 + x - 1 = 0, x)"), "x = -1/2 - sqrt(5)/2 = -1.618033988749895; x = -1/2 + sqrt(5)/2 = 0.6180339887498949");
+        assert_eq!(calc("solve(12x^2 = 1, x)"), "x = -sqrt(3)/6 = -0.28867513459481287; x = sqrt(3)/6 = 0.28867513459481287");
+        assert_eq!(calc("solve(x^2 = -1, x)"), "");
+        assert_eq!(calc_err("solve(x^2 = x^2, x)"), "Every value of x is a solution");
+
+        let mut env = Environment::new();
+        run(&mut env, "mode rational").unwrap();
+        assert_eq!(run(&mut env, "solve(3x = 1, x)").unwrap(), "x = 1/3");
+        assert_eq!(run(&mut env, "a = solve(3x = 1, x)").unwrap(), "1/3");
+        assert!(run(&mut env, "b = solve(x^2 = 4, x)").unwrap_err().contains("2 solutions"));
+        run(&mut env, "mode complex").unwrap();
+        assert_eq!(run(&mut env, "solve(x^2 + 4 = 0, x)").unwrap(), "x = -2i; x = 2i");
+        assert_eq!(run(&mut env, "solve(x^2 + 2 = 0, x)").unwrap(), format!("x = -sqrt(2)*i = -{}i; x = sqrt(2)*i = {}i", 2f64.sqrt(), 2f64.sqrt()));
+    }
+
+    #[test]
+    fn test_solve_numeric() {
+        let root = |input: &str| calc(input).trim_start_matches("x = ").parse::<f64>().unwrap();
+        assert!((root("solve(cos(x) = x, x, 0, 2)") - 0.7390851332151607).abs() < 1e-12);
+        assert!((root("solve(x^3 = 8, x)") - 2.0).abs() < 1e-12);
+        assert!((root("solve(exp(x) = 10, x)") - 10f64.ln()).abs() < 1e-12);
+
+        let roots: Vec<f64> = calc("solve(sin(x) = 0, x, -4, 4)")
+            .split("; ")
+            .map(|root| root.trim_start_matches("x = ").parse().unwrap())
+            .collect();
+        assert_eq!(roots.len(), 3);
+        for (root, expected) in roots.iter().zip([-std::f64::consts::PI, 0.0, std::f64::consts::PI]) {
+            assert!((root - expected).abs() < 1e-12, "{} vs {}", root, expected);
+        }
+
+        let rate: Vec<f64> = calc("solve(1000*(1+r)^10 = 2000, r)")
+            .split("; ")
+            .map(|root| root.trim_start_matches("r = ").parse().unwrap())
+            .collect();
+        assert!(rate.iter().any(|r| (r - (2f64.powf(0.1) - 1.0)).abs() < 1e-12));
+
+        assert_eq!(calc_err("solve(x = 1, x, 2, 1)"), "solve(): the interval 2 to 1 is empty");
+        assert!(calc_err("solve(x = 1, 2)").contains("expects a variable"));
+        assert_eq!(calc_err("1 + solve(x = 1, x)"), SOLVE_ALONE);
+    }
+
+    #[test]
+    fn test_complex_display() {
+        assert_eq!(Complex::new(2.0, 0.0).to_string(), "2");
+        assert_eq!(Complex::new(0.0, 1.0).to_string(), "i");
+        assert_eq!(Complex::new(0.0, -1.0).to_string(), "-i");
+        assert_eq!(Complex::new(-0.0, 2.5).to_string(), "2.5i");
+        assert_eq!(Complex::new(3.0, -4.0).to_string(), "3 - 4i");
+        assert_eq!(Complex::new(3.0, 1.0).to_string(), "3 + i");
+        assert_eq!(Complex::new(-1.0, 1.2e-16).to_string(), "-1");
+        assert_eq!(Complex::new(0.0, 2.0).to_polar_string(), format!("2*exp({}i)", std::f64::consts::FRAC_PI_2));
+        assert_eq!(Complex::new(0.0, 0.0).to_polar_string(), "0");
+    }
+
+    #[test]
+    fn test_complex_mode() {
+        let mut env = Environment::new();
+        assert_eq!(run(&mut env, "sqrt(-1)").unwrap_err(), "sqrt(): argument must not be negative");
+        run(&mut env, "mode complex").unwrap();
+        assert_eq!(run(&mut env, "sqrt(-1)").unwrap(), "i");
+        assert_eq!(run(&mut env, "(1 + i)^2").unwrap(), "2i");
+        assert_eq!(run(&mut env, "(3 + 4i)*(3 - 4i)").unwrap(), "25");
+        assert_eq!(run(&mut env, "exp(i*pi)").unwrap(), "-1");
+        assert_eq!(run(&mut env, "abs(3 + 4i)").unwrap(), "5");
+        assert_eq!(run(&mut env, "conj(1 + 2i)").unwrap(), "1 - 2i");
+        assert_eq!(run(&mut env, "re(ln(-1)) + im(ln(-1))").unwrap(), std::f64::consts::PI.to_string());
+        assert_eq!(run(&mut env, "1/(0i)").unwrap_err(), "Division by zero");
+        run(&mut env, "format polar").unwrap();
+        assert_eq!(run(&mut env, "1 + i").unwrap(), format!("{}*exp({}i)", 2f64.sqrt(), std::f64::consts::FRAC_PI_4));
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        assert_eq!(calc_err("1 / 0"), "Division by zero");
+        assert!(calc_err("sqrt(-1)").contains("sqrt"));
+        assert_eq!(calc_err("foo(2)"), "Missing operator before '('");
+        assert!(calc_err("1 +").contains("Unexpected end of input"));
+        assert!(calc_err("(1 + 2").contains("closing parenthesis"));
     }
-}
\ No newline at end of file
+}
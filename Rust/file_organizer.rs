@@ -1,254 +1,3817 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymlinkPolicy {
+    Skip,
+    Follow,
+    MoveLink,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TargetLayout {
+    Flatten,
+    PreserveSubpaths,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CollisionPolicy {
+    Skip,
+    RenameNumbered,
+    RenameTimestamp,
+    OverwriteIfNewer,
+    DedupeIdentical,
+}
+
+impl CollisionPolicy {
+    fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "skip" => Some(CollisionPolicy::Skip),
+            "number" | "numbered" => Some(CollisionPolicy::RenameNumbered),
+            "timestamp" => Some(CollisionPolicy::RenameTimestamp),
+            "newer" | "overwrite-if-newer" => Some(CollisionPolicy::OverwriteIfNewer),
+            "dedupe" => Some(CollisionPolicy::DedupeIdentical),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            CollisionPolicy::Skip => "skip the incoming file",
+            CollisionPolicy::RenameNumbered => "rename with a numeric suffix",
+            CollisionPolicy::RenameTimestamp => "rename with the file's timestamp",
+            CollisionPolicy::OverwriteIfNewer => "overwrite if the incoming file is newer",
+            CollisionPolicy::DedupeIdentical => "drop identical duplicates, rename the rest",
+        }
+    }
+}
+
+enum CollisionOutcome {
+    Move(PathBuf),
+    Skipped(String),
+    Deduplicated,
+}
+
+enum MoveOutcome {
+    Moved(PathBuf),
+    Skipped(String),
+    Deduplicated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DuplicateAction {
+    Ignore,
+    Delete,
+    HardLink,
+    Quarantine,
+}
+
+impl DuplicateAction {
+    fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "ignore" | "none" => Some(DuplicateAction::Ignore),
+            "delete" => Some(DuplicateAction::Delete),
+            "hardlink" | "link" => Some(DuplicateAction::HardLink),
+            "quarantine" => Some(DuplicateAction::Quarantine),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransferMode {
+    Move,
+    // Organize copies and leave the originals untouched
+    Copy,
+}
+
+impl TransferMode {
+    fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "move" => Some(TransferMode::Move),
+            "copy" => Some(TransferMode::Copy),
+            _ => None,
+        }
+    }
+
+    fn past_tense(&self) -> &'static str {
+        match self {
+            TransferMode::Move => "Moved",
+            TransferMode::Copy => "Copied",
+        }
+    }
+}
+
+const QUARANTINE_CATEGORY: &str = "Duplicates";
+const PARTIAL_HASH_BYTES: u64 = 4096;
+
+#[derive(Debug, Clone)]
+struct DuplicateSet {
+    size: u64,
+    // The first file is the one kept; the rest are its duplicates
+    files: Vec<PathBuf>,
+}
+
+impl DuplicateSet {
+    fn wasted_bytes(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+// DefaultHasher's algorithm isn't guaranteed and 64 bits can collide, so
+// this only narrows candidates; anything acted on is compared byte for byte.
+fn hash_file(path: &Path, limit: Option<u64>) -> io::Result<u64> {
+    use std::hash::Hasher;
+
+    let file = fs::File::open(path)?;
+    let mut reader: Box<dyn io::Read> = match limit {
+        Some(limit) => Box::new(io::Read::take(file, limit)),
+        None => Box::new(file),
+    };
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    let mut buffer = [0u8; 65536];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+// Narrow candidates by size, then a hash of the first few KiB, then a full
+// content hash. Empty files are left to the statistics report.
+fn find_duplicates(files: &[PathBuf]) -> Vec<DuplicateSet> {
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for path in files {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if metadata.is_file() && metadata.len() > 0 {
+                by_size.entry(metadata.len()).or_default().push(path.clone());
+            }
+        }
+    }
+
+    let mut sets = Vec::new();
+    for (size, candidates) in by_size {
+        if candidates.len() < 2 {
+            continue;
+        }
+
+        for partial_group in group_by_hash(candidates, Some(PARTIAL_HASH_BYTES)) {
+            // Files no larger than the partial window are already fully hashed
+            let full_groups = if size <= PARTIAL_HASH_BYTES {
+                vec![partial_group]
+            } else {
+                group_by_hash(partial_group, None)
+            };
+
+            for mut group in full_groups {
+                group.sort_by_key(|path| {
+                    let modified = fs::metadata(path).map(|m| modified_stamp(&m)).unwrap_or(u128::MAX);
+                    (modified, path.as_os_str().len(), path.clone())
+                });
+                sets.push(DuplicateSet { size, files: group });
+            }
+        }
+    }
+
+    sets.sort_by(|a, b| b.wasted_bytes().cmp(&a.wasted_bytes()).then_with(|| a.files.cmp(&b.files)));
+    sets
+}
+
+fn group_by_hash(paths: Vec<PathBuf>, limit: Option<u64>) -> Vec<Vec<PathBuf>> {
+    let mut groups: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for path in paths {
+        if let Ok(hash) = hash_file(&path, limit) {
+            groups.entry(hash).or_default().push(path);
+        }
+    }
+    groups.into_values().filter(|group| group.len() > 1).collect()
+}
+
+#[derive(Debug, Clone)]
+struct ScanOptions {
+    recursive: bool,
+    max_depth: Option<usize>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    include_hidden: bool,
+    symlinks: SymlinkPolicy,
+    layout: TargetLayout,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            recursive: false,
+            max_depth: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_hidden: true,
+            symlinks: SymlinkPolicy::MoveLink,
+            layout: TargetLayout::Flatten,
+        }
+    }
+}
+
+/// Shell-style glob match supporting `*`, `**`, `?` and `[...]` classes.
+/// A single `*` stops at `/`; `**` may span directory separators.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_from(&pattern, &text)
+}
+
+fn glob_match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            if pattern.get(1) == Some(&'*') {
+                let mut rest = &pattern[2..];
+                // "**/" also matches zero directories
+                if rest.first() == Some(&'/') && glob_match_from(&rest[1..], text) {
+                    return true;
+                }
+                rest = &pattern[2..];
+                (0..=text.len()).any(|i| glob_match_from(rest, &text[i..]))
+            } else {
+                let rest = &pattern[1..];
+                for i in 0..=text.len() {
+                    if glob_match_from(rest, &text[i..]) {
+                        return true;
+                    }
+                    if i < text.len() && text[i] == '/' {
+                        break;
+                    }
+                }
+                false
+            }
+        }
+        Some('?') => !text.is_empty() && text[0] != '/' && glob_match_from(&pattern[1..], &text[1..]),
+        Some('[') => {
+            let Some(&ch) = text.first() else {
+                return false;
+            };
+            match match_char_class(&pattern[1..], ch) {
+                Some((matched, consumed)) => matched && glob_match_from(&pattern[1 + consumed..], &text[1..]),
+                // Unterminated class: treat '[' literally
+                None => ch == '[' && glob_match_from(&pattern[1..], &text[1..]),
+            }
+        }
+        Some(&p) => !text.is_empty() && text[0] == p && glob_match_from(&pattern[1..], &text[1..]),
+    }
+}
+
+// Returns whether `ch` is in the class and how many pattern chars it spans
+// (including the closing bracket), or None if the class never closes.
+fn match_char_class(class: &[char], ch: char) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let negated = matches!(class.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < class.len() {
+        let c = class[i];
+        if c == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        if i + 2 < class.len() && class[i + 1] == '-' && class[i + 2] != ']' {
+            if class[i] <= ch && ch <= class[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if c == ch {
+                matched = true;
+            }
+            i += 1;
+        }
+        first = false;
+    }
+
+    None
+}
+
+// "report.pdf" -> "report_1.pdf", "report_2.pdf", ... until the name is free
+fn numbered_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+    (1..)
+        .map(|n| path.with_file_name(format!("{}_{}{}", stem, n, extension)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .unwrap()
+}
+
+fn timestamped_path(path: &Path, metadata: &fs::Metadata) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+    let secs = (modified_stamp(metadata) / 1_000_000_000) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let time_of_day = secs.rem_euclid(86_400);
+    let candidate = path.with_file_name(format!(
+        "{}_{:04}{:02}{:02}-{:02}{:02}{:02}{}",
+        stem, year, month, day,
+        time_of_day / 3600, time_of_day % 3600 / 60, time_of_day % 60,
+        extension
+    ));
+
+    if fs::symlink_metadata(&candidate).is_err() {
+        candidate
+    } else {
+        numbered_path(&candidate)
+    }
+}
+
+fn files_identical(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let mut file_a = io::BufReader::new(fs::File::open(a)?);
+    let mut file_b = io::BufReader::new(fs::File::open(b)?);
+    let mut buf_a = [0u8; 8192];
+    let mut buf_b = [0u8; 8192];
+
+    loop {
+        let read = io::Read::read(&mut file_a, &mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        io::Read::read_exact(&mut file_b, &mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+// Renames when possible; across filesystems the file is copied, verified and
+// only then removed from the source. If the source can't be removed, the copy
+// is deleted again so the file isn't left in two places with no journal entry.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_verified(from, to)?;
+            fs::remove_file(from).inspect_err(|_| {
+                let _ = fs::remove_file(to);
+            })
+        }
+        result => result,
+    }
+}
+
+// Copies contents, timestamps and permissions, then compares size and
+// contents byte for byte. A copy that fails verification is removed again. Symlinks are
+// recreated rather than followed.
+fn copy_verified(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.file_type().is_symlink() {
+        return copy_symlink(from, to);
+    }
+
+    let mut source = fs::File::open(from)?;
+    let mut copy = fs::OpenOptions::new().write(true).create_new(true).open(to)?;
+    let result = write_copy(&mut source, &mut copy, &metadata)
+        .and_then(|_| fs::set_permissions(to, metadata.permissions()))
+        .and_then(|_| verify_copy(from, to, metadata.len()));
+
+    if result.is_err() {
+        let _ = fs::remove_file(to);
+    }
+    result
+}
+
+fn write_copy(source: &mut fs::File, copy: &mut fs::File, metadata: &fs::Metadata) -> io::Result<()> {
+    io::copy(source, copy)?;
+
+    let mut times = fs::FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    copy.set_times(times)?;
+    copy.sync_all()
+}
+
+fn verify_copy(from: &Path, to: &Path, expected_len: u64) -> io::Result<()> {
+    let copied_len = fs::metadata(to)?.len();
+    if copied_len != expected_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("copy of {} has {} bytes, expected {}", from.display(), copied_len, expected_len),
+        ));
+    }
+    if !files_identical(from, to)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("contents of {} don't match its copy", from.display()),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("cannot copy symlink {} to another filesystem", from.display()),
+    ))
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+// ---------------------------------------------------------------------------
+// Minimal backtracking regex used by filename rules. Supports literals, `.`,
+// classes (`[a-z]`, `[^0-9]`, `\d`, `\w`, `\s`), anchors, groups,
+// alternation and the usual greedy/lazy quantifiers. A leading `(?i)`
+// makes the whole pattern case-insensitive.
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+enum RegexNode {
+    Char(char),
+    Any,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Start,
+    End,
+    Concat(Vec<RegexNode>),
+    Alternate(Vec<RegexNode>),
+    Repeat { node: Box<RegexNode>, min: usize, max: Option<usize>, greedy: bool },
+}
+
+#[derive(Debug, Clone)]
+struct Regex {
+    root: RegexNode,
+    case_insensitive: bool,
+}
+
+struct RegexParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    source: &'a str,
+}
+
+impl<'a> RegexParser<'a> {
+    fn error(&self, message: &str) -> String {
+        format!("Invalid regex '{}' at position {}: {}", self.source, self.pos, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn parse_alternation(&mut self) -> Result<RegexNode, String> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.parse_concat()?);
+        }
+
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { RegexNode::Alternate(branches) })
+    }
+
+    fn parse_concat(&mut self) -> Result<RegexNode, String> {
+        let mut nodes = Vec::new();
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(RegexNode::Concat(nodes))
+    }
+
+    fn parse_quantifier(&mut self, atom: RegexNode) -> Result<RegexNode, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let close = self.chars[self.pos..].iter().position(|&c| c == '}')
+                    .ok_or_else(|| self.error("unterminated '{'"))?;
+                let body: String = self.chars[self.pos + 1..self.pos + close].iter().collect();
+                let bounds = match body.split_once(',') {
+                    None => {
+                        let n = body.trim().parse().map_err(|_| self.error("bad repetition count"))?;
+                        (n, Some(n))
+                    }
+                    Some((lo, hi)) => {
+                        let lo = lo.trim().parse().map_err(|_| self.error("bad repetition count"))?;
+                        let hi = if hi.trim().is_empty() {
+                            None
+                        } else {
+                            Some(hi.trim().parse().map_err(|_| self.error("bad repetition count"))?)
+                        };
+                        (lo, hi)
+                    }
+                };
+                self.pos += close;
+                bounds
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+
+        if matches!(atom, RegexNode::Start | RegexNode::End) {
+            return Err(self.error("nothing to repeat"));
+        }
+
+        let greedy = if self.peek() == Some('?') {
+            self.pos += 1;
+            false
+        } else {
+            true
+        };
+
+        Ok(RegexNode::Repeat { node: Box::new(atom), min, max, greedy })
+    }
+
+    fn parse_atom(&mut self) -> Result<RegexNode, String> {
+        let ch = self.peek().ok_or_else(|| self.error("unexpected end of pattern"))?;
+        self.pos += 1;
+
+        match ch {
+            '.' => Ok(RegexNode::Any),
+            '^' => Ok(RegexNode::Start),
+            '$' => Ok(RegexNode::End),
+            '(' => {
+                // Non-capturing groups behave the same as plain ones here
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let inner = self.parse_alternation()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("missing ')'"));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            '[' => self.parse_class(),
+            '\\' => self.parse_escape(),
+            '*' | '+' | '?' | '{' => Err(self.error("nothing to repeat")),
+            _ => Ok(RegexNode::Char(ch)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<RegexNode, String> {
+        let ch = self.peek().ok_or_else(|| self.error("trailing backslash"))?;
+        self.pos += 1;
+
+        Ok(match Self::class_escape(ch) {
+            Some((ranges, negated)) => RegexNode::Class { ranges, negated },
+            None => RegexNode::Char(Self::literal_escape(ch)),
+        })
+    }
+
+    fn class_escape(ch: char) -> Option<(Vec<(char, char)>, bool)> {
+        let digits = vec![('0', '9')];
+        let word = vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+        let space = vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')];
+
+        match ch {
+            'd' => Some((digits, false)),
+            'D' => Some((digits, true)),
+            'w' => Some((word, false)),
+            'W' => Some((word, true)),
+            's' => Some((space, false)),
+            'S' => Some((space, true)),
+            _ => None,
+        }
+    }
+
+    fn literal_escape(ch: char) -> char {
+        match ch {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            other => other,
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<RegexNode, String> {
+        let mut ranges = Vec::new();
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut first = true;
+        loop {
+            let ch = self.peek().ok_or_else(|| self.error("missing ']'"))?;
+            self.pos += 1;
+
+            if ch == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let start = if ch == '\\' {
+                let escaped = self.peek().ok_or_else(|| self.error("trailing backslash"))?;
+                self.pos += 1;
+                if let Some((class_ranges, false)) = Self::class_escape(escaped) {
+                    ranges.extend(class_ranges);
+                    continue;
+                }
+                Self::literal_escape(escaped)
+            } else {
+                ch
+            };
+
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                let end = self.chars[self.pos + 1];
+                self.pos += 2;
+                if end < start {
+                    return Err(self.error("reversed character range"));
+                }
+                ranges.push((start, end));
+            } else {
+                ranges.push((start, start));
+            }
+        }
+
+        Ok(RegexNode::Class { ranges, negated })
+    }
+}
+
+impl Regex {
+    fn new(pattern: &str) -> Result<Self, String> {
+        let (body, case_insensitive) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (rest, true),
+            None => (pattern, false),
+        };
+
+        let mut parser = RegexParser { chars: body.chars().collect(), pos: 0, source: pattern };
+        let root = parser.parse_alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unbalanced ')'"));
+        }
+
+        Ok(Regex { root, case_insensitive })
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = if self.case_insensitive {
+            text.to_lowercase().chars().collect()
+        } else {
+            text.chars().collect()
+        };
+
+        (0..=text.len()).any(|start| self.match_node(&self.root, &text, start, &mut |_| true))
+    }
+
+    fn char_eq(&self, a: char, b: char) -> bool {
+        if self.case_insensitive {
+            a.to_lowercase().eq(b.to_lowercase())
+        } else {
+            a == b
+        }
+    }
+
+    fn match_node(&self, node: &RegexNode, text: &[char], pos: usize, next: &mut dyn FnMut(usize) -> bool) -> bool {
+        match node {
+            RegexNode::Char(c) => pos < text.len() && self.char_eq(text[pos], *c) && next(pos + 1),
+            RegexNode::Any => pos < text.len() && text[pos] != '\n' && next(pos + 1),
+            RegexNode::Class { ranges, negated } => {
+                if pos >= text.len() {
+                    return false;
+                }
+                let ch = text[pos];
+                let in_class = ranges.iter().any(|&(lo, hi)| {
+                    (lo <= ch && ch <= hi)
+                        || (self.case_insensitive && ch.to_uppercase().any(|u| lo <= u && u <= hi))
+                });
+                in_class != *negated && next(pos + 1)
+            }
+            RegexNode::Start => pos == 0 && next(pos),
+            RegexNode::End => pos == text.len() && next(pos),
+            RegexNode::Concat(nodes) => self.match_sequence(nodes, text, pos, next),
+            RegexNode::Alternate(branches) => branches.iter().any(|branch| self.match_node(branch, text, pos, next)),
+            RegexNode::Repeat { node, min, max, greedy } => {
+                self.match_repeat(node, *min, *max, *greedy, 0, text, pos, next)
+            }
+        }
+    }
+
+    fn match_sequence(&self, nodes: &[RegexNode], text: &[char], pos: usize, next: &mut dyn FnMut(usize) -> bool) -> bool {
+        match nodes.split_first() {
+            None => next(pos),
+            Some((first, rest)) => {
+                self.match_node(first, text, pos, &mut |p| self.match_sequence(rest, text, p, next))
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn match_repeat(
+        &self,
+        node: &RegexNode,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+        count: usize,
+        text: &[char],
+        pos: usize,
+        next: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        let can_stop = count >= min;
+        let can_continue = match max {
+            Some(max) => count < max,
+            None => true,
+        };
+
+        let more = |next: &mut dyn FnMut(usize) -> bool| {
+            can_continue
+                && self.match_node(node, text, pos, &mut |p| {
+                    // Zero-width iterations can't make progress
+                    p != pos && self.match_repeat(node, min, max, greedy, count + 1, text, p, next)
+                })
+        };
+
+        // Greedy repeats try another iteration first, lazy ones try stopping
+        if greedy {
+            if more(next) {
+                return true;
+            }
+            can_stop && next(pos)
+        } else {
+            if can_stop && next(pos) {
+                return true;
+            }
+            more(next)
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Configuration files use a small TOML subset: `[table]` and `[[array]]`
+// headers, `key = value` pairs with strings, integers, booleans and arrays,
+// and `#` comments.
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum ConfigValue {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<ConfigValue>),
+}
+
+#[derive(Debug, Clone, Default)]
+struct ConfigTable {
+    name: String,
+    is_array_item: bool,
+    entries: Vec<(String, ConfigValue)>,
+}
+
+impl ConfigTable {
+    fn get(&self, key: &str) -> Option<&ConfigValue> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    fn get_str(&self, key: &str) -> Result<Option<&str>, String> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(ConfigValue::Str(s)) => Ok(Some(s)),
+            Some(_) => Err(format!("[{}] '{}' must be a string", self.name, key)),
+        }
+    }
+
+    // Accepts either a single string or an array of strings
+    fn get_str_list(&self, key: &str) -> Result<Vec<String>, String> {
+        match self.get(key) {
+            None => Ok(Vec::new()),
+            Some(ConfigValue::Str(s)) => Ok(vec![s.clone()]),
+            Some(ConfigValue::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    ConfigValue::Str(s) => Ok(s.clone()),
+                    _ => Err(format!("[{}] '{}' must contain only strings", self.name, key)),
+                })
+                .collect(),
+            Some(_) => Err(format!("[{}] '{}' must be a string or array of strings", self.name, key)),
+        }
+    }
+}
+
+fn parse_config(text: &str) -> Result<Vec<ConfigTable>, String> {
+    let mut tables = vec![ConfigTable::default()];
+    let mut pending = String::new();
+    let mut pending_line = 0;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = strip_config_comment(raw_line);
+        let line = line.trim();
+
+        if pending.is_empty() {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+                tables.push(ConfigTable { name: name.trim().to_string(), is_array_item: true, entries: Vec::new() });
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                tables.push(ConfigTable { name: name.trim().to_string(), is_array_item: false, entries: Vec::new() });
+                continue;
+            }
+            pending_line = line_no;
+        }
+
+        // Arrays may span several lines; keep reading until brackets balance
+        pending.push_str(line);
+        pending.push(' ');
+        if bracket_depth(&pending) > 0 {
+            continue;
+        }
+
+        let statement = std::mem::take(&mut pending);
+        let (key, value) = statement
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected 'key = value'", pending_line))?;
+        let key = key.trim().trim_matches('"').to_string();
+        if key.is_empty() {
+            return Err(format!("line {}: missing key", pending_line));
+        }
+
+        let mut chars = value.trim().chars().peekable();
+        let value = parse_config_value(&mut chars).map_err(|e| format!("line {}: {}", pending_line, e))?;
+        if chars.any(|c| !c.is_whitespace()) {
+            return Err(format!("line {}: unexpected text after value", pending_line));
+        }
+
+        tables.last_mut().unwrap().entries.push((key, value));
+    }
+
+    if !pending.is_empty() {
+        return Err(format!("line {}: unterminated array", pending_line));
+    }
+
+    Ok(tables)
+}
+
+fn strip_config_comment(line: &str) -> &str {
+    let mut in_string: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, ch) in line.char_indices() {
+        match in_string {
+            Some(quote) => {
+                if escaped {
+                    escaped = false;
+                } else if ch == '\\' && quote == '"' {
+                    escaped = true;
+                } else if ch == quote {
+                    in_string = None;
+                }
+            }
+            None if ch == '"' || ch == '\'' => in_string = Some(ch),
+            None if ch == '#' => return &line[..i],
+            None => {}
+        }
+    }
+
+    line
+}
+
+fn bracket_depth(text: &str) -> i32 {
+    let mut depth = 0;
+    let mut in_string: Option<char> = None;
+    let mut escaped = false;
+
+    for ch in text.chars() {
+        match in_string {
+            Some(quote) => {
+                if escaped {
+                    escaped = false;
+                } else if ch == '\\' && quote == '"' {
+                    escaped = true;
+                } else if ch == quote {
+                    in_string = None;
+                }
+            }
+            None => match ch {
+                '"' | '\'' => in_string = Some(ch),
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+
+    depth
+}
+
+fn parse_config_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<ConfigValue, String> {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+
+    match chars.peek().copied() {
+        Some('"') => {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(ConfigValue::Str(value)),
+                    Some('\\') => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('"') => value.push('"'),
+                        Some('\\') => value.push('\\'),
+                        Some(other) => return Err(format!("unknown escape '\\{}'", other)),
+                        None => return Err("unterminated string".to_string()),
+                    },
+                    Some(ch) => value.push(ch),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+        }
+        Some('\'') => {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('\'') => return Ok(ConfigValue::Str(value)),
+                    Some(ch) => value.push(ch),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ',') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Ok(ConfigValue::Array(items));
+                }
+                if chars.peek().is_none() {
+                    return Err("unterminated array".to_string());
+                }
+                items.push(parse_config_value(chars)?);
+            }
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(&ch) = chars.peek() {
+                if ch == ',' || ch == ']' || ch.is_whitespace() {
+                    break;
+                }
+                word.push(ch);
+                chars.next();
+            }
+
+            match word.as_str() {
+                "true" => Ok(ConfigValue::Bool(true)),
+                "false" => Ok(ConfigValue::Bool(false)),
+                _ => word.replace('_', "").parse::<i64>()
+                    .map(ConfigValue::Int)
+                    .map_err(|_| format!("invalid value '{}'", word)),
+            }
+        }
+        None => Err("missing value".to_string()),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Calendar helpers (proleptic Gregorian, days relative to 1970-01-01)
+// ---------------------------------------------------------------------------
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn parse_date(text: &str) -> Result<i64, String> {
+    let parts: Vec<&str> = text.trim().split('-').collect();
+    let invalid = || format!("Invalid date '{}', expected YYYY-MM-DD", text);
+    if parts.len() != 3 {
+        return Err(invalid());
+    }
+
+    let year: i64 = parts[0].parse().map_err(|_| invalid())?;
+    let month: u32 = parts[1].parse().map_err(|_| invalid())?;
+    let day: u32 = parts[2].parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+
+    Ok(days_from_civil(year, month, day))
+}
+
+fn modified_days(metadata: &fs::Metadata) -> Option<i64> {
+    let modified = metadata.modified().ok()?;
+    let secs = match modified.duration_since(std::time::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    Some(secs.div_euclid(86_400))
+}
+
+fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("Invalid size '{}'", text))?;
+
+    let multiplier = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1u64,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        other => return Err(format!("Unknown size unit '{}'", other)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+fn config_size(table: &ConfigTable, key: &str) -> Result<Option<u64>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(ConfigValue::Int(n)) if *n >= 0 => Ok(Some(*n as u64)),
+        Some(ConfigValue::Str(s)) => parse_size(s).map(Some),
+        Some(_) => Err(format!("[{}] '{}' must be a size like 1048576 or \"1MB\"", table.name, key)),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Content sniffing: identify common formats from their magic bytes so
+// extensionless and misnamed files can still be categorized.
+// ---------------------------------------------------------------------------
+
+const SNIFF_BYTES: usize = 8192;
+
+// MIME type, category, and the extensions that legitimately carry it
+const MIME_TYPES: &[(&str, &str, &[&str])] = &[
+    ("image/png", "Images", &["png"]),
+    ("image/jpeg", "Images", &["jpg", "jpeg", "jpe", "jfif"]),
+    ("image/gif", "Images", &["gif"]),
+    ("image/bmp", "Images", &["bmp", "dib"]),
+    ("image/heic", "Images", &["heic", "heif"]),
+    ("image/heif", "Images", &["heif", "heic"]),
+    ("image/avif", "Images", &["avif"]),
+    ("application/pdf", "Documents", &["pdf"]),
+    ("application/vnd.openxmlformats-officedocument.wordprocessingml.document", "Documents", &["docx", "docm"]),
+    ("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", "Documents", &["xlsx", "xlsm"]),
+    ("application/vnd.openxmlformats-officedocument.presentationml.presentation", "Documents", &["pptx", "pptm"]),
+    ("application/zip", "Archives", &["zip", "jar", "apk", "epub", "odt", "ods", "odp", "whl"]),
+    ("application/gzip", "Archives", &["gz", "tgz"]),
+    ("application/x-7z-compressed", "Archives", &["7z"]),
+    ("application/vnd.rar", "Archives", &["rar"]),
+    ("application/x-elf", "Programs", &["so", "o", "elf", "bin", "run"]),
+    ("application/x-msdownload", "Programs", &["exe", "dll", "sys"]),
+    ("audio/mpeg", "Audio", &["mp3"]),
+    ("audio/wav", "Audio", &["wav"]),
+    ("audio/ogg", "Audio", &["ogg", "oga", "opus"]),
+    ("audio/flac", "Audio", &["flac"]),
+    ("audio/mp4", "Audio", &["m4a", "m4b", "aac"]),
+    ("video/mp4", "Videos", &["mp4", "m4v"]),
+    ("video/quicktime", "Videos", &["mov", "qt"]),
+    ("video/x-msvideo", "Videos", &["avi"]),
+];
+
+fn sniff_mime(path: &Path) -> Option<&'static str> {
+    let mut header = Vec::with_capacity(SNIFF_BYTES);
+    let file = fs::File::open(path).ok()?;
+    io::Read::read_to_end(&mut io::Read::take(file, SNIFF_BYTES as u64), &mut header).ok()?;
+    sniff_bytes(&header)
+}
+
+fn sniff_bytes(header: &[u8]) -> Option<&'static str> {
+    let contains = |needle: &[u8]| header.windows(needle.len()).any(|w| w == needle);
+
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if header.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if header.starts_with(b"BM") && header.len() >= 14 && header[6..10] == [0, 0, 0, 0] {
+        Some("image/bmp")
+    } else if header.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if header.starts_with(b"PK\x03\x04") {
+        // OOXML documents are ZIPs whose entries live under a known folder
+        if contains(b"word/") {
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+        } else if contains(b"xl/") {
+            Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+        } else if contains(b"ppt/") {
+            Some("application/vnd.openxmlformats-officedocument.presentationml.presentation")
+        } else {
+            Some("application/zip")
+        }
+    } else if header.starts_with(b"\x1f\x8b") {
+        Some("application/gzip")
+    } else if header.starts_with(b"7z\xbc\xaf\x27\x1c") {
+        Some("application/x-7z-compressed")
+    } else if header.starts_with(b"Rar!\x1a\x07") {
+        Some("application/vnd.rar")
+    } else if header.starts_with(b"\x7fELF") {
+        Some("application/x-elf")
+    } else if header.starts_with(b"MZ") && header.len() >= 64 {
+        Some("application/x-msdownload")
+    } else if header.starts_with(b"ID3")
+        || (header.len() >= 2 && header[0] == 0xff && matches!(header[1], 0xfb | 0xf3 | 0xf2))
+    {
+        Some("audio/mpeg")
+    } else if header.starts_with(b"RIFF") && header.len() >= 12 && &header[8..12] == b"WAVE" {
+        Some("audio/wav")
+    } else if header.starts_with(b"RIFF") && header.len() >= 12 && &header[8..12] == b"AVI " {
+        Some("video/x-msvideo")
+    } else if header.starts_with(b"OggS") {
+        Some("audio/ogg")
+    } else if header.starts_with(b"fLaC") {
+        Some("audio/flac")
+    } else if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        // ISO media files share one container; the major brand says what's inside
+        match &header[8..12] {
+            b"M4A " | b"M4B " => Some("audio/mp4"),
+            b"qt  " => Some("video/quicktime"),
+            b"heic" | b"heix" | b"hevc" | b"hevx" => Some("image/heic"),
+            b"mif1" | b"msf1" => Some("image/heif"),
+            b"avif" | b"avis" => Some("image/avif"),
+            b"isom" | b"iso2" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42" | b"mmp4" | b"avc1" | b"dash"
+            | b"M4V " | b"M4VH" | b"M4VP" | b"f4v " => Some("video/mp4"),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+fn mime_info(mime: &str) -> Option<(&'static str, &'static [&'static str])> {
+    MIME_TYPES
+        .iter()
+        .find(|(name, _, _)| *name == mime)
+        .map(|&(_, category, extensions)| (category, extensions))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ContentDetection {
+    Off,
+    // Only consult content when the extension is unknown or missing
+    Fallback,
+    // Trust content over the extension whenever it's recognized
+    Override,
+}
+
+// ---------------------------------------------------------------------------
+// Rule-based organization
+// ---------------------------------------------------------------------------
+
+const DEFAULT_TARGET_TEMPLATE: &str = "{category}/{name}";
+
+#[derive(Debug, Clone)]
+struct Rule {
+    name: String,
+    extensions: Vec<String>,
+    name_pattern: Option<Regex>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<i64>,
+    modified_before: Option<i64>,
+    mime: Option<String>,
+    category: String,
+    target: String,
+}
+
+impl Rule {
+    fn from_table(table: &ConfigTable, index: usize) -> Result<Self, String> {
+        let name = table.get_str("name")?.map(str::to_string).unwrap_or_else(|| format!("rule #{}", index + 1));
+        let category = table.get_str("category")?
+            .ok_or_else(|| format!("{}: missing 'category'", name))?
+            .to_string();
+
+        let extensions = table.get_str_list("extension")?
+            .into_iter()
+            .map(|ext| ext.trim_start_matches('.').to_lowercase())
+            .collect();
+        let name_pattern = table.get_str("name_regex")?
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| format!("{}: {}", name, e))?;
+        let modified_after = table.get_str("modified_after")?.map(parse_date).transpose()?;
+        let modified_before = table.get_str("modified_before")?.map(parse_date).transpose()?;
+        let target = table.get_str("target")?.unwrap_or(DEFAULT_TARGET_TEMPLATE).to_string();
+        validate_template(&target).map_err(|e| format!("{}: {}", name, e))?;
+
+        let rule = Rule {
+            extensions,
+            name_pattern,
+            min_size: config_size(table, "min_size")?,
+            max_size: config_size(table, "max_size")?,
+            modified_after,
+            modified_before,
+            mime: table.get_str("mime")?.map(str::to_lowercase),
+            category,
+            target,
+            name,
+        };
+
+        if let (Some(min), Some(max)) = (rule.min_size, rule.max_size) {
+            if min > max {
+                return Err(format!("{}: min_size is larger than max_size", rule.name));
+            }
+        }
+
+        Ok(rule)
+    }
+
+    fn matches(&self, path: &Path, metadata: &fs::Metadata) -> bool {
+        let filename = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+
+        if !self.extensions.is_empty() && !self.extensions.contains(&extension) {
+            return false;
+        }
+        if let Some(pattern) = &self.name_pattern {
+            if !pattern.is_match(&filename) {
+                return false;
+            }
+        }
+
+        let size = metadata.len();
+        if self.min_size.is_some_and(|min| size < min) || self.max_size.is_some_and(|max| size > max) {
+            return false;
+        }
+
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let Some(days) = modified_days(metadata) else {
+                return false;
+            };
+            if self.modified_after.is_some_and(|after| days < after)
+                || self.modified_before.is_some_and(|before| days >= before)
+            {
+                return false;
+            }
+        }
+
+        if let Some(wanted) = &self.mime {
+            let Some(actual) = sniff_mime(path) else {
+                return false;
+            };
+            let matched = match wanted.strip_suffix("/*") {
+                Some(family) => actual.split('/').next() == Some(family),
+                None => actual == wanted,
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+fn validate_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..].find('}').ok_or_else(|| format!("unclosed '{{' in template '{}'", template))?;
+        let placeholder = &rest[open + 1..open + close];
+        if !matches!(placeholder, "category" | "year" | "month" | "day" | "name" | "stem" | "ext" | "dir") {
+            return Err(format!("unknown placeholder '{{{}}}' in template '{}'", placeholder, template));
+        }
+        rest = &rest[open + close + 1..];
+    }
+
+    if !template.contains("{name}") && !template.contains("{stem}") {
+        return Err(format!("template '{}' must include {{name}} or {{stem}}", template));
+    }
+    Ok(())
+}
+
+/// Ordered organization rules loaded from a config file, e.g.
+///
+/// ```toml
+/// [[rule]]
+/// name = "Invoices"
+/// extension = ["pdf"]
+/// name_regex = "(?i)^invoice"
+/// max_size = "20MB"
+/// modified_after = "2023-01-01"
+/// category = "Finance"
+/// target = "{category}/{year}/{month}/{name}"
+/// ```
+///
+/// Files matching no rule fall back to the extension table.
+#[derive(Debug, Clone, Default)]
+struct RuleEngine {
+    rules: Vec<Rule>,
+}
+
+impl RuleEngine {
+    fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let tables = parse_config(text)?;
+        let rules = tables
+            .iter()
+            .filter(|t| t.is_array_item && t.name == "rule")
+            .enumerate()
+            .map(|(i, table)| Rule::from_table(table, i))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(RuleEngine { rules })
+    }
+
+    // First matching rule wins
+    fn find_rule(&self, path: &Path, metadata: &fs::Metadata) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(path, metadata))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Undo journal: every organization run appends its moves and created
+// directories to a journal file so the run can be reverted later.
+// ---------------------------------------------------------------------------
+
+const JOURNAL_DIR: &str = ".organizer_journal";
+
+#[derive(Debug, Clone, PartialEq)]
+enum JournalEntry {
+    CreatedDir(PathBuf),
+    Moved {
+        source: PathBuf,
+        destination: PathBuf,
+        size: u64,
+        modified: u128,
+    },
+    Linked {
+        path: PathBuf,
+        size: u64,
+        modified: u128,
+    },
+    // Copy mode leaves the source in place; undo only removes the copy
+    Copied {
+        source: PathBuf,
+        destination: PathBuf,
+        size: u64,
+        modified: u128,
+    },
+    // A duplicate deleted outright; undo copies the kept file back, as long
+    // as it still has the recorded size and timestamp
+    Deleted {
+        path: PathBuf,
+        original: PathBuf,
+        size: u64,
+        modified: u128,
+    },
+}
+
+impl JournalEntry {
+    fn to_line(&self) -> String {
+        match self {
+            JournalEntry::CreatedDir(path) => format!("dir\t{}", escape_journal_field(path)),
+            JournalEntry::Moved { source, destination, size, modified } => format!(
+                "move\t{}\t{}\t{}\t{}",
+                escape_journal_field(source),
+                escape_journal_field(destination),
+                size,
+                modified
+            ),
+            JournalEntry::Linked { path, size, modified } => {
+                format!("link\t{}\t{}\t{}", escape_journal_field(path), size, modified)
+            }
+            JournalEntry::Copied { source, destination, size, modified } => format!(
+                "copy\t{}\t{}\t{}\t{}",
+                escape_journal_field(source),
+                escape_journal_field(destination),
+                size,
+                modified
+            ),
+            JournalEntry::Deleted { path, original, size, modified } => format!(
+                "delete\t{}\t{}\t{}\t{}",
+                escape_journal_field(path),
+                escape_journal_field(original),
+                size,
+                modified
+            ),
+        }
+    }
+
+    // The same entry with every path made absolute
+    fn absolute(&self) -> Self {
+        match self {
+            JournalEntry::CreatedDir(path) => JournalEntry::CreatedDir(absolute_path(path)),
+            JournalEntry::Moved { source, destination, size, modified } => JournalEntry::Moved {
+                source: absolute_path(source),
+                destination: absolute_path(destination),
+                size: *size,
+                modified: *modified,
+            },
+            JournalEntry::Linked { path, size, modified } => JournalEntry::Linked {
+                path: absolute_path(path),
+                size: *size,
+                modified: *modified,
+            },
+            JournalEntry::Copied { source, destination, size, modified } => JournalEntry::Copied {
+                source: absolute_path(source),
+                destination: absolute_path(destination),
+                size: *size,
+                modified: *modified,
+            },
+            JournalEntry::Deleted { path, original, size, modified } => JournalEntry::Deleted {
+                path: absolute_path(path),
+                original: absolute_path(original),
+                size: *size,
+                modified: *modified,
+            },
+        }
+    }
+
+    fn from_line(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields.as_slice() {
+            ["dir", path] => Ok(JournalEntry::CreatedDir(unescape_journal_field(path))),
+            ["move", source, destination, size, modified] => Ok(JournalEntry::Moved {
+                source: unescape_journal_field(source),
+                destination: unescape_journal_field(destination),
+                size: size.parse().map_err(|_| format!("invalid size in journal line: {}", line))?,
+                modified: modified.parse().map_err(|_| format!("invalid timestamp in journal line: {}", line))?,
+            }),
+            ["link", path, size, modified] => Ok(JournalEntry::Linked {
+                path: unescape_journal_field(path),
+                size: size.parse().map_err(|_| format!("invalid size in journal line: {}", line))?,
+                modified: modified.parse().map_err(|_| format!("invalid timestamp in journal line: {}", line))?,
+            }),
+            ["copy", source, destination, size, modified] => Ok(JournalEntry::Copied {
+                source: unescape_journal_field(source),
+                destination: unescape_journal_field(destination),
+                size: size.parse().map_err(|_| format!("invalid size in journal line: {}", line))?,
+                modified: modified.parse().map_err(|_| format!("invalid timestamp in journal line: {}", line))?,
+            }),
+            ["delete", path, original, size, modified] => Ok(JournalEntry::Deleted {
+                path: unescape_journal_field(path),
+                original: unescape_journal_field(original),
+                size: size.parse().map_err(|_| format!("invalid size in journal line: {}", line))?,
+                modified: modified.parse().map_err(|_| format!("invalid timestamp in journal line: {}", line))?,
+            }),
+            _ => Err(format!("unrecognized journal line: {}", line)),
+        }
+    }
+}
+
+// Bytes that aren't valid UTF-8 are written as \xNN, so undo can find
+// files whose names aren't
+fn escape_journal_field(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            match ch {
+                '\\' => escaped.push_str("\\\\"),
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                _ => escaped.push(ch),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+fn unescape_journal_field(field: &str) -> PathBuf {
+    let mut bytes = Vec::new();
+    let mut chars = field.chars();
+    while let Some(ch) = chars.next() {
+        let ch = match ch {
+            '\\' => match chars.next() {
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(byte) => bytes.push(byte),
+                        Err(_) => bytes.extend(format!("\\x{}", hex).into_bytes()),
+                    }
+                    continue;
+                }
+                Some(other) => other,
+                None => '\\',
+            },
+            ch => ch,
+        };
+        bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Journals are undone from wherever the user happens to be, so they store
+// absolute paths. Only the parent is resolved: the file itself may already be
+// gone (a move's source) or be a symlink that mustn't be followed.
+fn absolute_path(path: &Path) -> PathBuf {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return path.to_path_buf();
+    };
+    let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+    match fs::canonicalize(parent) {
+        Ok(parent) => parent.join(name),
+        Err(_) => std::env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+fn modified_stamp(metadata: &fs::Metadata) -> u128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+// The journal file is only created along with its first entry, so a run
+// that changes nothing leaves no journal for undo to pick over a real one
+struct Journal {
+    dir: PathBuf,
+    file: Option<(PathBuf, fs::File)>,
+}
+
+impl Journal {
+    fn new(target_dir: &Path) -> Self {
+        Journal { dir: target_dir.join(JOURNAL_DIR), file: None }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    fn open(&mut self) -> io::Result<&mut (PathBuf, fs::File)> {
+        if self.file.is_none() {
+            fs::create_dir_all(&self.dir)?;
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+
+            // Zero-padded so journals sort chronologically by name
+            let mut counter = 0;
+            loop {
+                let path = self.dir.join(format!("run-{:012}-{:03}.journal", secs, counter));
+                match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                    Ok(file) => {
+                        self.file = Some((path, file));
+                        break;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => counter += 1,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    // Overwritten and deduplicated files are parked here rather than deleted,
+    // so undo can put them back. Parking a file always leads to an entry, so
+    // this opens the journal.
+    fn set_aside_dir(&mut self) -> io::Result<PathBuf> {
+        Ok(self.open()?.0.with_extension("replaced"))
+    }
+
+    // Written and flushed per entry so an interrupted run can still be undone
+    fn record(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        let line = format!("{}\n", entry.absolute().to_line());
+        let (_, file) = self.open()?;
+        io::Write::write_all(file, line.as_bytes())?;
+        file.sync_data()
+    }
+
+    fn read_entries(path: &Path) -> Result<Vec<JournalEntry>, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        text.lines()
+            .filter(|line| !line.is_empty())
+            .map(JournalEntry::from_line)
+            .collect()
+    }
+
+    fn pending_journals(target_dir: &Path) -> Vec<PathBuf> {
+        let mut journals: Vec<PathBuf> = fs::read_dir(target_dir.join(JOURNAL_DIR))
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().is_some_and(|ext| ext == "journal"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        journals.sort();
+        journals
+    }
+}
+
+#[derive(Debug, Default)]
+struct UndoReport {
+    restored: usize,
+    removed_copies: usize,
+    removed_dirs: usize,
+    // Files no longer at their destination; nothing left to restore
+    missing: Vec<PathBuf>,
+    // Entries that could be retried once the conflict is resolved
+    skipped: Vec<(JournalEntry, String)>,
+}
+
+// ---------------------------------------------------------------------------
+// Watch mode: Linux inotify via direct syscalls, with a polling fallback on
+// other platforms.
+// ---------------------------------------------------------------------------
+
+const WATCH_TICK: std::time::Duration = std::time::Duration::from_millis(250);
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::{CString, OsString};
+    use std::io;
+    use std::os::raw::{c_char, c_int};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::Path;
+
+    pub const IN_MODIFY: u32 = 0x0000_0002;
+    pub const IN_CLOSE_WRITE: u32 = 0x0000_0008;
+    pub const IN_MOVED_TO: u32 = 0x0000_0080;
+    pub const IN_CREATE: u32 = 0x0000_0100;
+    pub const IN_Q_OVERFLOW: u32 = 0x0000_4000;
+    pub const IN_ISDIR: u32 = 0x4000_0000;
+
+    const IN_NONBLOCK: c_int = 0o4000;
+    const IN_CLOEXEC: c_int = 0o2000000;
+    const EVENT_HEADER: usize = 16;
+
+    extern "C" {
+        fn inotify_init1(flags: c_int) -> c_int;
+        fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int;
+        fn read(fd: c_int, buf: *mut u8, count: usize) -> isize;
+        fn close(fd: c_int) -> c_int;
+    }
+
+    pub struct Event {
+        pub wd: i32,
+        pub mask: u32,
+        pub name: Option<OsString>,
+    }
+
+    pub struct Inotify {
+        fd: c_int,
+    }
+
+    impl Inotify {
+        pub fn new() -> io::Result<Self> {
+            // SAFETY: inotify_init1 takes only flags and returns a new fd or -1
+            let fd = unsafe { inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Inotify { fd })
+        }
+
+        pub fn add_watch(&self, path: &Path, mask: u32) -> io::Result<i32> {
+            let path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+            // SAFETY: `self.fd` is an inotify fd owned by this struct and open
+            // until drop, and `path` is a NUL-terminated string that outlives
+            // the call, which doesn't keep the pointer
+            let wd = unsafe { inotify_add_watch(self.fd, path.as_ptr(), mask) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(wd)
+        }
+
+        // Drains whatever events are queued without blocking
+        pub fn read_events(&self) -> io::Result<Vec<Event>> {
+            let mut events = Vec::new();
+            let mut buffer = [0u8; 4096];
+
+            loop {
+                // SAFETY: `self.fd` is open until drop, and the kernel writes at
+                // most `buffer.len()` bytes into the buffer we exclusively borrow
+                let read = unsafe { read(self.fd, buffer.as_mut_ptr(), buffer.len()) };
+                if read < 0 {
+                    let error = io::Error::last_os_error();
+                    if error.kind() == io::ErrorKind::WouldBlock {
+                        return Ok(events);
+                    }
+                    return Err(error);
+                }
+
+                // The kernel only returns whole events, each a 16-byte header
+                // followed by `len` bytes of NUL-padded name
+                let mut offset = 0;
+                let read = read as usize;
+                while offset + EVENT_HEADER <= read {
+                    let field = |at: usize| {
+                        let bytes = [buffer[offset + at], buffer[offset + at + 1], buffer[offset + at + 2], buffer[offset + at + 3]];
+                        u32::from_ne_bytes(bytes)
+                    };
+                    let wd = field(0) as i32;
+                    let mask = field(4);
+                    let len = field(12) as usize;
+
+                    let raw_name = &buffer[offset + EVENT_HEADER..offset + EVENT_HEADER + len];
+                    let name_len = raw_name.iter().position(|&b| b == 0).unwrap_or(len);
+                    let name = if name_len > 0 {
+                        Some(OsString::from_vec(raw_name[..name_len].to_vec()))
+                    } else {
+                        None
+                    };
+
+                    events.push(Event { wd, mask, name });
+                    offset += EVENT_HEADER + len;
+                }
+            }
+        }
+    }
+
+    impl Drop for Inotify {
+        fn drop(&mut self) {
+            // SAFETY: the fd was opened by `new`, isn't shared, and is closed
+            // exactly once here
+            unsafe {
+                close(self.fd);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct DirectoryWatcher {
+    inotify: inotify::Inotify,
+    watched: HashMap<i32, PathBuf>,
+    recursive: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl DirectoryWatcher {
+    const MASK: u32 = inotify::IN_CLOSE_WRITE | inotify::IN_MOVED_TO | inotify::IN_CREATE | inotify::IN_MODIFY;
+
+    fn new(organizer: &FileOrganizer) -> io::Result<Self> {
+        let mut watcher = DirectoryWatcher {
+            inotify: inotify::Inotify::new()?,
+            watched: HashMap::new(),
+            recursive: organizer.scan_options.recursive,
+        };
+        watcher.watch_tree(organizer, &organizer.source_dir)?;
+        Ok(watcher)
+    }
+
+    // A subdirectory that can't be read or watched (permissions, the inotify
+    // watch limit) is logged and skipped rather than ending the whole watch
+    fn watch_tree(&mut self, organizer: &FileOrganizer, dir: &Path) -> io::Result<()> {
+        let wd = self.inotify.add_watch(dir, Self::MASK)?;
+        self.watched.insert(wd, dir.to_path_buf());
+
+        if self.recursive {
+            for entry in fs::read_dir(dir)?.flatten() {
+                let path = entry.path();
+                if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) && !organizer.is_target_dir(&path) {
+                    self.watch_subtree(organizer, &path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn watch_subtree(&mut self, organizer: &FileOrganizer, dir: &Path) {
+        if let Err(e) = self.watch_tree(organizer, dir) {
+            watch_log(&format!("⚠️  Not watching {}: {}", dir.display(), e));
+        }
+    }
+
+    // Paths that changed since the last call
+    fn poll(&mut self, organizer: &FileOrganizer) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let mut changed = Vec::new();
+
+        for event in self.inotify.read_events()? {
+            if event.mask & inotify::IN_Q_OVERFLOW != 0 {
+                println!("⚠️  Watch queue overflowed; rescanning {}", organizer.source_dir.display());
+                changed.extend(organizer.scan_directory()?);
+                continue;
+            }
+
+            let (Some(dir), Some(name)) = (self.watched.get(&event.wd), event.name) else {
+                continue;
+            };
+            let path = dir.join(name);
+
+            if event.mask & inotify::IN_ISDIR != 0 {
+                // New subdirectories get their own watch; files already inside
+                // them are picked up as they settle
+                if self.recursive && !organizer.is_target_dir(&path) {
+                    self.watch_subtree(organizer, &path);
+                    if let Ok(files) = fs::read_dir(&path) {
+                        changed.extend(files.flatten().map(|e| e.path()));
+                    }
+                }
+            } else {
+                changed.push(path);
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct DirectoryWatcher {
+    snapshot: HashMap<PathBuf, (u64, u128)>,
+}
+
+#[cfg(not(target_os = "linux"))]
+impl DirectoryWatcher {
+    fn new(organizer: &FileOrganizer) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut watcher = DirectoryWatcher { snapshot: HashMap::new() };
+        watcher.poll(organizer)?;
+        Ok(watcher)
+    }
+
+    // Without inotify, diff successive directory listings
+    fn poll(&mut self, organizer: &FileOrganizer) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let files = organizer.scan_directory()?;
+        let mut snapshot = HashMap::new();
+        let mut changed = Vec::new();
+
+        for path in files {
+            if let Ok(metadata) = fs::symlink_metadata(&path) {
+                let state = (metadata.len(), modified_stamp(&metadata));
+                if self.snapshot.get(&path) != Some(&state) {
+                    changed.push(path.clone());
+                }
+                snapshot.insert(path, state);
+            }
+        }
+
+        self.snapshot = snapshot;
+        Ok(changed)
+    }
+}
+
+fn format_timestamp(time: std::time::SystemTime) -> String {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let time_of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}Z",
+        year, month, day,
+        time_of_day / 3600, time_of_day % 3600 / 60, time_of_day % 60
+    )
+}
+
+fn watch_log(message: &str) {
+    println!("[{}] {}", format_timestamp(std::time::SystemTime::now()), message);
+}
+
+#[derive(Debug, Clone)]
+struct PlannedMove {
+    source: PathBuf,
+    destination: PathBuf,
+    category: String,
+    rule: Option<String>,
+    duplicate_of: Option<PathBuf>,
+    mismatch: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Directory statistics reports
+// ---------------------------------------------------------------------------
+
+const DEFAULT_LARGEST_FILES: usize = 10;
+
+// Upper bounds in days since last modification; the last bucket is open-ended
+const AGE_BUCKETS: &[(&str, i64)] = &[
+    ("< 1 week", 7),
+    ("1 week - 1 month", 30),
+    ("1-3 months", 90),
+    ("3-12 months", 365),
+    ("1-2 years", 730),
+    ("> 2 years", i64::MAX),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReportFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "text" | "txt" => Some(ReportFormat::Text),
+            "json" => Some(ReportFormat::Json),
+            "csv" => Some(ReportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FileRecord {
+    path: String,
+    category: String,
+    extension: String,
+    size: u64,
+    modified_days: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+struct CategoryStats {
+    name: String,
+    files: usize,
+    bytes: u64,
+    // (extension, files, bytes), most files first
+    extensions: Vec<(String, usize, u64)>,
+}
+
+#[derive(Debug, Clone)]
+struct AgeBucket {
+    label: &'static str,
+    files: usize,
+    bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+struct DirectoryReport {
+    source: PathBuf,
+    generated: std::time::SystemTime,
+    files: Vec<FileRecord>,
+    mismatches: Vec<(String, String)>,
+    largest_count: usize,
+}
+
+impl DirectoryReport {
+    fn total_size(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+
+    fn today(&self) -> i64 {
+        let secs = self.generated
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        secs.div_euclid(86_400)
+    }
+
+    fn categories(&self) -> Vec<CategoryStats> {
+        let mut by_category: HashMap<&str, HashMap<&str, (usize, u64)>> = HashMap::new();
+        for file in &self.files {
+            let extension = by_category
+                .entry(&file.category)
+                .or_default()
+                .entry(&file.extension)
+                .or_insert((0, 0));
+            extension.0 += 1;
+            extension.1 += file.size;
+        }
+
+        let mut categories: Vec<CategoryStats> = by_category
+            .into_iter()
+            .map(|(name, extensions)| {
+                let mut extensions: Vec<(String, usize, u64)> = extensions
+                    .into_iter()
+                    .map(|(ext, (files, bytes))| (ext.to_string(), files, bytes))
+                    .collect();
+                extensions.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+                CategoryStats {
+                    name: name.to_string(),
+                    files: extensions.iter().map(|e| e.1).sum(),
+                    bytes: extensions.iter().map(|e| e.2).sum(),
+                    extensions,
+                }
+            })
+            .collect();
+        categories.sort_by(|a, b| b.files.cmp(&a.files).then(b.bytes.cmp(&a.bytes)).then(a.name.cmp(&b.name)));
+        categories
+    }
+
+    fn largest(&self) -> Vec<&FileRecord> {
+        let mut files: Vec<&FileRecord> = self.files.iter().filter(|f| f.size > 0).collect();
+        files.sort_by(|a, b| b.size.cmp(&a.size).then(a.path.cmp(&b.path)));
+        files.truncate(self.largest_count);
+        files
+    }
+
+    fn age_label(&self, file: &FileRecord) -> &'static str {
+        match file.modified_days {
+            Some(days) => {
+                let age = self.today() - days;
+                AGE_BUCKETS.iter().find(|(_, limit)| age < *limit).map(|(label, _)| *label).unwrap_or("unknown")
+            }
+            None => "unknown",
+        }
+    }
+
+    fn age_histogram(&self) -> Vec<AgeBucket> {
+        let mut buckets: Vec<AgeBucket> = AGE_BUCKETS
+            .iter()
+            .map(|(label, _)| AgeBucket { label, files: 0, bytes: 0 })
+            .collect();
+        for file in &self.files {
+            let label = self.age_label(file);
+            let bucket = match buckets.iter_mut().find(|b| b.label == label) {
+                Some(bucket) => bucket,
+                None => {
+                    buckets.push(AgeBucket { label, files: 0, bytes: 0 });
+                    buckets.last_mut().unwrap()
+                }
+            };
+            bucket.files += 1;
+            bucket.bytes += file.size;
+        }
+        buckets
+    }
+
+    fn empty_files(&self) -> Vec<&FileRecord> {
+        self.files.iter().filter(|f| f.size == 0).collect()
+    }
+
+    fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_text(),
+            ReportFormat::Json => self.to_json(),
+            ReportFormat::Csv => self.to_csv(),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let total_files = self.files.len().max(1) as f64;
+        let total_size = self.total_size().max(1) as f64;
+        let mut out = String::new();
+
+        out += "\n=== Directory Statistics ===\n";
+        out += &format!("Source: {}\n", self.source.display());
+        out += &format!("Total files: {}\n", self.files.len());
+        out += &format!("Total size: {}\n", format_size(self.total_size()));
+
+        out += "\nFile distribution by category:\n";
+        for category in self.categories() {
+            out += &format!(
+                "  {}: {} files ({:.1}%), {} ({:.1}%)\n",
+                category.name,
+                category.files,
+                category.files as f64 / total_files * 100.0,
+                format_size(category.bytes),
+                category.bytes as f64 / total_size * 100.0
+            );
+            for (extension, files, bytes) in &category.extensions {
+                let extension = if extension.is_empty() { "(none)".to_string() } else { format!(".{}", extension) };
+                out += &format!("      {}: {} files, {}\n", extension, files, format_size(*bytes));
+            }
+        }
+
+        let largest = self.largest();
+        if !largest.is_empty() {
+            out += &format!("\nLargest {} files:\n", largest.len());
+            for (i, file) in largest.iter().enumerate() {
+                out += &format!("  {:>2}. {} ({})\n", i + 1, file.path, format_size(file.size));
+            }
+        }
+
+        out += "\nAge by last modification:\n";
+        for bucket in self.age_histogram() {
+            out += &format!("  {:<17} {:>6} files, {}\n", bucket.label, bucket.files, format_size(bucket.bytes));
+        }
+
+        let empty = self.empty_files();
+        if !empty.is_empty() {
+            out += &format!("\nEmpty files: {}\n", empty.len());
+            for file in empty {
+                out += &format!("  {}\n", file.path);
+            }
+        }
+
+        if !self.mismatches.is_empty() {
+            out += &format!("\nExtension/content mismatches: {}\n", self.mismatches.len());
+            for (path, mismatch) in &self.mismatches {
+                out += &format!("  ⚠️  {}: {}\n", path, mismatch);
+            }
+        }
+
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let categories: Vec<String> = self.categories()
+            .iter()
+            .map(|category| {
+                let extensions: Vec<String> = category.extensions
+                    .iter()
+                    .map(|(extension, files, bytes)| format!(
+                        "        {{\"extension\": {}, \"files\": {}, \"bytes\": {}}}",
+                        json_string(extension), files, bytes
+                    ))
+                    .collect();
+                format!(
+                    "    {{\n      \"name\": {},\n      \"files\": {},\n      \"bytes\": {},\n      \"extensions\": {}\n    }}",
+                    json_string(&category.name), category.files, category.bytes, json_array(&extensions, "      ")
+                )
+            })
+            .collect();
+        let largest: Vec<String> = self.largest()
+            .iter()
+            .map(|file| format!("    {{\"path\": {}, \"bytes\": {}}}", json_string(&file.path), file.size))
+            .collect();
+        let ages: Vec<String> = self.age_histogram()
+            .iter()
+            .map(|bucket| format!(
+                "    {{\"range\": {}, \"files\": {}, \"bytes\": {}}}",
+                json_string(bucket.label), bucket.files, bucket.bytes
+            ))
+            .collect();
+        let empty: Vec<String> = self.empty_files()
+            .iter()
+            .map(|file| format!("    {}", json_string(&file.path)))
+            .collect();
+        let mismatches: Vec<String> = self.mismatches
+            .iter()
+            .map(|(path, note)| format!("    {{\"path\": {}, \"note\": {}}}", json_string(path), json_string(note)))
+            .collect();
+
+        format!(
+            "{{\n  \"source\": {},\n  \"generated\": {},\n  \"total_files\": {},\n  \"total_bytes\": {},\n  \
+             \"categories\": {},\n  \"largest_files\": {},\n  \"age_histogram\": {},\n  \
+             \"empty_files\": {},\n  \"mismatches\": {}\n}}\n",
+            json_string(&self.source.to_string_lossy()),
+            json_string(&format_timestamp(self.generated)),
+            self.files.len(),
+            self.total_size(),
+            json_array(&categories, "  "),
+            json_array(&largest, "  "),
+            json_array(&ages, "  "),
+            json_array(&empty, "  "),
+            json_array(&mismatches, "  ")
+        )
+    }
+
+    // One row per file so audits can slice the data however they need
+    fn to_csv(&self) -> String {
+        let mut out = String::from("path,category,extension,size_bytes,modified,age_range\n");
+        for file in &self.files {
+            let modified = match file.modified_days {
+                Some(days) => {
+                    let (year, month, day) = civil_from_days(days);
+                    format!("{:04}-{:02}-{:02}", year, month, day)
+                }
+                None => String::new(),
+            };
+            out += &format!(
+                "{},{},{},{},{},{}\n",
+                csv_field(&file.path),
+                csv_field(&file.category),
+                csv_field(&file.extension),
+                file.size,
+                modified,
+                csv_field(self.age_label(file))
+            );
+        }
+        out
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", size, UNITS[unit])
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Items are expected to be indented one level deeper than `indent`
+fn json_array(items: &[String], indent: &str) -> String {
+    if items.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n{}]", items.join(",\n"), indent)
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Organizer configuration file
+// ---------------------------------------------------------------------------
+
+/// Settings loaded from a config file in the same TOML subset as the rules:
+///
+/// ```toml
+/// source = "/srv/inbox"
+/// target = "/srv/sorted"
+/// default_categories = true      # keep the built-in extension table
+///
+/// [categories]
+/// Documents = ["txt", "pdf", "md"]
+/// Ebooks = ["epub", "mobi"]
+///
+/// [scan]
+/// recursive = true
+/// max_depth = 3
+/// exclude = ["*.part", "*.crdownload"]
+/// include_hidden = false
+/// symlinks = "skip"              # skip | follow | link
+/// layout = "flatten"             # flatten | preserve
+/// content_detection = "fallback" # off | fallback | override
+///
+/// [organize]
+/// collisions = "number"          # skip | number | timestamp | newer | dedupe
+/// duplicates = "ignore"          # ignore | delete | hardlink | quarantine
+/// transfer = "move"              # move | copy (leave the originals in place)
+///
+/// [[rule]]
+/// category = "Finance"
+/// name_regex = "(?i)invoice"
+/// ```
+#[derive(Debug, Clone, Default)]
+struct OrganizerConfig {
+    source: Option<String>,
+    target: Option<String>,
+    default_categories: bool,
+    categories: Vec<(String, Vec<String>)>,
+    scan: ScanOptions,
+    content_detection: Option<ContentDetection>,
+    collision_policy: Option<CollisionPolicy>,
+    duplicate_action: Option<DuplicateAction>,
+    transfer_mode: Option<TransferMode>,
+}
+
+impl OrganizerConfig {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut config = OrganizerConfig { default_categories: true, ..Default::default() };
+
+        for table in parse_config(text)? {
+            match (table.name.as_str(), table.is_array_item) {
+                ("", false) => config.parse_root(&table)?,
+                ("categories", false) => {
+                    for (category, _) in &table.entries {
+                        let extensions = table.get_str_list(category)?
+                            .into_iter()
+                            .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                            .collect();
+                        config.categories.push((category.clone(), extensions));
+                    }
+                }
+                ("scan", false) => config.parse_scan(&table)?,
+                ("organize", false) => config.parse_organize(&table)?,
+                ("rule", true) => {}
+                (name, _) => return Err(format!("Unknown config section [{}]", name)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn parse_root(&mut self, table: &ConfigTable) -> Result<(), String> {
+        for (key, _) in &table.entries {
+            match key.as_str() {
+                "source" => self.source = table.get_str(key)?.map(str::to_string),
+                "target" => self.target = table.get_str(key)?.map(str::to_string),
+                "default_categories" => self.default_categories = config_bool(table, key)?,
+                _ => return Err(format!("Unknown config key '{}'", key)),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_scan(&mut self, table: &ConfigTable) -> Result<(), String> {
+        for (key, value) in &table.entries {
+            match key.as_str() {
+                "recursive" => self.scan.recursive = config_bool(table, key)?,
+                "max_depth" => match value {
+                    ConfigValue::Int(depth) if *depth >= 0 => self.scan.max_depth = Some(*depth as usize),
+                    _ => return Err("[scan] 'max_depth' must be a non-negative integer".to_string()),
+                },
+                "include" => self.scan.include = table.get_str_list(key)?,
+                "exclude" => self.scan.exclude = table.get_str_list(key)?,
+                "include_hidden" => self.scan.include_hidden = config_bool(table, key)?,
+                "symlinks" => {
+                    self.scan.symlinks = match table.get_str(key)?.unwrap_or_default() {
+                        "skip" => SymlinkPolicy::Skip,
+                        "follow" => SymlinkPolicy::Follow,
+                        "link" => SymlinkPolicy::MoveLink,
+                        other => return Err(format!("[scan] unknown symlink policy '{}'", other)),
+                    }
+                }
+                "layout" => {
+                    self.scan.layout = match table.get_str(key)?.unwrap_or_default() {
+                        "flatten" => TargetLayout::Flatten,
+                        "preserve" => TargetLayout::PreserveSubpaths,
+                        other => return Err(format!("[scan] unknown layout '{}'", other)),
+                    }
+                }
+                "content_detection" => {
+                    self.content_detection = Some(match table.get_str(key)?.unwrap_or_default() {
+                        "off" => ContentDetection::Off,
+                        "fallback" => ContentDetection::Fallback,
+                        "override" => ContentDetection::Override,
+                        other => return Err(format!("[scan] unknown content detection mode '{}'", other)),
+                    })
+                }
+                _ => return Err(format!("Unknown config key [scan] '{}'", key)),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_organize(&mut self, table: &ConfigTable) -> Result<(), String> {
+        for (key, _) in &table.entries {
+            let value = table.get_str(key)?.unwrap_or_default();
+            match key.as_str() {
+                "collisions" => {
+                    self.collision_policy = Some(CollisionPolicy::parse(value)
+                        .ok_or_else(|| format!("[organize] unknown collision policy '{}'", value))?)
+                }
+                "duplicates" => {
+                    self.duplicate_action = Some(DuplicateAction::parse(value)
+                        .ok_or_else(|| format!("[organize] unknown duplicate action '{}'", value))?)
+                }
+                "transfer" => {
+                    self.transfer_mode = Some(TransferMode::parse(value)
+                        .ok_or_else(|| format!("[organize] unknown transfer mode '{}'", value))?)
+                }
+                _ => return Err(format!("Unknown config key [organize] '{}'", key)),
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, organizer: &mut FileOrganizer) {
+        if !self.default_categories {
+            organizer.file_types.clear();
+        }
+        for (category, extensions) in &self.categories {
+            for extension in extensions {
+                organizer.file_types.insert(extension.clone(), category.clone());
+            }
+        }
+
+        organizer.scan_options = self.scan.clone();
+        if let Some(mode) = self.content_detection {
+            organizer.content_detection = mode;
+        }
+        if let Some(policy) = self.collision_policy {
+            organizer.collision_policy = policy;
+        }
+        if let Some(action) = self.duplicate_action {
+            organizer.duplicate_action = action;
+        }
+        if let Some(mode) = self.transfer_mode {
+            organizer.transfer_mode = mode;
+        }
+    }
+}
+
+fn config_bool(table: &ConfigTable, key: &str) -> Result<bool, String> {
+    match table.get(key) {
+        Some(ConfigValue::Bool(value)) => Ok(*value),
+        _ => Err(format!("[{}] '{}' must be true or false", table.name, key)),
+    }
+}
+
+const DEFAULT_FILE_TYPES: &[(&str, &[&str])] = &[
+    ("Documents", &["txt", "doc", "docx", "pdf", "rtf"]),
+    ("Images", &["jpg", "jpeg", "png", "gif", "bmp", "svg"]),
+    ("Audio", &["mp3", "wav", "flac", "aac", "ogg"]),
+    ("Videos", &["mp4", "avi", "mkv", "mov", "wmv"]),
+    ("Archives", &["zip", "rar", "7z", "tar", "gz"]),
+    ("Programs", &["exe", "msi", "deb", "dmg"]),
+];
+
 struct FileOrganizer {
     source_dir: PathBuf,
     target_dir: PathBuf,
     file_types: HashMap<String, String>,
+    scan_options: ScanOptions,
+    rules: RuleEngine,
+    collision_policy: CollisionPolicy,
+    duplicate_action: DuplicateAction,
+    content_detection: ContentDetection,
+    transfer_mode: TransferMode,
+    interactive: bool,
 }
 
-impl FileOrganizer {
-    fn new(source: &str, target: &str) -> Self {
-        let mut file_types = HashMap::new();
-        
-        // Define file type mappings
-        file_types.insert("txt".to_string(), "Documents".to_string());
-        file_types.insert("doc".to_string(), "Documents".to_string());
-        file_types.insert("docx".to_string(), "Documents".to_string());
-        file_types.insert("pdf".to_string(), "Documents".to_string());
-        file_types.insert("rtf".to_string(), "Documents".to_string());
-        
-        file_types.insert("jpg".to_string(), "Images".to_string());
-        file_types.insert("jpeg".to_string(), "Images".to_string());
-        file_types.insert("png".to_string(), "Images".to_string());
-        file_types.insert("gif".to_string(), "Images".to_string());
-        file_types.insert("bmp".to_string(), "Images".to_string());
-        file_types.insert("svg".to_string(), "Images".to_string());
-        
-        file_types.insert("mp3".to_string(), "Audio".to_string());
-        file_types.insert("wav".to_string(), "Audio".to_string());
-        file_types.insert("flac".to_string(), "Audio".to_string());
-        file_types.insert("aac".to_string(), "Audio".to_string());
-        file_types.insert("ogg".to_string(), "Audio".to_string());
-        
-        file_types.insert("mp4".to_string(), "Videos".to_string());
-        file_types.insert("avi".to_string(), "Videos".to_string());
-        file_types.insert("mkv".to_string(), "Videos".to_string());
-        file_types.insert("mov".to_string(), "Videos".to_string());
-        file_types.insert("wmv".to_string(), "Videos".to_string());
+impl FileOrganizer {
+    fn new(source: &str, target: &str) -> Self {
+        let mut file_types = HashMap::new();
+        
+        // Define file type mappings
+        for (category, extensions) in DEFAULT_FILE_TYPES {
+            for extension in *extensions {
+                file_types.insert(extension.to_string(), category.to_string());
+            }
+        }
+        
+        FileOrganizer {
+            source_dir: PathBuf::from(source),
+            target_dir: PathBuf::from(target),
+            file_types,
+            scan_options: ScanOptions::default(),
+            rules: RuleEngine::default(),
+            collision_policy: CollisionPolicy::RenameNumbered,
+            duplicate_action: DuplicateAction::Ignore,
+            content_detection: ContentDetection::Fallback,
+            transfer_mode: TransferMode::Move,
+            interactive: true,
+        }
+    }
+
+    // Builds an organizer from a config file; explicit paths override the
+    // file's `source`/`target`. Read-only reports can do without a target.
+    // See the `OrganizerConfig` docs for the format.
+    fn from_config(config_path: &Path, source: Option<&str>, target: Option<&str>, read_only: bool) -> Result<Self, String> {
+        let text = fs::read_to_string(config_path)
+            .map_err(|e| format!("Cannot read {}: {}", config_path.display(), e))?;
+        let config = OrganizerConfig::parse(&text)?;
+
+        let source = source.map(str::to_string).or(config.source.clone())
+            .ok_or("No source directory given (use --source or 'source' in the config)")?;
+        let target = target.map(str::to_string).or(config.target.clone())
+            .or_else(|| read_only.then(|| source.clone()))
+            .ok_or("No target directory given (use --target or 'target' in the config)")?;
+
+        let mut organizer = FileOrganizer::new(&source, &target);
+        config.apply(&mut organizer);
+        organizer.rules = RuleEngine::parse(&text)?;
+        Ok(organizer)
+    }
+
+    fn get_file_category(&self, extension: &str) -> String {
+        self.file_types
+            .get(&extension.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| "Others".to_string())
+    }
+
+    fn create_directory_if_not_exists(&self, path: &Path, journal: &mut Journal) -> Result<(), Box<dyn std::error::Error>> {
+        if !path.exists() {
+            // Record each missing ancestor, outermost first, so undo can remove them
+            let mut missing: Vec<&Path> = path.ancestors().take_while(|p| !p.exists()).collect();
+            missing.reverse();
+
+            fs::create_dir_all(path)?;
+            for dir in missing {
+                journal.record(&JournalEntry::CreatedDir(dir.to_path_buf()))?;
+            }
+            println!("Created directory: {}", path.display());
+        }
+        Ok(())
+    }
+
+    fn scan_directory(&self) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let mut files = Vec::new();
+        
+        if !self.source_dir.exists() {
+            return Err(format!("Source directory does not exist: {}", self.source_dir.display()).into());
+        }
+
+        let mut visited = HashSet::new();
+        if let Ok(canonical) = fs::canonicalize(&self.source_dir) {
+            visited.insert(canonical);
+        }
+        self.walk_directory(&self.source_dir, 0, &mut visited, &mut files)?;
+
+        files.sort();
+        Ok(files)
+    }
+
+    fn walk_directory(
+        &self,
+        dir: &Path,
+        depth: usize,
+        visited: &mut HashSet<PathBuf>,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let options = &self.scan_options;
+
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if !options.include_hidden && is_hidden(&path) {
+                continue;
+            }
+
+            let file_type = entry.file_type()?;
+            let (is_dir, is_file) = if file_type.is_symlink() {
+                match options.symlinks {
+                    SymlinkPolicy::Skip => continue,
+                    SymlinkPolicy::MoveLink => (false, true),
+                    SymlinkPolicy::Follow => match fs::metadata(&path) {
+                        Ok(metadata) => (metadata.is_dir(), metadata.is_file()),
+                        // Dangling link
+                        Err(_) => continue,
+                    },
+                }
+            } else {
+                (file_type.is_dir(), file_type.is_file())
+            };
+
+            let relative = self.relative_path(&path);
+            if options.exclude.iter().any(|pattern| Self::pattern_matches(pattern, &relative)) {
+                continue;
+            }
+
+            if is_dir {
+                if !self.should_descend(depth) || self.is_target_dir(&path) {
+                    continue;
+                }
+
+                // Guard against symlink cycles when following links
+                if let Ok(canonical) = fs::canonicalize(&path) {
+                    if !visited.insert(canonical) {
+                        continue;
+                    }
+                }
+
+                if let Err(e) = self.walk_directory(&path, depth + 1, visited, files) {
+                    println!("⚠️  Skipping {}: {}", path.display(), e);
+                }
+            } else if is_file && self.is_included(&relative) {
+                files.push(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_included(&self, relative: &str) -> bool {
+        let include = &self.scan_options.include;
+        include.is_empty() || include.iter().any(|pattern| Self::pattern_matches(pattern, relative))
+    }
+
+    // Applies the scan filters to a single file, e.g. one reported by the watcher
+    fn accepts_file(&self, path: &Path) -> bool {
+        let options = &self.scan_options;
+        let Ok(relative) = path.strip_prefix(&self.source_dir) else {
+            return false;
+        };
+        let depth = relative.components().count().saturating_sub(1);
+        if depth > 0 && (!options.recursive || options.max_depth.is_some_and(|max| depth > max)) {
+            return false;
+        }
+        if !options.include_hidden && relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.')) {
+            return false;
+        }
+        if path.starts_with(&self.target_dir) {
+            return false;
+        }
+
+        // Excluded directories prune everything beneath them
+        let relative = self.relative_path(path);
+        let segments: Vec<&str> = relative.split('/').collect();
+        for end in 1..=segments.len() {
+            let prefix = segments[..end].join("/");
+            if options.exclude.iter().any(|pattern| Self::pattern_matches(pattern, &prefix)) {
+                return false;
+            }
+        }
+
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => options.symlinks != SymlinkPolicy::Skip && self.is_included(&relative),
+            Ok(metadata) => metadata.is_file() && self.is_included(&relative),
+            Err(_) => false,
+        }
+    }
+
+    fn should_descend(&self, depth: usize) -> bool {
+        if !self.scan_options.recursive {
+            return false;
+        }
+        match self.scan_options.max_depth {
+            Some(max) => depth < max,
+            None => true,
+        }
+    }
+
+    // Don't re-scan already organized files when the target lives inside the source
+    fn is_target_dir(&self, path: &Path) -> bool {
+        match (fs::canonicalize(path), fs::canonicalize(&self.target_dir)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => path == self.target_dir,
+        }
+    }
+
+    // Patterns containing '/' are matched against the path relative to the
+    // source directory, others against the file name alone.
+    fn pattern_matches(pattern: &str, relative: &str) -> bool {
+        if pattern.contains('/') {
+            glob_match(pattern, relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            glob_match(pattern, name)
+        }
+    }
+
+    fn relative_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.source_dir).unwrap_or(path);
+        relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn extension_category(&self, path: &Path) -> String {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        self.get_file_category(&extension)
+    }
+
+    // Category from the extension, refined by the file's content depending on
+    // the detection mode. Also returns a note when content and extension disagree.
+    fn detect_category(&self, file_path: &Path) -> (String, Option<String>) {
+        let by_extension = self.extension_category(file_path);
+        if self.content_detection == ContentDetection::Off {
+            return (by_extension, None);
+        }
+
+        let Some(mime) = sniff_mime(file_path) else {
+            return (by_extension, None);
+        };
+        let Some((content_category, extensions)) = mime_info(mime) else {
+            return (by_extension, None);
+        };
+
+        let extension = file_path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        let mismatch = if !extension.is_empty() && !extensions.contains(&extension.as_str()) {
+            Some(format!("content is {}, not .{}", mime, extension))
+        } else {
+            None
+        };
+
+        let use_content = match self.content_detection {
+            ContentDetection::Override => true,
+            ContentDetection::Fallback => by_extension == "Others",
+            ContentDetection::Off => false,
+        };
+        let category = if use_content { content_category.to_string() } else { by_extension };
+
+        (category, mismatch)
+    }
+
+    fn plan_file(&self, file_path: &Path) -> Option<PlannedMove> {
+        let metadata = fs::symlink_metadata(file_path).ok();
+        let rule = metadata.as_ref().and_then(|m| self.rules.find_rule(file_path, m));
+
+        let (detected, mismatch) = self.detect_category(file_path);
+
+        let (category, template) = match rule {
+            Some(rule) => (rule.category.clone(), rule.target.as_str()),
+            None => {
+                let template = match self.scan_options.layout {
+                    TargetLayout::Flatten => DEFAULT_TARGET_TEMPLATE,
+                    TargetLayout::PreserveSubpaths => "{category}/{dir}/{name}",
+                };
+                (detected, template)
+            }
+        };
+
+        let relative = self.render_template(template, file_path, &category, metadata.as_ref())?;
+        Some(PlannedMove {
+            source: file_path.to_path_buf(),
+            destination: self.target_dir.join(relative),
+            category,
+            rule: rule.map(|r| r.name.clone()),
+            duplicate_of: None,
+            mismatch,
+        })
+    }
+
+    fn render_template(&self, template: &str, file_path: &Path, category: &str, metadata: Option<&fs::Metadata>) -> Option<PathBuf> {
+        // File name fields stay OsStr, so names that aren't valid UTF-8 keep
+        // their original bytes
+        let name = file_path.file_name()?;
+        let stem = file_path.file_stem().unwrap_or_default();
+        let ext = file_path.extension().unwrap_or_default();
+        let parent = file_path.parent().unwrap_or(Path::new(""));
+        let dir = parent.strip_prefix(&self.source_dir).unwrap_or(parent);
+        let (year, month, day) = metadata
+            .and_then(modified_days)
+            .map(civil_from_days)
+            .unwrap_or((1970, 1, 1));
+
+        // One pass over the template, so braces inside a file name are
+        // copied as they are rather than expanded again
+        let mut rendered = std::ffi::OsString::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            rendered.push(&rest[..open]);
+            let after = &rest[open + 1..];
+            let value = after.find('}').and_then(|close| {
+                let value: std::ffi::OsString = match &after[..close] {
+                    "category" => category.into(),
+                    "year" => format!("{:04}", year).into(),
+                    "month" => format!("{:02}", month).into(),
+                    "day" => format!("{:02}", day).into(),
+                    "name" => name.into(),
+                    "stem" => stem.into(),
+                    "ext" => ext.into(),
+                    "dir" => dir.as_os_str().into(),
+                    _ => return None,
+                };
+                Some((value, close))
+            });
+            match value {
+                Some((value, close)) => {
+                    rendered.push(value);
+                    rest = &after[close + 1..];
+                }
+                None => {
+                    rendered.push("{");
+                    rest = after;
+                }
+            }
+        }
+        rendered.push(rest);
+
+        // Drop empty segments (e.g. "{dir}" for top-level files) and anything
+        // that could escape the target directory
+        let path: PathBuf = Path::new(&rendered)
+            .components()
+            .filter(|component| matches!(component, std::path::Component::Normal(_)))
+            .collect();
+        Some(path)
+    }
+
+    fn organize_files(&self, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let files = self.scan_directory()?;
         
-        file_types.insert("zip".to_string(), "Archives".to_string());
-        file_types.insert("rar".to_string(), "Archives".to_string());
-        file_types.insert("7z".to_string(), "Archives".to_string());
-        file_types.insert("tar".to_string(), "Archives".to_string());
-        file_types.insert("gz".to_string(), "Archives".to_string());
+        if files.is_empty() {
+            println!("No files found in source directory.");
+            return Ok(());
+        }
+
+        let duplicate_of = self.duplicate_lookup(&files);
+        let mut organization_plan: HashMap<String, Vec<PlannedMove>> = HashMap::new();
         
-        file_types.insert("exe".to_string(), "Programs".to_string());
-        file_types.insert("msi".to_string(), "Programs".to_string());
-        file_types.insert("deb".to_string(), "Programs".to_string());
-        file_types.insert("dmg".to_string(), "Programs".to_string());
+        // Analyze files and create organization plan
+        for file_path in &files {
+            if let Some(mut planned) = self.plan_file(file_path) {
+                planned.duplicate_of = duplicate_of.get(file_path).cloned();
+                if planned.duplicate_of.is_some() && self.duplicate_action == DuplicateAction::Quarantine {
+                    let filename = file_path.file_name().unwrap_or_default();
+                    planned.category = QUARANTINE_CATEGORY.to_string();
+                    planned.destination = self.target_dir.join(QUARANTINE_CATEGORY).join(filename);
+                }
+                organization_plan
+                    .entry(planned.category.clone())
+                    .or_default()
+                    .push(planned);
+            }
+        }
+
+        let collisions = Self::find_collisions(&organization_plan);
+
+        // Display organization plan
+        println!("\n=== File Organization Plan ===");
+        for (category, moves) in &organization_plan {
+            println!("\n📁 {} ({} files):", category, moves.len());
+            for planned in moves {
+                let destination = planned.destination.strip_prefix(&self.target_dir).unwrap_or(&planned.destination);
+                let rule = planned.rule.as_ref().map(|r| format!(" [{}]", r)).unwrap_or_default();
+                let collision = if collisions.contains(&planned.destination) { "  ⚠️  collision" } else { "" };
+                let duplicate = match (&planned.duplicate_of, self.duplicate_action) {
+                    (Some(original), DuplicateAction::Delete) => format!("  ♻ duplicate of {}, will be removed", self.relative_path(original)),
+                    (Some(original), DuplicateAction::HardLink) => format!("  🔗 duplicate of {}, will be hard-linked", self.relative_path(original)),
+                    (Some(original), _) => format!("  ♻ duplicate of {}", self.relative_path(original)),
+                    (None, _) => String::new(),
+                };
+                println!("  • {} → {}{}{}{}", self.relative_path(&planned.source), destination.display(), rule, collision, duplicate);
+                if let Some(mismatch) = &planned.mismatch {
+                    println!("      ⚠️  extension mismatch: {}", mismatch);
+                }
+            }
+        }
+
+        let mut policy = self.collision_policy;
+        if !collisions.is_empty() {
+            println!("\n⚠️  {} destination(s) collide with existing or other incoming files.", collisions.len());
+            println!("Collision policy: {}", policy.describe());
+        }
+
+        if self.transfer_mode == TransferMode::Copy {
+            println!("\nCopy mode: files are copied and the originals left in place.");
+        }
+
+        if dry_run {
+            println!("\n🔍 This was a dry run. No files were moved.");
+            return Ok(());
+        }
+
+        if !collisions.is_empty() && self.interactive {
+            print!("Policy for this run (skip/number/timestamp/newer/dedupe, Enter to keep): ");
+            io::Write::flush(&mut io::stdout()).unwrap();
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            if !input.trim().is_empty() {
+                match CollisionPolicy::parse(&input) {
+                    Some(chosen) => policy = chosen,
+                    None => println!("Unknown policy, keeping: {}", policy.describe()),
+                }
+            }
+        }
+
+        // Confirm before proceeding; non-interactive runs are confirmed up front
+        if self.interactive {
+            print!("\nProceed with file organization? (y/n): ");
+            io::Write::flush(&mut io::stdout()).unwrap();
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            
+            if !input.trim().eq_ignore_ascii_case("y") {
+                println!("Operation cancelled.");
+                return Ok(());
+            }
+        }
+
+        // Create target directory structure and move files. The target itself
+        // holds the journal, so it isn't journaled and survives an undo.
+        if !self.target_dir.exists() {
+            fs::create_dir_all(&self.target_dir)?;
+            println!("Created directory: {}", self.target_dir.display());
+        }
+        let mut journal = Journal::new(&self.target_dir);
         
-        FileOrganizer {
-            source_dir: PathBuf::from(source),
-            target_dir: PathBuf::from(target),
-            file_types,
+        let mut moved_count = 0;
+        let mut skipped_count = 0;
+        let mut deduplicated_count = 0;
+        let mut error_count = 0;
+
+        let mut linked_count = 0;
+
+        // Originals go first so duplicates can be linked to where they end up
+        let (duplicates, originals): (Vec<PlannedMove>, Vec<PlannedMove>) = organization_plan
+            .into_values()
+            .flatten()
+            .partition(|planned| {
+                planned.duplicate_of.is_some()
+                    && matches!(self.duplicate_action, DuplicateAction::Delete | DuplicateAction::HardLink)
+            });
+        let mut final_locations: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+        for planned in originals {
+            let file_path = &planned.source;
+            match self.execute_move(&planned, policy, &mut journal) {
+                Ok(MoveOutcome::Moved(target_path)) => {
+                    println!("✓ {}: {} → {}", 
+                           self.transfer_mode.past_tense(),
+                           file_path.display(), 
+                           target_path.display());
+                    final_locations.insert(file_path.clone(), target_path);
+                    moved_count += 1;
+                }
+                Ok(MoveOutcome::Skipped(reason)) => {
+                    println!("⏭ Skipped: {} ({})", file_path.display(), reason);
+                    skipped_count += 1;
+                }
+                Ok(MoveOutcome::Deduplicated) => {
+                    println!("♻ Duplicate: {} is identical to {}", file_path.display(), planned.destination.display());
+                    final_locations.insert(file_path.clone(), planned.destination.clone());
+                    deduplicated_count += 1;
+                }
+                Err(e) => {
+                    println!("✗ Failed to move {}: {}", file_path.display(), e);
+                    error_count += 1;
+                }
+            }
+        }
+
+        for planned in duplicates {
+            let file_path = &planned.source;
+            let original = planned.duplicate_of.as_ref().unwrap();
+            let original_location = final_locations.get(original).unwrap_or(original);
+
+            // Re-check byte for byte before discarding anything
+            match files_identical(file_path, original_location) {
+                Ok(true) => {}
+                Ok(false) | Err(_) => {
+                    println!("⏭ Skipped: {} (no longer identical to {})", file_path.display(), original_location.display());
+                    skipped_count += 1;
+                    continue;
+                }
+            }
+
+            let result: Result<(), Box<dyn std::error::Error>> = if self.duplicate_action == DuplicateAction::Delete {
+                self.delete_duplicate(file_path, original_location, &mut journal).map_err(Into::into).map(|_| {
+                    let action = match self.transfer_mode {
+                        TransferMode::Move => "Removed duplicate",
+                        TransferMode::Copy => "Not copying duplicate",
+                    };
+                    println!("♻ {}: {} (same as {})", action, file_path.display(), original_location.display());
+                    deduplicated_count += 1;
+                })
+            } else {
+                self.link_duplicate(&planned, original_location, policy, &mut journal).map(|linked| {
+                    if let Some(link) = linked {
+                        println!("🔗 Linked: {} → {} (same as {})", file_path.display(), link.display(), original_location.display());
+                        linked_count += 1;
+                    } else {
+                        skipped_count += 1;
+                    }
+                })
+            };
+
+            if let Err(e) = result {
+                println!("✗ Failed to handle duplicate {}: {}", file_path.display(), e);
+                error_count += 1;
+            }
+        }
+
+        println!("\n=== Organization Complete ===");
+        println!("Files {}: {}", self.transfer_mode.past_tense().to_lowercase(), moved_count);
+        println!("Skipped (collisions): {}", skipped_count);
+        println!("Duplicates removed: {}", deduplicated_count);
+        println!("Duplicates hard-linked: {}", linked_count);
+        println!("Errors: {}", error_count);
+        match journal.path() {
+            Some(path) => println!("Journal: {}", path.display()),
+            None => println!("Journal: nothing changed, so nothing to undo"),
+        }
+
+        if error_count > 0 {
+            return Err(format!("{} file(s) could not be organized", error_count).into());
         }
+        Ok(())
     }
 
-    fn get_file_category(&self, extension: &str) -> String {
-        self.file_types
-            .get(&extension.to_lowercase())
-            .cloned()
-            .unwrap_or_else(|| "Others".to_string())
+    // Creates the destination folder, applies the collision policy and moves
+    // the file, journaling every step
+    fn execute_move(&self, planned: &PlannedMove, policy: CollisionPolicy, journal: &mut Journal) -> Result<MoveOutcome, Box<dyn std::error::Error>> {
+        if let Some(parent) = planned.destination.parent() {
+            self.create_directory_if_not_exists(parent, journal)?;
+        }
+
+        let target_path = match self.resolve_collision(planned, policy, journal)? {
+            CollisionOutcome::Move(path) => path,
+            CollisionOutcome::Skipped(reason) => return Ok(MoveOutcome::Skipped(reason)),
+            CollisionOutcome::Deduplicated => return Ok(MoveOutcome::Deduplicated),
+        };
+
+        match self.transfer_mode {
+            TransferMode::Move => Self::move_journaled(&planned.source, &target_path, journal)?,
+            TransferMode::Copy => Self::copy_journaled(&planned.source, &target_path, journal)?,
+        }
+        Ok(MoveOutcome::Moved(target_path))
     }
 
-    fn create_directory_if_not_exists(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        if !path.exists() {
-            fs::create_dir_all(path)?;
-            println!("Created directory: {}", path.display());
+    // Destinations that already exist or are targeted by more than one file
+    fn find_collisions(plan: &HashMap<String, Vec<PlannedMove>>) -> HashSet<PathBuf> {
+        let mut seen = HashSet::new();
+        let mut collisions = HashSet::new();
+
+        for planned in plan.values().flatten() {
+            let exists = fs::symlink_metadata(&planned.destination).is_ok();
+            if !seen.insert(planned.destination.clone()) || exists {
+                collisions.insert(planned.destination.clone());
+            }
+        }
+
+        collisions
+    }
+
+    fn resolve_collision(&self, planned: &PlannedMove, policy: CollisionPolicy, journal: &mut Journal) -> io::Result<CollisionOutcome> {
+        let destination = &planned.destination;
+        let existing = match fs::symlink_metadata(destination) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(CollisionOutcome::Move(destination.clone())),
+        };
+        let incoming = fs::symlink_metadata(&planned.source)?;
+
+        match policy {
+            CollisionPolicy::Skip => Ok(CollisionOutcome::Skipped(format!("{} already exists", destination.display()))),
+            CollisionPolicy::RenameNumbered => Ok(CollisionOutcome::Move(numbered_path(destination))),
+            CollisionPolicy::RenameTimestamp => Ok(CollisionOutcome::Move(timestamped_path(destination, &incoming))),
+            CollisionPolicy::OverwriteIfNewer => {
+                if modified_stamp(&incoming) > modified_stamp(&existing) {
+                    self.set_aside(destination, journal)?;
+                    Ok(CollisionOutcome::Move(destination.clone()))
+                } else {
+                    Ok(CollisionOutcome::Skipped(format!("{} is newer or the same age", destination.display())))
+                }
+            }
+            CollisionPolicy::DedupeIdentical => {
+                if existing.is_file() && files_identical(&planned.source, destination)? {
+                    self.retire_source(&planned.source, journal)?;
+                    Ok(CollisionOutcome::Deduplicated)
+                } else {
+                    Ok(CollisionOutcome::Move(numbered_path(destination)))
+                }
+            }
+        }
+    }
+
+    // Maps every duplicate file to the copy that will be kept
+    fn duplicate_lookup(&self, files: &[PathBuf]) -> HashMap<PathBuf, PathBuf> {
+        if self.duplicate_action == DuplicateAction::Ignore {
+            return HashMap::new();
+        }
+
+        // A matching hash only makes files candidates; quarantine acts on
+        // this alone, so confirm the contents here
+        let mut lookup = HashMap::new();
+        for set in find_duplicates(files) {
+            let original = &set.files[0];
+            for duplicate in &set.files[1..] {
+                if files_identical(duplicate, original).unwrap_or(false) {
+                    lookup.insert(duplicate.clone(), original.clone());
+                }
+            }
+        }
+        lookup
+    }
+
+    // Replaces a duplicate with a hard link to the kept copy at the
+    // duplicate's planned destination. Returns None if the collision policy
+    // skipped it.
+    fn link_duplicate(
+        &self,
+        planned: &PlannedMove,
+        original: &Path,
+        policy: CollisionPolicy,
+        journal: &mut Journal,
+    ) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+        if let Some(parent) = planned.destination.parent() {
+            self.create_directory_if_not_exists(parent, journal)?;
+        }
+
+        let link = match self.resolve_collision(planned, policy, journal)? {
+            CollisionOutcome::Move(path) => path,
+            CollisionOutcome::Skipped(reason) => {
+                println!("⏭ Skipped: {} ({})", planned.source.display(), reason);
+                return Ok(None);
+            }
+            // Already set aside as identical to what sits at the destination
+            CollisionOutcome::Deduplicated => return Ok(Some(planned.destination.clone())),
+        };
+
+        fs::hard_link(original, &link)?;
+        let metadata = fs::symlink_metadata(&link)?;
+        let (size, modified) = (metadata.len(), modified_stamp(&metadata));
+        match self.transfer_mode {
+            // Removing the duplicate frees its space; undo moves the link
+            // back into its place, which restores the same content
+            TransferMode::Move => {
+                if let Err(e) = fs::remove_file(&planned.source) {
+                    let _ = fs::remove_file(&link);
+                    return Err(e.into());
+                }
+                journal.record(&JournalEntry::Moved { source: planned.source.clone(), destination: link.clone(), size, modified })?;
+            }
+            TransferMode::Copy => journal.record(&JournalEntry::Linked { path: link.clone(), size, modified })?,
+        }
+        Ok(Some(link))
+    }
+
+    // Deletes a duplicate that was verified identical to `original`. Copy
+    // mode never touches the originals.
+    fn delete_duplicate(&self, path: &Path, original: &Path, journal: &mut Journal) -> io::Result<()> {
+        if self.transfer_mode == TransferMode::Copy {
+            return Ok(());
+        }
+        let metadata = fs::metadata(original)?;
+        fs::remove_file(path)?;
+        journal.record(&JournalEntry::Deleted {
+            path: path.to_path_buf(),
+            original: original.to_path_buf(),
+            size: metadata.len(),
+            modified: modified_stamp(&metadata),
+        })
+    }
+
+    fn display_duplicates(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let files = self.scan_directory()?;
+        let sets = find_duplicates(&files);
+
+        if sets.is_empty() {
+            println!("No duplicate files found.");
+            return Ok(());
+        }
+
+        println!("\n=== Duplicate Files ===");
+        for (i, set) in sets.iter().enumerate() {
+            println!("\n#{} - {} copies of {:.2} KB ({:.2} KB wasted):",
+                     i + 1, set.files.len(), set.size as f64 / 1024.0, set.wasted_bytes() as f64 / 1024.0);
+            for (j, file) in set.files.iter().enumerate() {
+                let marker = if j == 0 { "keep" } else { "dup " };
+                println!("  [{}] {}", marker, self.relative_path(file));
+            }
         }
+
+        let duplicate_count: usize = sets.iter().map(|s| s.files.len() - 1).sum();
+        let wasted: u64 = sets.iter().map(|s| s.wasted_bytes()).sum();
+        println!("\nDuplicate sets: {}", sets.len());
+        println!("Redundant files: {}", duplicate_count);
+        println!("Wasted space: {:.2} MB", wasted as f64 / 1_048_576.0);
+
         Ok(())
     }
 
-    fn scan_directory(&self) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
-        let mut files = Vec::new();
-        
-        if !self.source_dir.exists() {
+    fn set_aside(&self, path: &Path, journal: &mut Journal) -> io::Result<()> {
+        let dir = journal.set_aside_dir()?;
+        fs::create_dir_all(&dir)?;
+
+        let filename = path.file_name().unwrap_or_default();
+        let mut parked = dir.join(filename);
+        if fs::symlink_metadata(&parked).is_ok() {
+            parked = numbered_path(&parked);
+        }
+        Self::move_journaled(path, &parked, journal)
+    }
+
+    // The journal records the file as it arrived, since a copy across
+    // filesystems can't always carry over every timestamp
+    fn move_journaled(from: &Path, to: &Path, journal: &mut Journal) -> io::Result<()> {
+        move_file(from, to)?;
+        let metadata = fs::symlink_metadata(to)?;
+        journal.record(&JournalEntry::Moved {
+            source: from.to_path_buf(),
+            destination: to.to_path_buf(),
+            size: metadata.len(),
+            modified: modified_stamp(&metadata),
+        })
+    }
+
+    fn copy_journaled(from: &Path, to: &Path, journal: &mut Journal) -> io::Result<()> {
+        copy_verified(from, to)?;
+        let metadata = fs::symlink_metadata(to)?;
+        journal.record(&JournalEntry::Copied {
+            source: from.to_path_buf(),
+            destination: to.to_path_buf(),
+            size: metadata.len(),
+            modified: modified_stamp(&metadata),
+        })
+    }
+
+    // Sets a source file aside once it's been handled; copy mode never
+    // touches the originals
+    fn retire_source(&self, path: &Path, journal: &mut Journal) -> io::Result<()> {
+        match self.transfer_mode {
+            TransferMode::Move => self.set_aside(path, journal),
+            TransferMode::Copy => Ok(()),
+        }
+    }
+
+    // Long-running mode: organize files as they arrive in the source
+    // directory, once they have stopped changing for `settle`.
+    fn watch(&self, settle: std::time::Duration) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.source_dir.is_dir() {
             return Err(format!("Source directory does not exist: {}", self.source_dir.display()).into());
         }
 
-        for entry in fs::read_dir(&self.source_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.is_file() {
-                files.push(path);
-            }
+        let mut watcher = DirectoryWatcher::new(self)?;
+        fs::create_dir_all(&self.target_dir)?;
+        let mut journal = Journal::new(&self.target_dir);
+
+        watch_log(&format!("Watching {} (settle delay {:.1}s). Press Ctrl+C to stop.",
+                           self.source_dir.display(), settle.as_secs_f64()));
+        watch_log(&format!("Journals: {}", journal.dir.display()));
+
+        // Files waiting to settle: when we last saw them change, and their size/mtime then
+        let mut pending: HashMap<PathBuf, (std::time::Instant, u64, u128)> = HashMap::new();
+
+        loop {
+            for path in watcher.poll(self)? {
+                if let Ok(metadata) = fs::symlink_metadata(&path) {
+                    pending.insert(path, (std::time::Instant::now(), metadata.len(), modified_stamp(&metadata)));
+                }
+            }
+
+            let due: Vec<PathBuf> = pending
+                .iter()
+                .filter(|(_, (seen, _, _))| seen.elapsed() >= settle)
+                .map(|(path, _)| path.clone())
+                .collect();
+
+            for path in due {
+                let (_, size, modified) = pending.remove(&path).unwrap();
+                let Ok(metadata) = fs::symlink_metadata(&path) else {
+                    continue;
+                };
+
+                // Still being written without events (e.g. network filesystems)
+                if metadata.len() != size || modified_stamp(&metadata) != modified {
+                    pending.insert(path, (std::time::Instant::now(), metadata.len(), modified_stamp(&metadata)));
+                    continue;
+                }
+
+                if self.accepts_file(&path) {
+                    self.organize_arrival(&path, &mut journal);
+                }
+            }
+
+            std::thread::sleep(WATCH_TICK);
+        }
+    }
+
+    fn organize_arrival(&self, path: &Path, journal: &mut Journal) {
+        let Some(planned) = self.plan_file(path) else {
+            return;
+        };
+        if let Some(mismatch) = &planned.mismatch {
+            watch_log(&format!("⚠️  {}: extension mismatch, {}", self.relative_path(path), mismatch));
+        }
+
+        match self.execute_move(&planned, self.collision_policy, journal) {
+            Ok(MoveOutcome::Moved(target)) => {
+                watch_log(&format!("✓ {}: {} → {} [{}]", self.transfer_mode.past_tense(), path.display(), target.display(), planned.category));
+            }
+            Ok(MoveOutcome::Skipped(reason)) => {
+                watch_log(&format!("⏭ Skipped: {} ({})", path.display(), reason));
+            }
+            Ok(MoveOutcome::Deduplicated) => {
+                watch_log(&format!("♻ Duplicate: {} is identical to {}", path.display(), planned.destination.display()));
+            }
+            Err(e) => watch_log(&format!("✗ Failed to move {}: {}", path.display(), e)),
+        }
+    }
+
+    fn undo_last_run(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let journal_path = match Journal::pending_journals(&self.target_dir).pop() {
+            Some(path) => path,
+            None => {
+                println!("No organization runs to undo in {}.", self.target_dir.display());
+                return Ok(());
+            }
+        };
+
+        let entries = Journal::read_entries(&journal_path)?;
+        println!("\nUndoing {} ({} entries)...", journal_path.display(), entries.len());
+        let report = Self::undo_entries(&entries);
+
+        for path in &report.missing {
+            println!("⚠️  Missing {}: file was moved or deleted since", path.display());
+        }
+        for (entry, reason) in &report.skipped {
+            match entry {
+                JournalEntry::Moved { destination: path, .. }
+                | JournalEntry::Linked { path, .. }
+                | JournalEntry::Copied { destination: path, .. } => {
+                    println!("⚠️  Skipped {}: {}", path.display(), reason)
+                }
+                JournalEntry::Deleted { path, .. } => println!("⚠️  Not restored {}: {}", path.display(), reason),
+                JournalEntry::CreatedDir(dir) => println!("⚠️  Kept directory {}: {}", dir.display(), reason),
+            }
+        }
+
+        println!("\n=== Undo Complete ===");
+        println!("Files restored: {}", report.restored);
+        println!("Copies removed: {}", report.removed_copies);
+        println!("Directories removed: {}", report.removed_dirs);
+        println!("Missing: {}", report.missing.len());
+        println!("Skipped: {}", report.skipped.len());
+
+        // Keep anything that couldn't be undone so a later attempt can retry it
+        let pending_moves = report.skipped
+            .iter()
+            .filter(|(entry, _)| !matches!(entry, JournalEntry::CreatedDir(_)))
+            .count();
+        if pending_moves == 0 {
+            fs::rename(&journal_path, journal_path.with_extension("undone"))?;
+        } else {
+            // Skipped entries were collected in reverse; restore journal order
+            let text: String = report.skipped
+                .iter()
+                .rev()
+                .map(|(entry, _)| format!("{}\n", entry.to_line()))
+                .collect();
+            fs::write(&journal_path, text)?;
+            println!("{} moves remain in {}", pending_moves, journal_path.display());
+        }
+
+        Ok(())
+    }
+
+    fn undo_entries(entries: &[JournalEntry]) -> UndoReport {
+        let mut report = UndoReport::default();
+
+        for entry in entries.iter().rev() {
+            match entry {
+                JournalEntry::Moved { source, destination, size, modified } => {
+                    let metadata = match fs::symlink_metadata(destination) {
+                        Ok(metadata) => metadata,
+                        Err(_) => {
+                            report.missing.push(destination.clone());
+                            continue;
+                        }
+                    };
+                    if metadata.len() != *size || modified_stamp(&metadata) != *modified {
+                        report.skipped.push((entry.clone(), "file was modified since".to_string()));
+                        continue;
+                    }
+                    if fs::symlink_metadata(source).is_ok() {
+                        report.skipped.push((entry.clone(), format!("{} already exists", source.display())));
+                        continue;
+                    }
+
+                    if let Some(parent) = source.parent() {
+                        if let Err(e) = fs::create_dir_all(parent) {
+                            report.skipped.push((entry.clone(), e.to_string()));
+                            continue;
+                        }
+                    }
+                    match move_file(destination, source) {
+                        Ok(_) => {
+                            println!("↩ Restored: {} → {}", destination.display(), source.display());
+                            report.restored += 1;
+                        }
+                        Err(e) => report.skipped.push((entry.clone(), e.to_string())),
+                    }
+                }
+                JournalEntry::Linked { path, size, modified }
+                | JournalEntry::Copied { destination: path, size, modified, .. } => {
+                    let metadata = match fs::symlink_metadata(path) {
+                        Ok(metadata) => metadata,
+                        Err(_) => {
+                            report.missing.push(path.clone());
+                            continue;
+                        }
+                    };
+                    if metadata.len() != *size || modified_stamp(&metadata) != *modified {
+                        report.skipped.push((entry.clone(), "file was modified since".to_string()));
+                        continue;
+                    }
+                    match fs::remove_file(path) {
+                        Ok(_) if matches!(entry, JournalEntry::Copied { .. }) => {
+                            println!("↩ Removed copy: {}", path.display());
+                            report.removed_copies += 1;
+                        }
+                        Ok(_) => {}
+                        Err(e) => report.skipped.push((entry.clone(), e.to_string())),
+                    }
+                }
+                JournalEntry::Deleted { path, original, size, modified } => {
+                    if fs::symlink_metadata(path).is_ok() {
+                        report.skipped.push((entry.clone(), format!("{} already exists", path.display())));
+                        continue;
+                    }
+                    let metadata = match fs::metadata(original) {
+                        Ok(metadata) => metadata,
+                        Err(_) => {
+                            report.missing.push(original.clone());
+                            continue;
+                        }
+                    };
+                    if metadata.len() != *size || modified_stamp(&metadata) != *modified {
+                        report.skipped.push((entry.clone(), format!("{} was modified since", original.display())));
+                        continue;
+                    }
+
+                    if let Some(parent) = path.parent() {
+                        if let Err(e) = fs::create_dir_all(parent) {
+                            report.skipped.push((entry.clone(), e.to_string()));
+                            continue;
+                        }
+                    }
+                    match copy_verified(original, path) {
+                        Ok(_) => {
+                            println!("↩ Restored duplicate: {} (from {})", path.display(), original.display());
+                            report.restored += 1;
+                        }
+                        Err(e) => report.skipped.push((entry.clone(), e.to_string())),
+                    }
+                }
+                JournalEntry::CreatedDir(dir) => {
+                    if !dir.exists() {
+                        continue;
+                    }
+                    // Only remove directories the run created that are empty again
+                    match fs::remove_dir(dir) {
+                        Ok(_) => report.removed_dirs += 1,
+                        Err(_) => report.skipped.push((entry.clone(), "directory is not empty".to_string())),
+                    }
+                }
+            }
+        }
+
+        report
+    }
+
+    fn statistics_report(&self, largest_count: usize) -> Result<DirectoryReport, Box<dyn std::error::Error>> {
+        let mut records = Vec::new();
+        let mut mismatches = Vec::new();
+
+        for file_path in self.scan_directory()? {
+            let category = match self.plan_file(&file_path) {
+                Some(planned) => {
+                    if let Some(mismatch) = planned.mismatch {
+                        mismatches.push((self.relative_path(&file_path), mismatch));
+                    }
+                    planned.category
+                }
+                None => self.extension_category(&file_path),
+            };
+            // Dangling symlinks are reported with the size of the link itself
+            let metadata = match fs::metadata(&file_path).or_else(|_| fs::symlink_metadata(&file_path)) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+
+            records.push(FileRecord {
+                path: self.relative_path(&file_path),
+                category,
+                extension: file_path
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default(),
+                size: metadata.len(),
+                modified_days: modified_days(&metadata),
+            });
         }
 
-        Ok(files)
+        Ok(DirectoryReport {
+            source: self.source_dir.clone(),
+            generated: std::time::SystemTime::now(),
+            files: records,
+            mismatches,
+            largest_count,
+        })
     }
 
-    fn organize_files(&self, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
-        let files = self.scan_directory()?;
-        
-        if files.is_empty() {
+    fn display_statistics(&self, largest_count: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let report = self.statistics_report(largest_count)?;
+
+        if report.files.is_empty() {
             println!("No files found in source directory.");
             return Ok(());
         }
 
-        let mut organization_plan: HashMap<String, Vec<PathBuf>> = HashMap::new();
-        
-        // Analyze files and create organization plan
-        for file_path in &files {
-            let extension = file_path
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("")
-                .to_lowercase();
-            
-            let category = self.get_file_category(&extension);
-            organization_plan
-                .entry(category)
-                .or_insert_with(Vec::new)
-                .push(file_path.clone());
-        }
+        print!("{}", report.to_text());
+        Ok(())
+    }
 
-        // Display organization plan
-        println!("\n=== File Organization Plan ===");
-        for (category, files) in &organization_plan {
-            println!("\n📁 {} ({} files):", category, files.len());
-            for file in files {
-                if let Some(filename) = file.file_name() {
-                    println!("  • {}", filename.to_string_lossy());
-                }
+    // Writes the report to `output`, or to stdout for piping
+    fn export_statistics(
+        &self,
+        format: ReportFormat,
+        largest_count: usize,
+        output: Option<&Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let text = self.statistics_report(largest_count)?.render(format);
+        match output {
+            Some(path) => {
+                fs::write(path, text)?;
+                println!("Report written to {}", path.display());
             }
+            None => print!("{}", text),
         }
+        Ok(())
+    }
+}
 
-        if dry_run {
-            println!("\n🔍 This was a dry run. No files were moved.");
-            return Ok(());
-        }
+fn prompt(message: &str) -> String {
+    print!("{}", message);
+    io::Write::flush(&mut io::stdout()).unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).expect("Failed to read input");
+    input.trim().to_string()
+}
 
-        // Confirm before proceeding
-        print!("\nProceed with file organization? (y/n): ");
-        io::Write::flush(&mut io::stdout()).unwrap();
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        
-        if !input.trim().eq_ignore_ascii_case("y") {
-            println!("Operation cancelled.");
-            return Ok(());
-        }
+fn parse_pattern_list(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
 
-        // Create target directory structure and move files
-        self.create_directory_if_not_exists(&self.target_dir)?;
-        
-        let mut moved_count = 0;
-        let mut error_count = 0;
+fn configure_scan_options(options: &mut ScanOptions, content_detection: &mut ContentDetection) {
+    println!("\nCurrent scan options: {:?}", options);
+    println!("Content detection: {:?}", content_detection);
+    println!("(Press Enter to keep a setting unchanged)");
 
-        for (category, files) in organization_plan {
-            let category_dir = self.target_dir.join(&category);
-            self.create_directory_if_not_exists(&category_dir)?;
+    match prompt("Recurse into subdirectories? (y/n): ").to_lowercase().as_str() {
+        "y" => options.recursive = true,
+        "n" => options.recursive = false,
+        _ => {}
+    }
 
-            for file_path in files {
-                if let Some(filename) = file_path.file_name() {
-                    let target_path = category_dir.join(filename);
-                    
-                    match fs::rename(&file_path, &target_path) {
-                        Ok(_) => {
-                            println!("✓ Moved: {} → {}", 
-                                   file_path.display(), 
-                                   target_path.display());
-                            moved_count += 1;
-                        }
-                        Err(e) => {
-                            println!("✗ Failed to move {}: {}", file_path.display(), e);
-                            error_count += 1;
-                        }
-                    }
-                }
-            }
+    if options.recursive {
+        let depth = prompt("Maximum depth (number, or 'none' for unlimited): ");
+        if depth.eq_ignore_ascii_case("none") {
+            options.max_depth = None;
+        } else if let Ok(depth) = depth.parse::<usize>() {
+            options.max_depth = Some(depth);
+        } else if !depth.is_empty() {
+            println!("Invalid depth, keeping current setting.");
         }
+    }
 
-        println!("\n=== Organization Complete ===");
-        println!("Files moved: {}", moved_count);
-        println!("Errors: {}", error_count);
+    let include = prompt("Include patterns, comma separated (e.g. '*.pdf, docs/**'; '-' to clear): ");
+    if include == "-" {
+        options.include.clear();
+    } else if !include.is_empty() {
+        options.include = parse_pattern_list(&include);
+    }
 
-        Ok(())
+    let exclude = prompt("Exclude patterns, comma separated (e.g. '*.tmp, node_modules'; '-' to clear): ");
+    if exclude == "-" {
+        options.exclude.clear();
+    } else if !exclude.is_empty() {
+        options.exclude = parse_pattern_list(&exclude);
     }
 
-    fn display_statistics(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let files = self.scan_directory()?;
-        
-        if files.is_empty() {
-            println!("No files found in source directory.");
-            return Ok(());
-        }
+    match prompt("Include hidden files? (y/n): ").to_lowercase().as_str() {
+        "y" => options.include_hidden = true,
+        "n" => options.include_hidden = false,
+        _ => {}
+    }
 
-        let mut stats: HashMap<String, usize> = HashMap::new();
-        let mut total_size = 0u64;
+    match prompt("Symlinks: skip, follow, or move the link itself? (skip/follow/link): ").to_lowercase().as_str() {
+        "skip" => options.symlinks = SymlinkPolicy::Skip,
+        "follow" => options.symlinks = SymlinkPolicy::Follow,
+        "link" => options.symlinks = SymlinkPolicy::MoveLink,
+        _ => {}
+    }
 
-        for file_path in &files {
-            let extension = file_path
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("no_extension")
-                .to_lowercase();
-            
-            let category = self.get_file_category(&extension);
-            *stats.entry(category).or_insert(0) += 1;
+    match prompt("Content detection: off, fallback for unknown extensions, or override? (off/fallback/override): ").to_lowercase().as_str() {
+        "off" => *content_detection = ContentDetection::Off,
+        "fallback" => *content_detection = ContentDetection::Fallback,
+        "override" => *content_detection = ContentDetection::Override,
+        _ => {}
+    }
+
+    match prompt("Target layout: flatten into categories or preserve subpaths? (flatten/preserve): ").to_lowercase().as_str() {
+        "flatten" => options.layout = TargetLayout::Flatten,
+        "preserve" => options.layout = TargetLayout::PreserveSubpaths,
+        _ => {}
+    }
+}
+
+const USAGE: &str = "\
+Usage: file_organizer [OPTIONS] [COMMAND]
+
+Commands:
+  stats         Show directory statistics
+  plan          Show the organization plan without moving anything
+  apply         Organize files (requires --yes)
+  undo          Revert the most recent run
+  duplicates    Report duplicate files
+  watch         Organize files as they arrive
+
+Options:
+  -c, --config <FILE>   Load settings, categories and rules from FILE
+  -s, --source <DIR>    Source directory (overrides the config)
+  -t, --target <DIR>    Target directory (overrides the config)
+  -y, --yes             Don't ask for confirmation
+      --copy            Copy files into the target, leaving the originals
+      --settle <SECS>   Settle delay for watch mode (default 2)
+      --format <FMT>    Report format for stats: text, json or csv (default text)
+      --top <N>         Number of largest files in the stats report (default 10)
+  -o, --output <FILE>   Write the stats report to FILE instead of stdout
+  -h, --help            Show this help
+
+Without a command, the interactive menu is started.";
+
+#[derive(Debug, Default)]
+struct CliArgs {
+    config: Option<PathBuf>,
+    source: Option<String>,
+    target: Option<String>,
+    command: Option<String>,
+    yes: bool,
+    copy: bool,
+    settle: Option<std::time::Duration>,
+    format: Option<ReportFormat>,
+    top: Option<usize>,
+    output: Option<PathBuf>,
+    help: bool,
+}
+
+fn parse_args(args: &[String]) -> Result<CliArgs, String> {
+    let mut cli = CliArgs::default();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| iter.next().cloned().ok_or(format!("{} requires a value", name));
 
-            if let Ok(metadata) = fs::metadata(file_path) {
-                total_size += metadata.len();
+        match arg.as_str() {
+            "-c" | "--config" => cli.config = Some(PathBuf::from(value(arg)?)),
+            "-s" | "--source" => cli.source = Some(value(arg)?),
+            "-t" | "--target" => cli.target = Some(value(arg)?),
+            "-y" | "--yes" => cli.yes = true,
+            "--copy" => cli.copy = true,
+            "--settle" => {
+                let settle = value(arg)?;
+                cli.settle = Some(settle.parse().ok()
+                    .and_then(|s| std::time::Duration::try_from_secs_f64(s).ok())
+                    .ok_or(format!("Invalid settle delay '{}'", settle))?);
             }
+            "--format" => {
+                let format = value(arg)?;
+                cli.format = Some(ReportFormat::parse(&format).ok_or(format!("Unknown report format '{}'", format))?);
+            }
+            "--top" => {
+                let top = value(arg)?;
+                cli.top = Some(top.parse().map_err(|_| format!("Invalid file count '{}'", top))?);
+            }
+            "-o" | "--output" => cli.output = Some(PathBuf::from(value(arg)?)),
+            "-h" | "--help" => cli.help = true,
+            "stats" | "plan" | "apply" | "undo" | "duplicates" | "watch" => {
+                if let Some(existing) = &cli.command {
+                    return Err(format!("Multiple commands given: '{}' and '{}'", existing, arg));
+                }
+                cli.command = Some(arg.clone());
+            }
+            other if other.starts_with('-') => return Err(format!("Unknown option '{}'", other)),
+            other => return Err(format!("Unknown command '{}'", other)),
         }
+    }
 
-        println!("\n=== Directory Statistics ===");
-        println!("Source: {}", self.source_dir.display());
-        println!("Total files: {}", files.len());
-        println!("Total size: {:.2} MB", total_size as f64 / 1_048_576.0);
-        
-        println!("\nFile distribution by category:");
-        let mut sorted_stats: Vec<_> = stats.iter().collect();
-        sorted_stats.sort_by(|a, b| b.1.cmp(a.1));
-        
-        for (category, count) in sorted_stats {
-            let percentage = (*count as f64 / files.len() as f64) * 100.0;
-            println!("  {}: {} files ({:.1}%)", category, count, percentage);
+    Ok(cli)
+}
+
+fn run_cli(cli: &CliArgs) -> Result<(), Box<dyn std::error::Error>> {
+    // Reports only read the source, so they don't need a target
+    let read_only = matches!(cli.command.as_deref(), Some("stats") | Some("duplicates"));
+    let mut organizer = match &cli.config {
+        Some(path) => FileOrganizer::from_config(path, cli.source.as_deref(), cli.target.as_deref(), read_only)?,
+        None => {
+            match (&cli.source, &cli.target) {
+                (Some(source), Some(target)) => FileOrganizer::new(source, target),
+                (Some(source), None) if read_only => FileOrganizer::new(source, source),
+                _ => return Err("--source and --target are required without --config".into()),
+            }
         }
+    };
+    organizer.interactive = false;
+    if cli.copy {
+        organizer.transfer_mode = TransferMode::Copy;
+    }
 
-        Ok(())
+    match cli.command.as_deref() {
+        Some("stats") => {
+            let top = cli.top.unwrap_or(DEFAULT_LARGEST_FILES);
+            match (cli.format.unwrap_or(ReportFormat::Text), &cli.output) {
+                (ReportFormat::Text, None) => organizer.display_statistics(top),
+                (format, output) => organizer.export_statistics(format, top, output.as_deref()),
+            }
+        }
+        Some("plan") => organizer.organize_files(true),
+        Some("apply") => {
+            if !cli.yes {
+                return Err("Refusing to move files without confirmation; pass --yes".into());
+            }
+            organizer.organize_files(false)
+        }
+        Some("undo") => organizer.undo_last_run(),
+        Some("duplicates") => organizer.display_duplicates(),
+        Some("watch") => organizer.watch(cli.settle.unwrap_or(std::time::Duration::from_secs(2))),
+        _ => unreachable!("commands are validated by parse_args"),
     }
 }
 
 fn main() {
-    println!("📁 File Organizer");
-    println!("=================");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let cli = match parse_args(&args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    print!("Enter source directory path: ");
-    io::Write::flush(&mut io::stdout()).unwrap();
-    let mut source = String::new();
-    io::stdin().read_line(&mut source).expect("Failed to read input");
-    let source = source.trim();
+    if cli.help {
+        println!("{}", USAGE);
+        return;
+    }
 
-    print!("Enter target directory path: ");
-    io::Write::flush(&mut io::stdout()).unwrap();
-    let mut target = String::new();
-    io::stdin().read_line(&mut target).expect("Failed to read input");
-    let target = target.trim();
+    if cli.command.is_some() {
+        if let Err(e) = run_cli(&cli) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    let organizer = FileOrganizer::new(source, target);
+    println!("📁 File Organizer");
+    println!("=================");
+
+    let mut organizer = match &cli.config {
+        Some(path) => match FileOrganizer::from_config(path, cli.source.as_deref(), cli.target.as_deref(), false) {
+            Ok(organizer) => {
+                println!("Loaded config: {}", path.display());
+                println!("Source: {}", organizer.source_dir.display());
+                println!("Target: {}", organizer.target_dir.display());
+                organizer
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let source = match &cli.source {
+                Some(source) => source.clone(),
+                None => prompt("Enter source directory path: "),
+            };
+            let target = match &cli.target {
+                Some(target) => target.clone(),
+                None => prompt("Enter target directory path: "),
+            };
+            FileOrganizer::new(&source, &target)
+        }
+    };
+    if cli.copy {
+        organizer.transfer_mode = TransferMode::Copy;
+    }
 
     loop {
         println!("\nOptions:");
         println!("1. Show directory statistics");
         println!("2. Preview organization (dry run)");
         println!("3. Organize files");
-        println!("4. Exit");
-        print!("Choose an option (1-4): ");
+        println!("4. Configure scan options");
+        println!("5. Load organization rules");
+        println!("6. Undo last organization");
+        println!("7. Find duplicate files");
+        println!("8. Watch source directory");
+        println!("9. Exit");
+        print!("Choose an option (1-9): ");
         io::Write::flush(&mut io::stdout()).unwrap();
 
         let mut input = String::new();
@@ -256,8 +3819,22 @@ fn main() {
         
         match input.trim() {
             "1" => {
-                if let Err(e) = organizer.display_statistics() {
+                if let Err(e) = organizer.display_statistics(DEFAULT_LARGEST_FILES) {
                     println!("Error: {}", e);
+                    continue;
+                }
+                let format = prompt("Export report? (json/csv/text, Enter to skip): ");
+                if format.is_empty() {
+                    continue;
+                }
+                match ReportFormat::parse(&format) {
+                    Some(format) => {
+                        let path = prompt("Report file path: ");
+                        if let Err(e) = organizer.export_statistics(format, DEFAULT_LARGEST_FILES, Some(Path::new(&path))) {
+                            println!("Error: {}", e);
+                        }
+                    }
+                    None => println!("Unknown format '{}'.", format),
                 }
             }
             "2" => {
@@ -266,17 +3843,791 @@ fn main() {
                 }
             }
             "3" => {
+                let mode = prompt(&format!("Move or copy files? (move/copy, Enter to keep {}): ",
+                                           organizer.transfer_mode.past_tense().to_lowercase()));
+                if !mode.is_empty() {
+                    match TransferMode::parse(&mode) {
+                        Some(mode) => organizer.transfer_mode = mode,
+                        None => println!("Unknown mode, keeping {:?}.", organizer.transfer_mode),
+                    }
+                }
                 if let Err(e) = organizer.organize_files(false) {
                     println!("Error: {}", e);
                 }
             }
-            "4" => {
+            "4" => configure_scan_options(&mut organizer.scan_options, &mut organizer.content_detection),
+            "5" => {
+                let path = prompt("Enter rules file path: ");
+                match RuleEngine::load(Path::new(&path)) {
+                    Ok(rules) => {
+                        println!("Loaded {} rules:", rules.rules.len());
+                        for (i, rule) in rules.rules.iter().enumerate() {
+                            println!("  {}. {} → {} ({})", i + 1, rule.name, rule.category, rule.target);
+                        }
+                        organizer.rules = rules;
+                    }
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            "6" => {
+                if let Err(e) = organizer.undo_last_run() {
+                    println!("Error: {}", e);
+                }
+            }
+            "7" => {
+                if let Err(e) = organizer.display_duplicates() {
+                    println!("Error: {}", e);
+                }
+                let action = prompt("Duplicate handling when organizing (ignore/delete/hardlink/quarantine, Enter to keep): ");
+                if !action.is_empty() {
+                    match DuplicateAction::parse(&action) {
+                        Some(action) => organizer.duplicate_action = action,
+                        None => println!("Unknown action, keeping {:?}.", organizer.duplicate_action),
+                    }
+                }
+            }
+            "8" => {
+                let settle = prompt("Settle delay in seconds (default 2): ");
+                // Negative, NaN or too large for a Duration falls back to the default
+                let settle = settle.parse::<f64>().ok()
+                    .and_then(|s| std::time::Duration::try_from_secs_f64(s).ok())
+                    .unwrap_or(std::time::Duration::from_secs(2));
+                if let Err(e) = organizer.watch(settle) {
+                    println!("Error: {}", e);
+                }
+            }
+            "9" => {
                 println!("Goodbye!");
                 break;
             }
             _ => {
-                println!("Invalid choice! Please enter 1-4.");
+                println!("Invalid choice! Please enter 1-9.");
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh, empty directory under the system temp dir
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file_organizer_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(dir: &Path, relative: &str, contents: &str) -> PathBuf {
+        let path = dir.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.txt", "notes.txt"));
+        assert!(!glob_match("*.txt", "notes.txt.bak"));
+        assert!(!glob_match("*.txt", "dir/notes.txt"));
+        assert!(glob_match("**/*.txt", "notes.txt"));
+        assert!(glob_match("**/*.txt", "a/b/notes.txt"));
+        assert!(glob_match("photos/**", "photos/2024/trip.jpg"));
+        assert!(glob_match("file?.log", "file1.log"));
+        assert!(!glob_match("file?.log", "file10.log"));
+        assert!(glob_match("img[0-9].png", "img7.png"));
+        assert!(!glob_match("img[!0-9].png", "img7.png"));
+        assert!(glob_match("[]]x", "]x"));
+        // An unterminated class is a literal bracket
+        assert!(glob_match("a[b", "a[b"));
+    }
+
+    #[test]
+    fn test_pattern_matches_name_or_path() {
+        assert!(FileOrganizer::pattern_matches("*.tmp", "cache/deep/x.tmp"));
+        assert!(!FileOrganizer::pattern_matches("cache/*.tmp", "cache/deep/x.tmp"));
+        assert!(FileOrganizer::pattern_matches("cache/**/*.tmp", "cache/deep/x.tmp"));
+    }
+
+    #[test]
+    fn test_scan_filters() {
+        let source = temp_dir("scan");
+        touch(&source, "top.txt", "a");
+        touch(&source, ".hidden.txt", "a");
+        touch(&source, "one/mid.txt", "a");
+        touch(&source, "one/two/deep.txt", "a");
+        touch(&source, "one/skip.tmp", "a");
+        touch(&source, "node_modules/lib.txt", "a");
+
+        let mut organizer = FileOrganizer::new(source.to_str().unwrap(), source.join("out").to_str().unwrap());
+        let names = |organizer: &FileOrganizer| -> Vec<String> {
+            organizer.scan_directory().unwrap().iter().map(|path| organizer.relative_path(path)).collect()
+        };
+        assert_eq!(names(&organizer), vec![".hidden.txt", "top.txt"]);
+
+        organizer.scan_options.recursive = true;
+        organizer.scan_options.include_hidden = false;
+        organizer.scan_options.exclude = vec!["node_modules".to_string(), "*.tmp".to_string()];
+        assert_eq!(names(&organizer), vec!["one/mid.txt", "one/two/deep.txt", "top.txt"]);
+
+        organizer.scan_options.max_depth = Some(1);
+        assert_eq!(names(&organizer), vec!["one/mid.txt", "top.txt"]);
+        assert!(!organizer.accepts_file(&source.join("one/two/deep.txt")));
+        assert!(!organizer.accepts_file(&source.join("node_modules/lib.txt")));
+        assert!(organizer.accepts_file(&source.join("one/mid.txt")));
+
+        organizer.scan_options.include = vec!["one/*".to_string()];
+        assert_eq!(names(&organizer), vec!["one/mid.txt"]);
+
+        fs::remove_dir_all(&source).unwrap();
+    }
+
+    #[test]
+    fn test_regex() {
+        let matches = |pattern: &str, text: &str| Regex::new(pattern).unwrap().is_match(text);
+        assert!(matches("^invoice", "invoice_2024.pdf"));
+        assert!(!matches("^invoice", "my_invoice.pdf"));
+        assert!(matches("(?i)^INVOICE", "invoice.pdf"));
+        assert!(matches(r"^IMG_\d{4}\.(jpe?g|png)$", "IMG_0042.jpeg"));
+        assert!(!matches(r"^IMG_\d{4}\.(jpe?g|png)$", "IMG_042.png"));
+        assert!(matches("^a.+?b$", "axxb"));
+        assert!(matches("^[^0-9]+$", "report"));
+        assert!(!matches("^[^0-9]+$", "report1"));
+        assert!(matches("^(ab)*c$", "ababc"));
+        assert!(matches(r"^[\w-]+$", "draft-v2_final"));
+        assert!(matches("^x{2,}$", "xxxx"));
+        assert!(!matches("^x{2,3}$", "xxxx"));
+
+        assert!(Regex::new("(abc").unwrap_err().contains("missing ')'"));
+        assert!(Regex::new("abc)").unwrap_err().contains("unbalanced ')'"));
+        assert!(Regex::new("*a").unwrap_err().contains("nothing to repeat"));
+        assert!(Regex::new("[z-a]").unwrap_err().contains("reversed character range"));
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let tables = parse_config(
+            r#"
+            source = "~/Downloads"  # trailing comment
+            [scan]
+            recursive = true
+            exclude = [
+                "*.tmp",  # temporary files
+                'node_modules',
+            ]
+            [[rule]]
+            name = "Big # files"
+            min_size = 1_000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0].get("source"), Some(&ConfigValue::Str("~/Downloads".to_string())));
+        assert_eq!(tables[1].name, "scan");
+        assert_eq!(tables[1].get("recursive"), Some(&ConfigValue::Bool(true)));
+        assert_eq!(tables[1].get_str_list("exclude").unwrap(), vec!["*.tmp", "node_modules"]);
+        assert!(tables[2].is_array_item);
+        assert_eq!(tables[2].get_str("name").unwrap(), Some("Big # files"));
+        assert_eq!(tables[2].get("min_size"), Some(&ConfigValue::Int(1000)));
+
+        assert!(parse_config("key").unwrap_err().contains("line 1: expected 'key = value'"));
+        assert!(parse_config("\nkey = \"open").unwrap_err().contains("line 2: unterminated string"));
+        assert!(parse_config("key = [1, 2").unwrap_err().contains("unterminated array"));
+        assert!(parse_config("key = maybe").unwrap_err().contains("invalid value 'maybe'"));
+        assert!(parse_config("key = 1 2").unwrap_err().contains("unexpected text after value"));
+    }
+
+    #[test]
+    fn test_calendar_and_sizes() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+        assert_eq!(days_from_civil(2000, 3, 1) - days_from_civil(2000, 2, 28), 2);
+        assert_eq!(parse_date("2023-01-15").unwrap(), days_from_civil(2023, 1, 15));
+        assert!(parse_date("2023-13-01").is_err());
+        assert!(parse_date("yesterday").is_err());
+
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("2KB").unwrap(), 2048);
+        assert_eq!(parse_size("1.5 MiB").unwrap(), 1_572_864);
+        assert_eq!(parse_size("1g").unwrap(), 1 << 30);
+        assert!(parse_size("10 parsecs").unwrap_err().contains("Unknown size unit"));
+        assert!(parse_size("MB").is_err());
+    }
+
+    #[test]
+    fn test_rule_engine() {
+        let engine = RuleEngine::parse(
+            r#"
+            [[rule]]
+            name = "Invoices"
+            extension = [".PDF"]
+            name_regex = "(?i)^invoice"
+            category = "Finance"
+            target = "{category}/{year}/{name}"
+
+            [[rule]]
+            category = "Small"
+            max_size = "1KB"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(engine.rules.len(), 2);
+        assert_eq!(engine.rules[0].extensions, vec!["pdf"]);
+        assert_eq!(engine.rules[1].name, "rule #2");
+        assert_eq!(engine.rules[1].target, DEFAULT_TARGET_TEMPLATE);
+
+        let dir = temp_dir("rules");
+        let invoice = touch(&dir, "Invoice-march.pdf", "%PDF");
+        let notes = touch(&dir, "notes.pdf", "tiny");
+        let big = touch(&dir, "big.bin", &"x".repeat(2048));
+        let rule = |path: &Path| engine.find_rule(path, &fs::metadata(path).unwrap()).map(|rule| rule.name.clone());
+        assert_eq!(rule(&invoice).as_deref(), Some("Invoices"));
+        assert_eq!(rule(&notes).as_deref(), Some("rule #2"));
+        assert_eq!(rule(&big), None);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let error = RuleEngine::parse("[[rule]]\ncategory = \"X\"\ntarget = \"{category}/{size}\"").unwrap_err();
+        assert!(error.contains("unknown placeholder '{size}'"));
+        let error = RuleEngine::parse("[[rule]]\ncategory = \"X\"\ntarget = \"{category}\"").unwrap_err();
+        assert!(error.contains("must include {name} or {stem}"));
+        let error = RuleEngine::parse("[[rule]]\ncategory = \"X\"\nmin_size = 10\nmax_size = 5").unwrap_err();
+        assert!(error.contains("min_size is larger than max_size"));
+        assert!(RuleEngine::parse("[[rule]]\nname = \"x\"").unwrap_err().contains("x: missing 'category'"));
+    }
+
+    #[test]
+    fn test_render_template() {
+        let organizer = FileOrganizer::new("/source", "/target");
+        let render = |template: &str, path: &str| {
+            organizer.render_template(template, Path::new(path), "Documents", None).unwrap()
+        };
+
+        assert_eq!(render(DEFAULT_TARGET_TEMPLATE, "/source/report.txt"), PathBuf::from("Documents/report.txt"));
+        assert_eq!(render("{category}/{year}-{month}-{day}/{stem}.{ext}", "/source/a.txt"), PathBuf::from("Documents/1970-01-01/a.txt"));
+        assert_eq!(render("{category}/{dir}/{name}", "/source/x/y/a.txt"), PathBuf::from("Documents/x/y/a.txt"));
+        assert_eq!(render("{category}/{dir}/{name}", "/source/a.txt"), PathBuf::from("Documents/a.txt"));
+
+        // Braces in file names are copied verbatim, never expanded
+        assert_eq!(render(DEFAULT_TARGET_TEMPLATE, "/source/a{ext}.txt"), PathBuf::from("Documents/a{ext}.txt"));
+        assert_eq!(render("{category}/{stem}", "/source/{name}{dir}.txt"), PathBuf::from("Documents/{name}{dir}"));
+        assert_eq!(render("{category}/{name}", "/source/..{.txt"), PathBuf::from("Documents/..{.txt"));
+        assert_eq!(render("/{category}/../{dir}/./{name}", "/source/x/a.txt"), PathBuf::from("Documents/x/a.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_render_template_keeps_non_utf8_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let organizer = FileOrganizer::new("/source", "/target");
+        let render = |template: &str, path: &[u8]| {
+            organizer.render_template(template, Path::new(OsStr::from_bytes(path)), "Documents", None).unwrap()
+        };
+        assert_eq!(
+            render("{category}/{dir}/{stem}.{ext}", b"/source/d\xffir/caf\xe9.txt"),
+            Path::new(OsStr::from_bytes(b"Documents/d\xffir/caf\xe9.txt"))
+        );
+        // Names that only differ in their invalid bytes don't collide
+        assert_ne!(render(DEFAULT_TARGET_TEMPLATE, b"/source/caf\xe9.txt"), render(DEFAULT_TARGET_TEMPLATE, b"/source/caf\xe8.txt"));
+
+        let dir = temp_dir("non_utf8");
+        let (source, target) = (dir.join("in"), dir.join("out"));
+        fs::create_dir_all(&source).unwrap();
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(source.join(name), "menu").unwrap();
+
+        let mut organizer = FileOrganizer::new(source.to_str().unwrap(), target.to_str().unwrap());
+        organizer.interactive = false;
+        organizer.organize_files(false).unwrap();
+        assert_eq!(fs::read_to_string(target.join("Documents").join(name)).unwrap(), "menu");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_collision_naming() {
+        let dir = temp_dir("naming");
+        let report = touch(&dir, "report.pdf", "v1");
+        touch(&dir, "report_1.pdf", "v2");
+        assert_eq!(numbered_path(&report), dir.join("report_2.pdf"));
+        assert_eq!(numbered_path(&dir.join("README")), dir.join("README_1"));
+
+        // 2023-11-14 22:13:20 UTC
+        let file = fs::File::options().write(true).open(&report).unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)).unwrap();
+        let metadata = fs::metadata(&report).unwrap();
+        let stamped = timestamped_path(&report, &metadata);
+        assert_eq!(stamped, dir.join("report_20231114-221320.pdf"));
+        fs::write(&stamped, "taken").unwrap();
+        assert_eq!(timestamped_path(&report, &metadata), dir.join("report_20231114-221320_1.pdf"));
+
+        assert!(files_identical(&report, &dir.join("report_1.pdf")).is_ok_and(|same| !same));
+        let copy = touch(&dir, "copy.pdf", "v1");
+        assert!(files_identical(&report, &copy).unwrap());
+
+        assert_eq!(CollisionPolicy::parse(" Number "), Some(CollisionPolicy::RenameNumbered));
+        assert_eq!(CollisionPolicy::parse("overwrite-if-newer"), Some(CollisionPolicy::OverwriteIfNewer));
+        assert_eq!(CollisionPolicy::parse("clobber"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_collision_policies() {
+        let dir = temp_dir("collisions");
+        let source = dir.join("in");
+        let target = dir.join("out");
+        let run = |policy: CollisionPolicy, contents: &str| {
+            touch(&source, "notes.txt", contents);
+            let mut organizer = FileOrganizer::new(source.to_str().unwrap(), target.to_str().unwrap());
+            organizer.interactive = false;
+            organizer.collision_policy = policy;
+            organizer.organize_files(false).unwrap();
+        };
+        let documents = target.join("Documents");
+        let read = |name: &str| fs::read_to_string(documents.join(name)).unwrap();
+
+        run(CollisionPolicy::Skip, "first");
+        run(CollisionPolicy::Skip, "second");
+        assert_eq!(read("notes.txt"), "first");
+        assert!(source.join("notes.txt").exists());
+
+        run(CollisionPolicy::RenameNumbered, "second");
+        assert_eq!(read("notes_1.txt"), "second");
+
+        // Identical content is set aside instead of kept twice
+        run(CollisionPolicy::DedupeIdentical, "first");
+        assert!(!source.join("notes.txt").exists());
+        assert!(!documents.join("notes_2.txt").exists());
+
+        // The older file that gets replaced is parked for undo, not deleted
+        let old = fs::File::options().write(true).open(documents.join("notes.txt")).unwrap();
+        old.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000)).unwrap();
+        run(CollisionPolicy::OverwriteIfNewer, "third");
+        assert_eq!(read("notes.txt"), "third");
+        let journal = Journal::pending_journals(&target).pop().unwrap();
+        assert_eq!(fs::read_to_string(journal.with_extension("replaced").join("notes.txt")).unwrap(), "first");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let dir = temp_dir("duplicates");
+        let big = "x".repeat(PARTIAL_HASH_BYTES as usize + 10);
+        let files = vec![
+            touch(&dir, "a.txt", "same"),
+            touch(&dir, "b.txt", "same"),
+            touch(&dir, "c.txt", "diff"),
+            touch(&dir, "empty1", ""),
+            touch(&dir, "empty2", ""),
+            // Same size and first few KiB, different tail
+            touch(&dir, "big1", &format!("{}1", big)),
+            touch(&dir, "big2", &format!("{}2", big)),
+            touch(&dir, "big3", &format!("{}1", big)),
+        ];
+
+        let sets = find_duplicates(&files);
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].files.len(), 2);
+        assert!(sets[0].files.iter().all(|path| path.ends_with("big1") || path.ends_with("big3")));
+        assert_eq!(sets[0].wasted_bytes(), big.len() as u64 + 1);
+        let mut small = sets[1].files.clone();
+        small.sort();
+        assert_eq!(small, vec![dir.join("a.txt"), dir.join("b.txt")]);
+
+        assert_eq!(DuplicateAction::parse("LINK"), Some(DuplicateAction::HardLink));
+        assert_eq!(DuplicateAction::parse("shred"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_actions_reclaim_space() {
+        let dir = temp_dir("reclaim");
+        let source = dir.join("in");
+        let target = dir.join("out");
+        let organize = |action: DuplicateAction| {
+            touch(&source, "a.txt", "same");
+            let copy = touch(&source, "b.txt", "same");
+            // The oldest copy is the one kept
+            let file = fs::File::options().write(true).open(&copy).unwrap();
+            file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+
+            let mut organizer = FileOrganizer::new(source.to_str().unwrap(), target.to_str().unwrap());
+            organizer.interactive = false;
+            organizer.duplicate_action = action;
+            organizer.organize_files(false).unwrap();
+            organizer
+        };
+        let set_aside = || {
+            fs::read_dir(target.join(JOURNAL_DIR)).unwrap().any(|entry| entry.unwrap().path().extension().is_some_and(|ext| ext == "replaced"))
+        };
+
+        let organizer = organize(DuplicateAction::Delete);
+        assert!(target.join("Documents/a.txt").exists());
+        assert!(!target.join("Documents/b.txt").exists());
+        assert!(!source.join("b.txt").exists());
+        assert!(!set_aside());
+        organizer.undo_last_run().unwrap();
+        assert_eq!(fs::read_to_string(source.join("b.txt")).unwrap(), "same");
+        assert_eq!(fs::read_to_string(source.join("a.txt")).unwrap(), "same");
+
+        fs::remove_dir_all(&dir).unwrap();
+        let organizer = organize(DuplicateAction::HardLink);
+        assert!(!source.join("b.txt").exists());
+        assert!(!set_aside());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let kept = fs::metadata(target.join("Documents/a.txt")).unwrap();
+            let link = fs::metadata(target.join("Documents/b.txt")).unwrap();
+            assert_eq!(kept.ino(), link.ino());
+            assert_eq!(kept.nlink(), 2);
+        }
+        organizer.undo_last_run().unwrap();
+        assert_eq!(fs::read_to_string(source.join("b.txt")).unwrap(), "same");
+        assert!(!target.join("Documents").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+        organize(DuplicateAction::Quarantine);
+        assert!(target.join("Documents/a.txt").exists());
+        assert_eq!(fs::read_to_string(target.join(QUARANTINE_CATEGORY).join("b.txt")).unwrap(), "same");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sniff_bytes() {
+        let ftyp = |brand: &[u8]| [b"\0\0\0\x18ftyp".as_slice(), brand, b"\0\0\0\0"].concat();
+        assert_eq!(sniff_bytes(b"\x89PNG\r\n\x1a\n\0\0"), Some("image/png"));
+        assert_eq!(sniff_bytes(b"\xff\xd8\xff\xe0"), Some("image/jpeg"));
+        assert_eq!(sniff_bytes(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff_bytes(b"PK\x03\x04....word/document.xml"), Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document"));
+        assert_eq!(sniff_bytes(b"PK\x03\x04....META-INF/"), Some("application/zip"));
+        assert_eq!(sniff_bytes(b"RIFF\0\0\0\0WAVEfmt "), Some("audio/wav"));
+        assert_eq!(sniff_bytes(b"\x7fELF\x02\x01"), Some("application/x-elf"));
+        assert_eq!(sniff_bytes(b"plain text"), None);
+        assert_eq!(sniff_bytes(b""), None);
+
+        assert_eq!(sniff_bytes(&ftyp(b"isom")), Some("video/mp4"));
+        assert_eq!(sniff_bytes(&ftyp(b"M4V ")), Some("video/mp4"));
+        assert_eq!(sniff_bytes(&ftyp(b"M4A ")), Some("audio/mp4"));
+        assert_eq!(sniff_bytes(&ftyp(b"qt  ")), Some("video/quicktime"));
+        assert_eq!(sniff_bytes(&ftyp(b"heic")), Some("image/heic"));
+        assert_eq!(sniff_bytes(&ftyp(b"mif1")), Some("image/heif"));
+        assert_eq!(sniff_bytes(&ftyp(b"avif")), Some("image/avif"));
+        assert_eq!(sniff_bytes(&ftyp(b"crx ")), None);
+
+        assert_eq!(mime_info("image/heic"), Some(("Images", ["heic", "heif"].as_slice())));
+        assert_eq!(mime_info("text/plain"), None);
+    }
+
+    #[test]
+    fn test_detect_category() {
+        let dir = temp_dir("sniff");
+        let photo = dir.join("IMG_0001.heic");
+        fs::write(&photo, [b"\0\0\0\x18ftypheic".as_slice(), &[0; 16]].concat()).unwrap();
+        let misnamed = dir.join("scan.txt");
+        fs::write(&misnamed, b"%PDF-1.4 ...").unwrap();
+        let extensionless = dir.join("download");
+        fs::write(&extensionless, b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let mut organizer = FileOrganizer::new(dir.to_str().unwrap(), dir.join("out").to_str().unwrap());
+        assert_eq!(organizer.detect_category(&photo), ("Images".to_string(), None));
+        assert_eq!(organizer.detect_category(&extensionless), ("Images".to_string(), None));
+        assert_eq!(
+            organizer.detect_category(&misnamed),
+            ("Documents".to_string(), Some("content is application/pdf, not .txt".to_string()))
+        );
+
+        organizer.content_detection = ContentDetection::Off;
+        assert_eq!(organizer.detect_category(&extensionless), ("Others".to_string(), None));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_watcher_reports_new_files() {
+        let source = temp_dir("watch");
+        fs::create_dir(source.join("existing")).unwrap();
+        let mut organizer = FileOrganizer::new(source.to_str().unwrap(), source.join("out").to_str().unwrap());
+        organizer.scan_options.recursive = true;
+
+        let mut watcher = DirectoryWatcher::new(&organizer).unwrap();
+        assert_eq!(watcher.watched.len(), 2);
+        let top = touch(&source, "top.txt", "a");
+        let nested = touch(&source, "existing/nested.txt", "b");
+        fs::create_dir(source.join("fresh")).unwrap();
+
+        let changed = watcher.poll(&organizer).unwrap();
+        assert!(changed.contains(&top));
+        assert!(changed.contains(&nested));
+        assert_eq!(watcher.watched.len(), 3);
+
+        // A subtree that can't be watched is skipped, not fatal
+        watcher.watch_subtree(&organizer, &source.join("missing"));
+        assert_eq!(watcher.watched.len(), 3);
+        fs::remove_dir_all(&source).unwrap();
+    }
+
+    #[test]
+    fn test_config_target_only_needed_to_move_files() {
+        let dir = temp_dir("config");
+        let config = touch(&dir, "organizer.toml", "source = \"in\"\n");
+
+        let organizer = FileOrganizer::from_config(&config, None, None, true).unwrap();
+        assert_eq!(organizer.source_dir, PathBuf::from("in"));
+        let Err(error) = FileOrganizer::from_config(&config, None, None, false) else {
+            panic!("a target is needed to move files");
+        };
+        assert!(error.contains("No target directory"));
+        assert_eq!(FileOrganizer::from_config(&config, None, Some("out"), false).unwrap().target_dir, PathBuf::from("out"));
+
+        let args: Vec<String> = ["stats", "--config", config.to_str().unwrap()].iter().map(|a| a.to_string()).collect();
+        let cli = parse_args(&args).unwrap();
+        assert_eq!(cli.command.as_deref(), Some("stats"));
+        assert!(parse_args(&["stats".to_string(), "plan".to_string()]).unwrap_err().contains("Multiple commands"));
+
+        let settle = |value: &str| parse_args(&["watch".to_string(), "--settle".to_string(), value.to_string()]).map(|cli| cli.settle);
+        assert_eq!(settle("0.5"), Ok(Some(std::time::Duration::from_millis(500))));
+        for value in ["-1", "inf", "NaN", "1e300"] {
+            assert!(settle(value).unwrap_err().contains("Invalid settle delay"), "{}", value);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_verified() {
+        let dir = temp_dir("copy");
+        let original = touch(&dir, "original.bin", "payload");
+        let stamp = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        fs::File::options().write(true).open(&original).unwrap().set_modified(stamp).unwrap();
+
+        let copy = dir.join("copy.bin");
+        copy_verified(&original, &copy).unwrap();
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "payload");
+        assert_eq!(fs::metadata(&copy).unwrap().modified().unwrap(), stamp);
+        // Never overwrites an existing file
+        assert_eq!(copy_verified(&original, &copy).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+
+        #[cfg(unix)]
+        {
+            let link = dir.join("link");
+            std::os::unix::fs::symlink("original.bin", &link).unwrap();
+            copy_verified(&link, &dir.join("link_copy")).unwrap();
+            assert_eq!(fs::read_link(dir.join("link_copy")).unwrap(), PathBuf::from("original.bin"));
+        }
+
+        let moved = dir.join("moved.bin");
+        move_file(&copy, &moved).unwrap();
+        assert!(!copy.exists());
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "payload");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_report_formatting() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.50 KB");
+        assert_eq!(format_size(5 << 30), "5.00 GB");
+        assert_eq!(format_size(3 << 50), "3072.00 TB");
+
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("say \"hi\"\\now"), "\"say \\\"hi\\\"\\\\now\"");
+        assert_eq!(json_string("a\nb\tc\u{1}"), "\"a\\nb\\tc\\u0001\"");
+        assert_eq!(json_array(&[], "  "), "[]");
+        assert_eq!(json_array(&["    1".to_string(), "    2".to_string()], "  "), "[\n    1,\n    2\n  ]");
+
+        assert_eq!(csv_field("notes.txt"), "notes.txt");
+        assert_eq!(csv_field("a,b.txt"), "\"a,b.txt\"");
+        assert_eq!(csv_field("say \"hi\".txt"), "\"say \"\"hi\"\".txt\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+
+        assert_eq!(ReportFormat::parse(" JSON "), Some(ReportFormat::Json));
+        assert_eq!(ReportFormat::parse("txt"), Some(ReportFormat::Text));
+        assert_eq!(ReportFormat::parse("xml"), None);
+    }
+
+    #[test]
+    fn test_directory_report() {
+        let today = days_from_civil(2024, 6, 1);
+        let record = |path: &str, category: &str, size: u64, age: Option<i64>| FileRecord {
+            path: path.to_string(),
+            category: category.to_string(),
+            extension: path.rsplit_once('.').map(|(_, ext)| ext.to_string()).unwrap_or_default(),
+            size,
+            modified_days: age.map(|age| today - age),
+        };
+        let report = DirectoryReport {
+            source: PathBuf::from("/data"),
+            generated: std::time::UNIX_EPOCH + std::time::Duration::from_secs(today as u64 * 86_400 + 3600),
+            files: vec![
+                record("a.jpg", "Images", 300, Some(0)),
+                record("b.png", "Images", 100, Some(10)),
+                record("c.jpg", "Images", 200, Some(100)),
+                record("report, final.pdf", "Documents", 500, Some(800)),
+                record("empty", "Documents", 0, None),
+            ],
+            mismatches: vec![("b.png".to_string(), "content is image/jpeg, not .png".to_string())],
+            largest_count: 2,
+        };
+
+        assert_eq!(report.total_size(), 1100);
+        let categories = report.categories();
+        assert_eq!(categories.iter().map(|c| (c.name.as_str(), c.files, c.bytes)).collect::<Vec<_>>(),
+                   vec![("Images", 3, 600), ("Documents", 2, 500)]);
+        assert_eq!(categories[0].extensions, vec![("jpg".to_string(), 2, 500), ("png".to_string(), 1, 100)]);
+
+        let largest: Vec<&str> = report.largest().iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(largest, vec!["report, final.pdf", "a.jpg"]);
+        let empty: Vec<&str> = report.empty_files().iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(empty, vec!["empty"]);
+
+        let histogram: Vec<(&str, usize, u64)> = report.age_histogram().iter().map(|b| (b.label, b.files, b.bytes)).collect();
+        assert_eq!(histogram, vec![
+            ("< 1 week", 1, 300),
+            ("1 week - 1 month", 1, 100),
+            ("1-3 months", 0, 0),
+            ("3-12 months", 1, 200),
+            ("1-2 years", 0, 0),
+            ("> 2 years", 1, 500),
+            ("unknown", 1, 0),
+        ]);
+
+        let json = report.render(ReportFormat::Json);
+        assert!(json.contains("\"total_files\": 5,\n  \"total_bytes\": 1100,"));
+        assert!(json.contains("{\"extension\": \"jpg\", \"files\": 2, \"bytes\": 500}"));
+        assert!(json.contains("\"largest_files\": [\n    {\"path\": \"report, final.pdf\", \"bytes\": 500},\n"));
+        assert!(json.contains("\"empty_files\": [\n    \"empty\"\n  ]"));
+        assert!(json.contains("{\"path\": \"b.png\", \"note\": \"content is image/jpeg, not .png\"}"));
+
+        let csv = report.render(ReportFormat::Csv);
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows[0], "path,category,extension,size_bytes,modified,age_range");
+        assert_eq!(rows[1], "a.jpg,Images,jpg,300,2024-06-01,< 1 week");
+        assert_eq!(rows[4], "\"report, final.pdf\",Documents,pdf,500,2022-03-24,> 2 years");
+        assert_eq!(rows[5], "empty,Documents,,0,,unknown");
+
+        let text = report.render(ReportFormat::Text);
+        assert!(text.contains("Total size: 1.07 KB"));
+        assert!(text.contains("  Images: 3 files (60.0%), 600 B (54.5%)"));
+        assert!(text.contains("Largest 2 files:"));
+        assert!(text.contains("Empty files: 1\n  empty"));
+    }
+
+    #[test]
+    fn test_statistics_report() {
+        let dir = temp_dir("stats");
+        touch(&dir, "photos/cat.JPG", "not really a jpeg");
+        touch(&dir, "notes.txt", "hello");
+        touch(&dir, "blank.md", "");
+
+        let mut organizer = FileOrganizer::new(dir.to_str().unwrap(), dir.join("out").to_str().unwrap());
+        organizer.scan_options.recursive = true;
+        let report = organizer.statistics_report(DEFAULT_LARGEST_FILES).unwrap();
+        let mut files: Vec<(&str, &str, u64)> =
+            report.files.iter().map(|f| (f.path.as_str(), f.extension.as_str(), f.size)).collect();
+        files.sort();
+        assert_eq!(files, vec![("blank.md", "md", 0), ("notes.txt", "txt", 5), ("photos/cat.JPG", "jpg", 17)]);
+        assert_eq!(report.empty_files().len(), 1);
+        assert_eq!(report.age_histogram()[0].files, 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_journal_lines() {
+        let entries = vec![
+            JournalEntry::CreatedDir(PathBuf::from("/t/Documents")),
+            JournalEntry::Moved {
+                source: PathBuf::from("/s/tab\there.txt"),
+                destination: PathBuf::from("/t/back\\slash\nnew.txt"),
+                size: 12,
+                modified: 1_700_000_000_123_456_789,
+            },
+            JournalEntry::Linked { path: PathBuf::from("/t/link"), size: 3, modified: 0 },
+            JournalEntry::Copied { source: PathBuf::from("/s/a"), destination: PathBuf::from("/t/a"), size: 1, modified: 2 },
+            JournalEntry::Deleted { path: PathBuf::from("/s/b"), original: PathBuf::from("/t/a"), size: 1, modified: 2 },
+        ];
+        for entry in entries {
+            let line = entry.to_line();
+            assert!(!line.contains('\n'));
+            assert_eq!(JournalEntry::from_line(&line).unwrap(), entry);
+        }
+        assert_eq!(unescape_journal_field("a\\xzz\\"), PathBuf::from("a\\xzz\\"));
+        assert!(JournalEntry::from_line("move\ta\tb\tbig\t0").unwrap_err().contains("invalid size"));
+        assert!(JournalEntry::from_line("rename\ta").is_err());
+
+        let relative = absolute_path(Path::new("some/where.txt"));
+        assert!(relative.is_absolute());
+        assert!(relative.ends_with("some/where.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_journal_non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(OsStr::from_bytes(b"/s/caf\xe9\\x41.txt"));
+        let entry = JournalEntry::Linked { path: path.clone(), size: 1, modified: 2 };
+        let line = entry.to_line();
+        assert_eq!(line, "link\t/s/caf\\xe9\\\\x41.txt\t1\t2");
+        assert_eq!(JournalEntry::from_line(&line).unwrap(), entry);
+
+        let dir = temp_dir("undo_non_utf8");
+        let (source, target) = (dir.join("in"), dir.join("out"));
+        fs::create_dir_all(&source).unwrap();
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(source.join(name), "menu").unwrap();
+
+        let mut organizer = FileOrganizer::new(source.to_str().unwrap(), target.to_str().unwrap());
+        organizer.interactive = false;
+        organizer.organize_files(false).unwrap();
+        assert!(!source.join(name).exists());
+        organizer.undo_last_run().unwrap();
+        assert_eq!(fs::read_to_string(source.join(name)).unwrap(), "menu");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_organize_and_undo() {
+        let dir = temp_dir("undo");
+        let source = dir.join("in");
+        let target = dir.join("out");
+        touch(&source, "report.txt", "quarterly");
+        touch(&source, "photo.jpg", "pixels");
+
+        let mut organizer = FileOrganizer::new(source.to_str().unwrap(), target.to_str().unwrap());
+        organizer.interactive = false;
+        organizer.organize_files(false).unwrap();
+        assert!(target.join("Documents/report.txt").exists());
+        assert!(!source.join("report.txt").exists());
+
+        let journals = Journal::pending_journals(&target);
+        assert_eq!(journals.len(), 1);
+        for entry in Journal::read_entries(&journals[0]).unwrap() {
+            if let JournalEntry::Moved { source, destination, .. } = entry {
+                assert!(source.is_absolute() && destination.is_absolute());
             }
         }
+
+        // A run that only skips files writes no journal to shadow the real one
+        let again = touch(&source, "report.txt", "newer draft");
+        organizer.collision_policy = CollisionPolicy::Skip;
+        organizer.organize_files(false).unwrap();
+        assert_eq!(Journal::pending_journals(&target).len(), 1);
+        fs::remove_file(again).unwrap();
+
+        organizer.undo_last_run().unwrap();
+        assert_eq!(fs::read_to_string(source.join("report.txt")).unwrap(), "quarterly");
+        assert!(source.join("photo.jpg").exists());
+        assert!(!target.join("Documents").exists());
+        assert!(Journal::pending_journals(&target).is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
     }
-}
\ No newline at end of file
+}
@@ -1,23 +1,179 @@
 use std::collections::HashMap;
 use std::io;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
 struct ScanResult {
-    ip: IpAddr,
     port: u16,
     is_open: bool,
     response_time: Option<Duration>,
     service: Option<String>,
 }
 
+// RFC 6298 smoothing gains for the RTT mean and mean deviation
+const RTT_ALPHA: f64 = 0.125;
+const RTT_BETA: f64 = 0.25;
+// How much the timeout rate must grow between rounds to count as congestion
+const LOSS_MARGIN: f64 = 0.1;
+
+/// Outcome of a single connect attempt, as seen by the timing estimator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProbeOutcome {
+    // The host answered (SYN-ACK or RST), giving us a round-trip sample
+    Responded(Duration),
+    // Nothing came back before the connect timeout
+    TimedOut,
+    // Some other local or network error; carries no timing information
+    Failed,
+}
+
+/// Per-host round-trip estimator in the style of TCP's retransmission
+/// timeout: a smoothed mean (SRTT) and mean deviation (RTTVAR) of observed
+/// connect times, with the timeout set to SRTT + 4 * RTTVAR and doubled
+/// on every timeout until the next sample.
+#[derive(Debug, Clone)]
+struct RttEstimator {
+    srtt: Option<f64>,
+    rttvar: f64,
+    timeout: Duration,
+    min_timeout: Duration,
+    max_timeout: Duration,
+}
+
+impl RttEstimator {
+    fn new(initial: Duration, min_timeout: Duration, max_timeout: Duration) -> Self {
+        RttEstimator {
+            srtt: None,
+            rttvar: 0.0,
+            timeout: initial.clamp(min_timeout, max_timeout),
+            min_timeout,
+            max_timeout,
+        }
+    }
+
+    fn observe(&mut self, sample: Duration) {
+        let rtt = sample.as_secs_f64();
+
+        let srtt = match self.srtt {
+            None => {
+                self.rttvar = rtt / 2.0;
+                rtt
+            }
+            Some(srtt) => {
+                self.rttvar = (1.0 - RTT_BETA) * self.rttvar + RTT_BETA * (srtt - rtt).abs();
+                (1.0 - RTT_ALPHA) * srtt + RTT_ALPHA * rtt
+            }
+        };
+        self.srtt = Some(srtt);
+
+        let timeout = Duration::from_secs_f64(srtt + 4.0 * self.rttvar);
+        self.timeout = timeout.clamp(self.min_timeout, self.max_timeout);
+    }
+
+    // RFC 6298 (5.5): each timeout doubles the RTO until a new sample
+    // arrives, so a slow host isn't written off by a timeout tuned to fast
+    // refusals
+    fn back_off(&mut self) {
+        self.timeout = (self.timeout * 2).min(self.max_timeout);
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn smoothed_rtt(&self) -> Option<Duration> {
+        self.srtt.map(Duration::from_secs_f64)
+    }
+}
+
+/// Shared scheduling state for one host: the RTT estimator plus an
+/// AIMD-style window limiting how many probes may be in flight at once.
+///
+/// Probes are counted in rounds of one window each. Filtered ports time out
+/// on healthy hosts too, so a timeout alone isn't a congestion signal; the
+/// window is halved only when a round loses noticeably more probes than the
+/// round before it.
+#[derive(Debug)]
+struct HostTiming {
+    rtt: RttEstimator,
+    window: usize,
+    min_window: usize,
+    max_window: usize,
+    in_flight: usize,
+    next_port: usize,
+    round_probes: usize,
+    round_timeouts: usize,
+    last_loss: f64,
+}
+
+impl HostTiming {
+    fn new(rtt: RttEstimator, min_window: usize, max_window: usize) -> Self {
+        let max_window = max_window.max(1);
+        let min_window = min_window.clamp(1, max_window);
+
+        HostTiming {
+            rtt,
+            window: max_window,
+            min_window,
+            max_window,
+            in_flight: 0,
+            next_port: 0,
+            round_probes: 0,
+            round_timeouts: 0,
+            last_loss: 0.0,
+        }
+    }
+
+    fn record(&mut self, outcome: ProbeOutcome) {
+        match outcome {
+            ProbeOutcome::Responded(rtt) => {
+                self.rtt.observe(rtt);
+                self.window = (self.window + 1).min(self.max_window);
+            }
+            // Timeouts only count as loss once we know the host does answer
+            ProbeOutcome::TimedOut if self.rtt.srtt.is_some() => {
+                self.rtt.back_off();
+                self.round_timeouts += 1;
+            }
+            ProbeOutcome::TimedOut => {
+                self.rtt.back_off();
+                return;
+            }
+            ProbeOutcome::Failed => return,
+        }
+
+        self.round_probes += 1;
+        if self.round_probes >= self.window {
+            let loss = self.round_timeouts as f64 / self.round_probes as f64;
+            if loss > self.last_loss + LOSS_MARGIN {
+                self.window = (self.window / 2).max(self.min_window);
+            }
+            self.last_loss = loss;
+            self.round_probes = 0;
+            self.round_timeouts = 0;
+        }
+    }
+}
+
+/// Timing figures reported once a host scan finishes.
+#[derive(Debug, Clone)]
+struct TimingSummary {
+    smoothed_rtt: Option<Duration>,
+    final_timeout: Duration,
+    final_parallelism: usize,
+}
+
 struct NetworkScanner {
     timeout: Duration,
+    min_timeout: Duration,
+    max_timeout: Duration,
+    adaptive: bool,
     thread_count: usize,
+    min_parallelism: usize,
     common_ports: Vec<u16>,
     port_services: HashMap<u16, String>,
 }
@@ -47,95 +203,151 @@ impl NetworkScanner {
 
         NetworkScanner {
             timeout: Duration::from_millis(1000),
+            min_timeout: Duration::from_millis(50),
+            max_timeout: Duration::from_millis(5000),
+            adaptive: true,
             thread_count: 50,
+            min_parallelism: 4,
             common_ports: vec![21, 22, 23, 25, 53, 80, 110, 143, 443, 993, 995, 3389, 5432, 3306, 1433, 6379, 27017],
             port_services,
         }
     }
 
-    fn scan_port(&self, ip: IpAddr, port: u16) -> ScanResult {
+    fn probe(ip: IpAddr, port: u16, timeout: Duration, port_services: &HashMap<u16, String>) -> (ScanResult, ProbeOutcome) {
         let start_time = Instant::now();
         let socket_addr = SocketAddr::new(ip, port);
-        
-        match TcpStream::connect_timeout(&socket_addr, self.timeout) {
+
+        match TcpStream::connect_timeout(&socket_addr, timeout) {
             Ok(_) => {
                 let response_time = start_time.elapsed();
-                let service = self.port_services.get(&port).cloned();
-                
-                ScanResult {
-                    ip,
+                let service = port_services.get(&port).cloned();
+
+                let result = ScanResult {
                     port,
                     is_open: true,
                     response_time: Some(response_time),
                     service,
-                }
+                };
+                (result, ProbeOutcome::Responded(response_time))
+            }
+            Err(e) => {
+                // A refused connection is still a round trip to the host
+                let (response_time, outcome) = match e.kind() {
+                    io::ErrorKind::ConnectionRefused => {
+                        let elapsed = start_time.elapsed();
+                        (Some(elapsed), ProbeOutcome::Responded(elapsed))
+                    }
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => (None, ProbeOutcome::TimedOut),
+                    _ => (None, ProbeOutcome::Failed),
+                };
+
+                let result = ScanResult {
+                    port,
+                    is_open: false,
+                    response_time,
+                    service: None,
+                };
+                (result, outcome)
             }
-            Err(_) => ScanResult {
-                ip,
-                port,
-                is_open: false,
-                response_time: None,
-                service: None,
-            },
         }
     }
 
-    fn scan_host(&self, ip: IpAddr, ports: &[u16]) -> Vec<ScanResult> {
+    fn host_timing(&self) -> HostTiming {
+        let rtt = if self.adaptive {
+            RttEstimator::new(self.timeout, self.min_timeout, self.max_timeout)
+        } else {
+            RttEstimator::new(self.timeout, self.timeout, self.timeout)
+        };
+        let min_window = if self.adaptive { self.min_parallelism } else { self.thread_count };
+
+        HostTiming::new(rtt, min_window, self.thread_count)
+    }
+
+    fn scan_host(&self, ip: IpAddr, ports: &[u16]) -> (Vec<ScanResult>, TimingSummary) {
         let results = Arc::new(Mutex::new(Vec::new()));
+        let timing = Arc::new((Mutex::new(self.host_timing()), Condvar::new()));
+        let ports: Arc<Vec<u16>> = Arc::new(ports.to_vec());
+        let port_services = Arc::new(self.port_services.clone());
+        let adaptive = self.adaptive;
         let mut handles = Vec::new();
-        
-        let chunk_size = (ports.len() + self.thread_count - 1) / self.thread_count;
-        
-        for chunk in ports.chunks(chunk_size) {
-            let chunk = chunk.to_vec();
+
+        let worker_count = self.thread_count.min(ports.len()).max(1);
+
+        for _ in 0..worker_count {
             let results_clone = Arc::clone(&results);
-            let timeout = self.timeout;
-            let port_services = self.port_services.clone();
-            
+            let timing = Arc::clone(&timing);
+            let ports = Arc::clone(&ports);
+            let port_services = Arc::clone(&port_services);
+
             let handle = thread::spawn(move || {
-                for &port in &chunk {
-                    let start_time = Instant::now();
-                    let socket_addr = SocketAddr::new(ip, port);
-                    
-                    let scan_result = match TcpStream::connect_timeout(&socket_addr, timeout) {
-                        Ok(_) => {
-                            let response_time = start_time.elapsed();
-                            let service = port_services.get(&port).cloned();
-                            
-                            ScanResult {
-                                ip,
-                                port,
-                                is_open: true,
-                                response_time: Some(response_time),
-                                service,
-                            }
+                let (lock, cvar) = &*timing;
+
+                loop {
+                    // Claim the next port once the window has room for it
+                    let (port, timeout) = {
+                        let mut state = lock.lock().unwrap();
+                        while state.next_port < ports.len() && state.in_flight >= state.window {
+                            state = cvar.wait(state).unwrap();
+                        }
+                        if state.next_port >= ports.len() {
+                            break;
                         }
-                        Err(_) => ScanResult {
-                            ip,
-                            port,
-                            is_open: false,
-                            response_time: None,
-                            service: None,
-                        },
+
+                        let port = ports[state.next_port];
+                        state.next_port += 1;
+                        state.in_flight += 1;
+                        (port, state.rtt.timeout())
                     };
-                    
+
+                    let (mut scan_result, mut outcome) = Self::probe(ip, port, timeout, &port_services);
+
+                    // A timed-out port gets one more probe with the backed-off
+                    // timeout before it's reported closed
+                    let mut retry = adaptive;
+                    loop {
+                        let retry_timeout = {
+                            let mut state = lock.lock().unwrap();
+                            if adaptive {
+                                state.record(outcome);
+                            }
+                            if retry && outcome == ProbeOutcome::TimedOut {
+                                Some(state.rtt.timeout())
+                            } else {
+                                state.in_flight -= 1;
+                                None
+                            }
+                        };
+                        cvar.notify_all();
+
+                        let Some(timeout) = retry_timeout else { break };
+                        retry = false;
+                        (scan_result, outcome) = Self::probe(ip, port, timeout, &port_services);
+                    }
+
                     if scan_result.is_open {
                         results_clone.lock().unwrap().push(scan_result);
                     }
                 }
             });
-            
+
             handles.push(handle);
         }
-        
+
         // Wait for all threads to complete
         for handle in handles {
             handle.join().unwrap();
         }
-        
+
+        let state = timing.0.lock().unwrap();
+        let summary = TimingSummary {
+            smoothed_rtt: state.rtt.smoothed_rtt(),
+            final_timeout: state.rtt.timeout(),
+            final_parallelism: state.window,
+        };
+
         let mut final_results = results.lock().unwrap().clone();
         final_results.sort_by_key(|r| r.port);
-        final_results
+        (final_results, summary)
     }
 
     fn scan_network_range(&self, base_ip: &str, start: u8, end: u8) -> HashMap<IpAddr, Vec<ScanResult>> {
@@ -145,7 +357,7 @@ impl NetworkScanner {
             let ip_str = format!("{}.{}", base_ip, i);
             if let Ok(ip) = IpAddr::from_str(&ip_str) {
                 println!("Scanning {}...", ip);
-                let results = self.scan_host(ip, &self.common_ports);
+                let (results, timing) = self.scan_host(ip, &self.common_ports);
                 
                 if !results.is_empty() {
                     network_results.insert(ip, results);
@@ -153,6 +365,9 @@ impl NetworkScanner {
                 } else {
                     println!("  ✗ No open ports found");
                 }
+                if self.adaptive {
+                    println!("  {}", Self::format_timing(&timing));
+                }
             }
         }
         
@@ -183,6 +398,23 @@ impl NetworkScanner {
         }
     }
 
+    fn format_timing(timing: &TimingSummary) -> String {
+        let srtt = timing.smoothed_rtt
+            .map(|t| format!("{:.2}ms", t.as_secs_f64() * 1000.0))
+            .unwrap_or_else(|| "no replies".to_string());
+
+        format!("⏱️  RTT: {}, timeout: {}ms, parallelism: {}",
+                srtt,
+                timing.final_timeout.as_millis(),
+                timing.final_parallelism)
+    }
+
+    fn display_timing(&self, timing: &TimingSummary) {
+        if self.adaptive {
+            println!("\n{}", Self::format_timing(timing));
+        }
+    }
+
     fn display_network_results(&self, network_results: &HashMap<IpAddr, Vec<ScanResult>>) {
         if network_results.is_empty() {
             println!("No hosts with open ports found.");
@@ -243,7 +475,7 @@ fn main() {
     println!("⚠️  Warning: Only scan networks you own or have permission to scan!");
     println!("Unauthorized network scanning may be illegal in your jurisdiction.\n");
 
-    let scanner = NetworkScanner::new();
+    let mut scanner = NetworkScanner::new();
 
     loop {
         println!("Options:");
@@ -252,8 +484,9 @@ fn main() {
         println!("3. Quick scan (common ports)");
         println!("4. Custom port range scan");
         println!("5. Show scanner settings");
-        println!("6. Exit");
-        print!("Choose an option (1-6): ");
+        println!("6. Toggle adaptive timing");
+        println!("7. Exit");
+        print!("Choose an option (1-7): ");
         io::Write::flush(&mut io::stdout()).unwrap();
 
         let mut input = String::new();
@@ -277,10 +510,11 @@ fn main() {
                             Ok(ports) => {
                                 println!("Scanning {} ports on {}...", ports.len(), ip);
                                 let start_time = Instant::now();
-                                let results = scanner.scan_host(ip, &ports);
+                                let (results, timing) = scanner.scan_host(ip, &ports);
                                 let scan_duration = start_time.elapsed();
                                 
                                 scanner.display_results(&results);
+                                scanner.display_timing(&timing);
                                 println!("\nScan completed in {:.2} seconds", scan_duration.as_secs_f64());
                             }
                             Err(e) => println!("Error: {}", e),
@@ -332,10 +566,11 @@ fn main() {
                     Ok(ip) => {
                         println!("Quick scanning {} common ports on {}...", scanner.common_ports.len(), ip);
                         let start_time = Instant::now();
-                        let results = scanner.scan_host(ip, &scanner.common_ports);
+                        let (results, timing) = scanner.scan_host(ip, &scanner.common_ports);
                         let scan_duration = start_time.elapsed();
                         
                         scanner.display_results(&results);
+                        scanner.display_timing(&timing);
                         println!("\nQuick scan completed in {:.2} seconds", scan_duration.as_secs_f64());
                     }
                     Err(e) => println!("Error: {}", e),
@@ -356,10 +591,11 @@ fn main() {
                     (Ok(ip), Ok(ports)) => {
                         println!("Scanning {} custom ports on {}...", ports.len(), ip);
                         let start_time = Instant::now();
-                        let results = scanner.scan_host(ip, &ports);
+                        let (results, timing) = scanner.scan_host(ip, &ports);
                         let scan_duration = start_time.elapsed();
                         
                         scanner.display_results(&results);
+                        scanner.display_timing(&timing);
                         println!("\nCustom scan completed in {:.2} seconds", scan_duration.as_secs_f64());
                     }
                     (Err(e), _) | (_, Err(e)) => println!("Error: {}", e),
@@ -367,14 +603,24 @@ fn main() {
             }
             "5" => {
                 println!("\n⚙️  Scanner Settings:");
-                println!("  Timeout: {}ms", scanner.timeout.as_millis());
-                println!("  Thread count: {}", scanner.thread_count);
+                if scanner.adaptive {
+                    println!("  Timing: adaptive (RTT-based)");
+                    println!("  Initial timeout: {}ms", scanner.timeout.as_millis());
+                    println!("  Timeout bounds: {}-{}ms",
+                             scanner.min_timeout.as_millis(),
+                             scanner.max_timeout.as_millis());
+                    println!("  Parallelism: {}-{} probes", scanner.min_parallelism, scanner.thread_count);
+                } else {
+                    println!("  Timing: fixed");
+                    println!("  Timeout: {}ms", scanner.timeout.as_millis());
+                    println!("  Thread count: {}", scanner.thread_count);
+                }
                 println!("  Common ports: {} ports", scanner.common_ports.len());
                 println!("  Known services: {} mappings", scanner.port_services.len());
                 
                 println!("\n📋 Common ports scanned:");
                 for (i, &port) in scanner.common_ports.iter().enumerate() {
-                    let service = scanner.port_services.get(&port).unwrap_or(&"Unknown".to_string());
+                    let service = scanner.port_services.get(&port).map(String::as_str).unwrap_or("Unknown");
                     print!("{}:{} ", port, service);
                     if (i + 1) % 4 == 0 {
                         println!();
@@ -383,11 +629,15 @@ fn main() {
                 println!();
             }
             "6" => {
+                scanner.adaptive = !scanner.adaptive;
+                println!("Adaptive timing {}.", if scanner.adaptive { "enabled" } else { "disabled" });
+            }
+            "7" => {
                 println!("Goodbye!");
                 break;
             }
             _ => {
-                println!("Invalid choice! Please enter 1-6.");
+                println!("Invalid choice! Please enter 1-7.");
             }
         }
     }
@@ -418,5 +668,161 @@ mod tests {
         assert!(!scanner.common_ports.is_empty());
         assert!(!scanner.port_services.is_empty());
         assert!(scanner.timeout.as_millis() > 0);
+        assert!(scanner.min_timeout <= scanner.timeout && scanner.timeout <= scanner.max_timeout);
+    }
+
+    #[test]
+    fn test_rtt_estimator() {
+        let mut rtt = RttEstimator::new(
+            Duration::from_millis(1000),
+            Duration::from_millis(10),
+            Duration::from_millis(5000),
+        );
+        assert_eq!(rtt.timeout(), Duration::from_millis(1000));
+        assert!(rtt.smoothed_rtt().is_none());
+
+        // First sample: SRTT = R, RTTVAR = R/2, timeout = 3R
+        rtt.observe(Duration::from_millis(20));
+        assert_eq!(rtt.smoothed_rtt(), Some(Duration::from_millis(20)));
+        assert_eq!(rtt.timeout().as_millis(), 60);
+
+        // Steady samples shrink the variance and the timeout follows
+        for _ in 0..50 {
+            rtt.observe(Duration::from_millis(20));
+        }
+        assert!(rtt.timeout() < Duration::from_millis(25));
+        assert!(rtt.timeout() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_rtt_estimator_clamps() {
+        let mut rtt = RttEstimator::new(
+            Duration::from_millis(1000),
+            Duration::from_millis(50),
+            Duration::from_millis(2000),
+        );
+        rtt.observe(Duration::from_millis(1));
+        assert_eq!(rtt.timeout(), Duration::from_millis(50));
+
+        rtt.observe(Duration::from_secs(10));
+        assert_eq!(rtt.timeout(), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn test_rtt_estimator_backs_off() {
+        let mut rtt = RttEstimator::new(
+            Duration::from_millis(1000),
+            Duration::from_millis(50),
+            Duration::from_millis(400),
+        );
+        // Fast refusals pull the timeout down to the floor
+        for _ in 0..20 {
+            rtt.observe(Duration::from_millis(1));
+        }
+        assert_eq!(rtt.timeout(), Duration::from_millis(50));
+
+        // A slow port answering in 150ms times out, and each timeout doubles
+        // the RTO up to the maximum
+        rtt.back_off();
+        assert_eq!(rtt.timeout(), Duration::from_millis(100));
+        rtt.back_off();
+        assert_eq!(rtt.timeout(), Duration::from_millis(200));
+        rtt.back_off();
+        rtt.back_off();
+        assert_eq!(rtt.timeout(), Duration::from_millis(400));
+
+        // The late sample then keeps the timeout above its own round trip
+        rtt.observe(Duration::from_millis(150));
+        assert!(rtt.timeout() > Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_host_timing_timeout_backs_off() {
+        let rtt = RttEstimator::new(
+            Duration::from_millis(1000),
+            Duration::from_millis(50),
+            Duration::from_millis(5000),
+        );
+        let mut timing = HostTiming::new(rtt, 2, 8);
+        for _ in 0..20 {
+            timing.record(ProbeOutcome::Responded(Duration::from_millis(1)));
+        }
+        assert_eq!(timing.rtt.timeout(), Duration::from_millis(50));
+        timing.record(ProbeOutcome::TimedOut);
+        assert_eq!(timing.rtt.timeout(), Duration::from_millis(100));
+        timing.record(ProbeOutcome::Failed);
+        assert_eq!(timing.rtt.timeout(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_host_timing_window() {
+        let rtt = RttEstimator::new(
+            Duration::from_millis(1000),
+            Duration::from_millis(10),
+            Duration::from_millis(5000),
+        );
+        let mut timing = HostTiming::new(rtt, 2, 8);
+        assert_eq!(timing.window, 8);
+
+        // Timeouts before any reply don't shrink the window
+        timing.record(ProbeOutcome::TimedOut);
+        assert_eq!(timing.window, 8);
+
+        // A clean round, then rounds with rising loss
+        for _ in 0..8 {
+            timing.record(ProbeOutcome::Responded(Duration::from_millis(5)));
+        }
+        assert_eq!(timing.window, 8);
+        for _ in 0..4 {
+            timing.record(ProbeOutcome::Responded(Duration::from_millis(5)));
+            timing.record(ProbeOutcome::TimedOut);
+        }
+        assert_eq!(timing.window, 4);
+        for _ in 0..4 {
+            timing.record(ProbeOutcome::TimedOut);
+        }
+        assert_eq!(timing.window, 2);
+
+        // Loss that stops rising doesn't shrink it further
+        for _ in 0..10 {
+            timing.record(ProbeOutcome::TimedOut);
+        }
+        assert_eq!(timing.window, 2);
+
+        timing.record(ProbeOutcome::Responded(Duration::from_millis(5)));
+        assert_eq!(timing.window, 3);
+    }
+
+    #[test]
+    fn test_host_timing_filtered_ports() {
+        let rtt = RttEstimator::new(
+            Duration::from_millis(1000),
+            Duration::from_millis(10),
+            Duration::from_millis(5000),
+        );
+        let mut timing = HostTiming::new(rtt, 4, 32);
+
+        // One open port followed by a long filtered range costs a single halving
+        timing.record(ProbeOutcome::Responded(Duration::from_millis(5)));
+        for _ in 0..200 {
+            timing.record(ProbeOutcome::TimedOut);
+        }
+        assert_eq!(timing.window, 16);
+    }
+
+    #[test]
+    fn test_scan_closed_local_port() {
+        let scanner = NetworkScanner::new();
+        let ip = parse_ip_input("127.0.0.1").unwrap();
+
+        // Bind then drop a listener so the port is known to be closed
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (results, timing) = scanner.scan_host(ip, &[port]);
+        assert!(results.is_empty());
+        assert!(timing.smoothed_rtt.is_some());
     }
 }
\ No newline at end of file
@@ -1,5 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
 use std::io;
+use std::path::PathBuf;
+use std::process;
 
 #[derive(Debug, Clone)]
 struct Item {
@@ -66,7 +70,7 @@ impl Room {
 
         if !self.exits.is_empty() {
             description.push_str("\n\n🚪 Exits:");
-            for (direction, _) in &self.exits {
+            for direction in self.exits.keys() {
                 description.push_str(&format!("\n  • {}", direction));
             }
         }
@@ -137,18 +141,852 @@ impl Player {
     }
 }
 
+// A minimal JSON reader and writer for world and save files
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(HashMap<String, JsonValue>),
+}
+
+impl JsonValue {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_i32(&self) -> Option<i32> {
+        match self {
+            JsonValue::Number(n) if n.fract() == 0.0 && (i32::MIN as f64..=i32::MAX as f64).contains(n) => Some(*n as i32),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    fn as_object(&self) -> Option<&HashMap<String, JsonValue>> {
+        match self {
+            JsonValue::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn write_json(f: &mut fmt::Formatter<'_>, value: &JsonValue, indent: usize) -> fmt::Result {
+    let padding = "  ".repeat(indent + 1);
+    match value {
+        JsonValue::Null => write!(f, "null"),
+        JsonValue::Bool(b) => write!(f, "{}", b),
+        JsonValue::Number(n) => write!(f, "{}", n),
+        JsonValue::String(s) => write_json_string(f, s),
+        JsonValue::Array(array) if array.is_empty() => write!(f, "[]"),
+        JsonValue::Array(array) => {
+            writeln!(f, "[")?;
+            for (i, item) in array.iter().enumerate() {
+                write!(f, "{}", padding)?;
+                write_json(f, item, indent + 1)?;
+                writeln!(f, "{}", if i + 1 < array.len() { "," } else { "" })?;
+            }
+            write!(f, "{}]", "  ".repeat(indent))
+        }
+        JsonValue::Object(object) if object.is_empty() => write!(f, "{{}}"),
+        JsonValue::Object(object) => {
+            // Sorted, so saving the same state twice gives the same file
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+            writeln!(f, "{{")?;
+            for (i, key) in keys.iter().enumerate() {
+                write!(f, "{}", padding)?;
+                write_json_string(f, key)?;
+                write!(f, ": ")?;
+                write_json(f, &object[*key], indent + 1)?;
+                writeln!(f, "{}", if i + 1 < keys.len() { "," } else { "" })?;
+            }
+            write!(f, "{}}}", "  ".repeat(indent))
+        }
+    }
+}
+
+// Pretty-printed, two spaces per level
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_json(f, self, 0)
+    }
+}
+
+struct JsonParser {
+    input: Vec<char>,
+    position: usize,
+}
+
+impl JsonParser {
+    fn new(input: &str) -> Self {
+        JsonParser {
+            input: input.chars().collect(),
+            position: 0,
+        }
+    }
+
+    fn current_char(&self) -> Option<char> {
+        self.input.get(self.position).copied()
+    }
+
+    fn advance(&mut self) {
+        self.position += 1;
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.current_char().is_some_and(char::is_whitespace) {
+            self.advance();
+        }
+    }
+
+    // Line number of the current position, for error messages
+    fn line(&self) -> usize {
+        self.input[..self.position.min(self.input.len())].iter().filter(|&&ch| ch == '\n').count() + 1
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line(), message)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.current_char() != Some(expected) {
+            return Err(self.error(&format!("expected '{}'", expected)));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut result = String::new();
+
+        while let Some(ch) = self.current_char() {
+            self.advance();
+            match ch {
+                '"' => return Ok(result),
+                '\\' => {
+                    let escaped = self.current_char().ok_or_else(|| self.error("unterminated string"))?;
+                    self.advance();
+                    match escaped {
+                        'n' => result.push('\n'),
+                        't' => result.push('\t'),
+                        'r' => result.push('\r'),
+                        'u' => {
+                            let hex: String = self.input.iter().skip(self.position).take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16).map_err(|_| self.error("invalid \\u escape"))?;
+                            result.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                            self.position += 4;
+                        }
+                        c => result.push(c),
+                    }
+                }
+                c => result.push(c),
+            }
+        }
+
+        Err(self.error("unterminated string"))
+    }
+
+    fn parse_number(&mut self) -> Result<f64, String> {
+        let start = self.position;
+        while self.current_char().is_some_and(|ch| ch.is_ascii_digit() || "+-.eE".contains(ch)) {
+            self.advance();
+        }
+        let number: String = self.input[start..self.position].iter().collect();
+        number.parse::<f64>().map_err(|_| self.error(&format!("invalid number: {}", number)))
+    }
+
+    fn parse_array(&mut self) -> Result<Vec<JsonValue>, String> {
+        self.expect('[')?;
+        let mut array = Vec::new();
+        self.skip_whitespace();
+        if self.current_char() == Some(']') {
+            self.advance();
+            return Ok(array);
+        }
+
+        loop {
+            array.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.current_char() {
+                Some(',') => self.advance(),
+                Some(']') => {
+                    self.advance();
+                    return Ok(array);
+                }
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<HashMap<String, JsonValue>, String> {
+        self.expect('{')?;
+        let mut object = HashMap::new();
+        self.skip_whitespace();
+        if self.current_char() == Some('}') {
+            self.advance();
+            return Ok(object);
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            if object.insert(key.clone(), value).is_some() {
+                return Err(self.error(&format!("duplicate key \"{}\"", key)));
+            }
+            self.skip_whitespace();
+            match self.current_char() {
+                Some(',') => self.advance(),
+                Some('}') => {
+                    self.advance();
+                    return Ok(object);
+                }
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn parse_literal(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, String> {
+        let matches = word.chars().enumerate().all(|(i, ch)| self.input.get(self.position + i) == Some(&ch));
+        if !matches {
+            return Err(self.error("invalid literal"));
+        }
+        self.position += word.len();
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        match self.current_char() {
+            Some('"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some('[') => Ok(JsonValue::Array(self.parse_array()?)),
+            Some('{') => Ok(JsonValue::Object(self.parse_object()?)),
+            Some('t') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some('f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some('n') => self.parse_literal("null", JsonValue::Null),
+            Some(ch) if ch.is_ascii_digit() || ch == '-' => Ok(JsonValue::Number(self.parse_number()?)),
+            Some(ch) => Err(self.error(&format!("unexpected character '{}'", ch))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse(&mut self) -> Result<JsonValue, String> {
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+        if self.position < self.input.len() {
+            return Err(self.error("unexpected characters after the world definition"));
+        }
+        Ok(value)
+    }
+}
+
+fn text_field(object: &HashMap<String, JsonValue>, key: &str, context: &str) -> Result<String, String> {
+    match object.get(key) {
+        Some(value) => value.as_str().map(str::to_string).ok_or(format!("{}: \"{}\" must be a string", context, key)),
+        None => Err(format!("{}: missing \"{}\"", context, key)),
+    }
+}
+
+fn items_from_json(value: &JsonValue, context: &str) -> Result<Vec<Item>, String> {
+    let items = value.as_array().ok_or(format!("{}: \"items\" must be a list", context))?;
+    items.iter().map(|item| Item::from_json(item, context)).collect()
+}
+
+fn items_to_json(items: &[Item]) -> JsonValue {
+    JsonValue::Array(items.iter().map(Item::to_json).collect())
+}
+
+// Items and rooms read from world files and round-trip through save files
+impl Item {
+    fn from_json(value: &JsonValue, context: &str) -> Result<Self, String> {
+        let fields = value.as_object().ok_or(format!("{}: each item must be an object", context))?;
+        let name = text_field(fields, "name", context)?;
+        let context = format!("item '{}'", name);
+        let usable = match fields.get("usable") {
+            Some(value) => value.as_bool().ok_or(format!("{}: \"usable\" must be true or false", context))?,
+            None => false,
+        };
+        Ok(Item::new(&name, &text_field(fields, "description", &context)?, usable))
+    }
+
+    fn to_json(&self) -> JsonValue {
+        JsonValue::Object(HashMap::from([
+            ("name".to_string(), JsonValue::String(self.name.clone())),
+            ("description".to_string(), JsonValue::String(self.description.clone())),
+            ("usable".to_string(), JsonValue::Bool(self.usable)),
+        ]))
+    }
+}
+
+impl Room {
+    fn from_json(id: &str, value: &JsonValue) -> Result<Self, String> {
+        let context = format!("room '{}'", id);
+        let fields = value.as_object().ok_or(format!("{} must be an object", context))?;
+        let mut room = Room::new(&text_field(fields, "name", &context)?, &text_field(fields, "description", &context)?);
+
+        if let Some(exits) = fields.get("exits") {
+            let exits = exits.as_object().ok_or(format!("{}: \"exits\" must be an object", context))?;
+            for (direction, target) in exits {
+                let target = target.as_str().ok_or(format!("{}: exit '{}' must name a room", context, direction))?;
+                room.add_exit(direction, target);
+            }
+        }
+        if let Some(items) = fields.get("items") {
+            room.items = items_from_json(items, &context)?;
+        }
+        // Only save files record this
+        if let Some(visited) = fields.get("visited") {
+            room.visited = visited.as_bool().ok_or(format!("{}: \"visited\" must be true or false", context))?;
+        }
+        Ok(room)
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let exits = self.exits.iter().map(|(direction, target)| (direction.clone(), JsonValue::String(target.clone())));
+        JsonValue::Object(HashMap::from([
+            ("name".to_string(), JsonValue::String(self.name.clone())),
+            ("description".to_string(), JsonValue::String(self.description.clone())),
+            ("exits".to_string(), JsonValue::Object(exits.collect())),
+            ("items".to_string(), items_to_json(&self.items)),
+            ("visited".to_string(), JsonValue::Bool(self.visited)),
+        ]))
+    }
+}
+
+// What a rule checks before it fires
+#[derive(Debug, Clone)]
+enum Condition {
+    InRoom(String),
+    Holding(String),
+    FlagSet(String),
+    FlagUnset(String),
+}
+
+// What a rule does, in order
+#[derive(Debug, Clone)]
+enum Effect {
+    Say(String),
+    Health(i32),
+    Score(i32),
+    // Places an item in the current room
+    Spawn(Item),
+    Unlock { room: String, direction: String, target: String },
+    SetFlag(String),
+    ClearFlag(String),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    conditions: Vec<Condition>,
+    effects: Vec<Effect>,
+    // The item stays in the inventory instead of being used up
+    keep: bool,
+}
+
+// How an item behaves when used: the first rule whose conditions all hold
+// fires, and if none do the item stays with the player and `otherwise` is
+// shown
+#[derive(Debug, Clone)]
+struct ItemUse {
+    rules: Vec<Rule>,
+    otherwise: Option<String>,
+}
+
+// A string or a list of strings, as in "holding": "key" or ["key", "map"]
+fn text_list(value: &JsonValue, context: &str, key: &str) -> Result<Vec<String>, String> {
+    let error = || format!("{}: \"{}\" must be a string or a list of strings", context, key);
+    match value {
+        JsonValue::String(text) => Ok(vec![text.clone()]),
+        JsonValue::Array(values) => values.iter().map(|v| v.as_str().map(str::to_string).ok_or_else(error)).collect(),
+        _ => Err(error()),
+    }
+}
+
+impl ItemUse {
+    // Reads the "uses" and "otherwise" of an item definition, registering
+    // any items its rules spawn along the way
+    fn from_json(fields: &HashMap<String, JsonValue>, context: &str, uses: &mut HashMap<String, ItemUse>) -> Result<Self, String> {
+        let rules = match fields.get("uses") {
+            Some(rules) => rules.as_array().ok_or(format!("{}: \"uses\" must be a list of rules", context))?,
+            None => return Err(format!("{}: missing \"uses\"", context)),
+        };
+        let otherwise = match fields.get("otherwise") {
+            Some(_) => Some(text_field(fields, "otherwise", context)?),
+            None => None,
+        };
+        let rules = rules.iter().map(|rule| Rule::from_json(rule, context, uses)).collect::<Result<_, _>>()?;
+        Ok(ItemUse { rules, otherwise })
+    }
+}
+
+impl Rule {
+    fn from_json(value: &JsonValue, context: &str, uses: &mut HashMap<String, ItemUse>) -> Result<Self, String> {
+        let fields = value.as_object().ok_or(format!("{}: each rule must be an object", context))?;
+        if let Some(key) = fields.keys().find(|key| !["when", "do", "keep"].contains(&key.as_str())) {
+            return Err(format!("{}: unknown rule field \"{}\"; expected \"when\", \"do\" or \"keep\"", context, key));
+        }
+
+        let mut conditions = Vec::new();
+        if let Some(when) = fields.get("when") {
+            let when = when.as_object().ok_or(format!("{}: \"when\" must be an object", context))?;
+            let mut keys: Vec<&String> = when.keys().collect();
+            keys.sort();
+            for key in keys {
+                let condition: fn(String) -> Condition = match key.as_str() {
+                    "room" => Condition::InRoom,
+                    "holding" => Condition::Holding,
+                    "flags" => Condition::FlagSet,
+                    "unset" => Condition::FlagUnset,
+                    _ => {
+                        return Err(format!(
+                            "{}: unknown condition \"{}\"; expected \"room\", \"holding\", \"flags\" or \"unset\"",
+                            context, key
+                        ))
+                    }
+                };
+                conditions.extend(text_list(&when[key], context, key)?.into_iter().map(condition));
+            }
+        }
+
+        let effects = match fields.get("do") {
+            Some(effects) => effects.as_array().ok_or(format!("{}: \"do\" must be a list of effects", context))?,
+            None => return Err(format!("{}: each rule needs a \"do\" list", context)),
+        };
+        let effects = effects.iter().map(|effect| Effect::from_json(effect, context, uses)).collect::<Result<_, _>>()?;
+
+        let keep = match fields.get("keep") {
+            Some(keep) => keep.as_bool().ok_or(format!("{}: \"keep\" must be true or false", context))?,
+            None => false,
+        };
+        Ok(Rule { conditions, effects, keep })
+    }
+}
+
+impl Effect {
+    // Each effect is an object with a single key, like {"score": 50}
+    fn from_json(value: &JsonValue, context: &str, uses: &mut HashMap<String, ItemUse>) -> Result<Self, String> {
+        let (kind, argument) = match value.as_object() {
+            Some(fields) if fields.len() == 1 => fields.iter().next().unwrap(),
+            _ => return Err(format!("{}: each effect must be an object with one key, like {{\"say\": \"...\"}}", context)),
+        };
+        let text = || argument.as_str().map(str::to_string).ok_or(format!("{}: \"{}\" takes a string", context, kind));
+        let number = || argument.as_i32().ok_or(format!("{}: \"{}\" takes a whole number", context, kind));
+
+        match kind.as_str() {
+            "say" => Ok(Effect::Say(text()?)),
+            "health" => Ok(Effect::Health(number()?)),
+            "score" => Ok(Effect::Score(number()?)),
+            "spawn" => {
+                let mut item = Item::from_json(argument, context)?;
+                register_item_use(&mut item, argument, uses)?;
+                Ok(Effect::Spawn(item))
+            }
+            "unlock" => {
+                let fields = argument.as_object().ok_or(format!("{}: \"unlock\" takes {{\"room\", \"exit\", \"to\"}}", context))?;
+                Ok(Effect::Unlock {
+                    room: text_field(fields, "room", context)?,
+                    direction: text_field(fields, "exit", context)?,
+                    target: text_field(fields, "to", context)?,
+                })
+            }
+            "set" => Ok(Effect::SetFlag(text()?)),
+            "clear" => Ok(Effect::ClearFlag(text()?)),
+            _ => Err(format!(
+                "{}: unknown effect \"{}\"; expected say, health, score, spawn, unlock, set or clear",
+                context, kind
+            )),
+        }
+    }
+}
+
+// Records how a world-file item is used, keyed by its lowercased name.
+// Items with rules are usable unless the definition says otherwise.
+fn register_item_use(item: &mut Item, value: &JsonValue, uses: &mut HashMap<String, ItemUse>) -> Result<(), String> {
+    let fields = match value.as_object() {
+        Some(fields) if fields.contains_key("uses") => fields,
+        _ => return Ok(()),
+    };
+    let item_use = ItemUse::from_json(fields, &format!("item '{}'", item.name), uses)?;
+    if !fields.contains_key("usable") {
+        item.usable = true;
+    }
+    uses.insert(item.name.to_lowercase(), item_use);
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum WinCondition {
+    HaveItem(String),
+    ReachRoom(String),
+}
+
+// Everything a game needs to start: rooms keyed by id, where the player
+// begins, and what it takes to win
+#[derive(Debug)]
+struct World {
+    title: String,
+    intro: Vec<String>,
+    start: String,
+    rooms: HashMap<String, Room>,
+    win: WinCondition,
+    win_message: String,
+    // Keyed by lowercased item name
+    item_uses: HashMap<String, ItemUse>,
+}
+
+// The Mysterious Manor, bundled so the game runs without a world file
+const DEFAULT_WORLD: &str = r#"{
+  "title": "Mysterious Manor",
+  "intro": [
+    "You find yourself in an old, mysterious manor house.",
+    "Explore the rooms, collect items, and uncover the secrets within!"
+  ],
+  "start": "entrance",
+  "win": { "item": "treasure", "message": "Congratulations! You found the treasure!" },
+  "rooms": {
+    "entrance": {
+      "name": "Entrance Hall",
+      "description": "You stand in a grand entrance hall with marble floors and high ceilings. Dust particles dance in the sunlight streaming through tall windows.",
+      "exits": { "north": "library", "east": "kitchen" },
+      "items": [
+        {
+          "name": "key",
+          "description": "A rusty old key",
+          "uses": [
+            {
+              "when": { "room": "study" },
+              "do": [
+                { "say": "🗝️  You use the key to unlock a secret compartment!" },
+                { "spawn": { "name": "treasure", "description": "A chest full of gold coins!" } },
+                { "score": 50 },
+                { "say": "🎉 +50 points for solving the puzzle!" }
+              ]
+            }
+          ],
+          "otherwise": "The key doesn't seem useful here."
+        }
+      ]
+    },
+    "library": {
+      "name": "Library",
+      "description": "Towering bookshelves line the walls of this quiet library. Ancient tomes and scrolls are scattered across wooden tables.",
+      "exits": { "south": "entrance", "west": "study" },
+      "items": [
+        {
+          "name": "book",
+          "description": "A leather-bound spellbook",
+          "uses": [
+            { "do": [{ "say": "📖 You read the spellbook and learn a new spell! (+20 points)" }, { "score": 20 }] }
+          ]
+        },
+        { "name": "candle", "description": "A flickering candle", "usable": false }
+      ]
+    },
+    "kitchen": {
+      "name": "Kitchen",
+      "description": "A rustic kitchen with copper pots hanging from the ceiling. The smell of herbs and spices fills the air.",
+      "exits": { "west": "entrance", "north": "pantry" },
+      "items": [
+        {
+          "name": "bread",
+          "description": "Fresh baked bread",
+          "uses": [
+            { "do": [{ "health": 20 }, { "say": "🍞 You eat the bread and feel a bit better! (+20 health)" }] }
+          ]
+        }
+      ]
+    },
+    "study": {
+      "name": "Study",
+      "description": "A cozy study with a large oak desk covered in papers and quills. A fireplace crackles warmly in the corner.",
+      "exits": { "east": "library" },
+      "items": [
+        {
+          "name": "scroll",
+          "description": "An ancient scroll with mysterious writing",
+          "uses": [
+            {
+              "do": [
+                { "say": "📜 The scroll reveals the location of hidden treasure!" },
+                { "say": "💡 Hint: Try using the key in the study..." },
+                { "score": 10 }
+              ]
+            }
+          ]
+        }
+      ]
+    },
+    "pantry": {
+      "name": "Pantry",
+      "description": "A small storage room filled with jars, sacks, and preserved foods. It's quite dark and cramped in here.",
+      "exits": { "south": "kitchen" },
+      "items": [
+        {
+          "name": "potion",
+          "description": "A glowing health potion",
+          "uses": [
+            {
+              "do": [
+                { "health": 50 },
+                { "score": 15 },
+                { "say": "🧪 You drink the health potion and feel refreshed! (+50 health, +15 points)" }
+              ]
+            }
+          ]
+        }
+      ]
+    }
+  }
+}"#;
+
+impl World {
+    fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        World::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Parses a JSON world definition and checks that it's playable
+    fn parse(text: &str) -> Result<Self, String> {
+        let root = JsonParser::new(text).parse()?;
+        let root = root.as_object().ok_or("the world must be a JSON object")?;
+
+        let title = text_field(root, "title", "world")?;
+        let start = text_field(root, "start", "world")?;
+        let intro = match root.get("intro") {
+            None => Vec::new(),
+            Some(JsonValue::String(line)) => vec![line.clone()],
+            Some(value) => value
+                .as_array()
+                .and_then(|lines| lines.iter().map(|line| line.as_str().map(str::to_string)).collect())
+                .ok_or("world: \"intro\" must be a string or a list of strings")?,
+        };
+
+        let win = root.get("win").and_then(JsonValue::as_object).ok_or("world: missing \"win\" object")?;
+        let win_message = match win.get("message") {
+            Some(_) => text_field(win, "message", "win")?,
+            None => "Congratulations! You won!".to_string(),
+        };
+        let win = match (win.get("item"), win.get("room")) {
+            (Some(_), None) => WinCondition::HaveItem(text_field(win, "item", "win")?),
+            (None, Some(_)) => WinCondition::ReachRoom(text_field(win, "room", "win")?),
+            _ => return Err("win: give exactly one of \"item\" or \"room\"".to_string()),
+        };
+
+        let room_values = root.get("rooms").and_then(JsonValue::as_object).ok_or("world: missing \"rooms\" object")?;
+        let mut rooms = HashMap::new();
+        let mut item_uses = HashMap::new();
+        for (id, value) in room_values {
+            let mut room = Room::from_json(id, value)?;
+            if let Some(items) = value.as_object().and_then(|fields| fields.get("items")).and_then(JsonValue::as_array) {
+                for (item, value) in room.items.iter_mut().zip(items) {
+                    register_item_use(item, value, &mut item_uses)?;
+                }
+            }
+            rooms.insert(id.clone(), room);
+        }
+
+        let world = World { title, intro, start, rooms, win, win_message, item_uses };
+        world.validate()?;
+        Ok(world)
+    }
+
+    // Reports every problem at once, so a designer can fix them in one pass
+    fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        let mut ids: Vec<&String> = self.rooms.keys().collect();
+        ids.sort();
+
+        if !self.rooms.contains_key(&self.start) {
+            problems.push(format!("the starting room '{}' doesn't exist", self.start));
+        }
+        if let WinCondition::ReachRoom(id) = &self.win {
+            if !self.rooms.contains_key(id) {
+                problems.push(format!("the winning room '{}' doesn't exist", id));
+            }
+        }
+
+        // Rooms that item rules refer to, and the exits and items they create
+        let mut users: Vec<&String> = self.item_uses.keys().collect();
+        users.sort();
+        let mut unlocks = Vec::new();
+        let mut spawns = Vec::new();
+        for user in &users {
+            for rule in &self.item_uses[*user].rules {
+                for condition in &rule.conditions {
+                    if let Condition::InRoom(room) = condition {
+                        if !self.rooms.contains_key(room) {
+                            problems.push(format!("item '{}': rule needs unknown room '{}'", user, room));
+                        }
+                    }
+                }
+                for effect in &rule.effects {
+                    match effect {
+                        Effect::Unlock { room, target, .. } => {
+                            for id in [room, target] {
+                                if !self.rooms.contains_key(id) {
+                                    problems.push(format!("item '{}': unlock refers to unknown room '{}'", user, id));
+                                }
+                            }
+                            unlocks.push((room, target));
+                        }
+                        Effect::Spawn(item) => spawns.push((&item.name, *user)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        for id in &ids {
+            let mut exits: Vec<(&String, &String)> = self.rooms[*id].exits.iter().collect();
+            exits.sort();
+            for (direction, target) in exits {
+                if !self.rooms.contains_key(target) {
+                    problems.push(format!("room '{}': exit '{}' leads to unknown room '{}'", id, direction, target));
+                }
+            }
+        }
+
+        if self.rooms.contains_key(&self.start) {
+            let mut reached = vec![&self.start];
+            let mut index = 0;
+            while index < reached.len() {
+                if let Some(room) = self.rooms.get(reached[index]) {
+                    for target in room.exits.values() {
+                        if !reached.contains(&target) {
+                            reached.push(target);
+                        }
+                    }
+                }
+                // Exits that an item can open count too
+                for (room, target) in &unlocks {
+                    if *room == reached[index] && !reached.contains(target) {
+                        reached.push(target);
+                    }
+                }
+                index += 1;
+            }
+            for id in &ids {
+                if !reached.contains(id) {
+                    problems.push(format!("room '{}' can't be reached from '{}'", id, self.start));
+                }
+            }
+        }
+
+        // Item names are matched case-insensitively, so they must be unique that way
+        let mut seen: HashMap<String, &String> = HashMap::new();
+        for id in &ids {
+            for item in &self.rooms[*id].items {
+                match seen.insert(item.name.to_lowercase(), id) {
+                    Some(first) if first == *id => problems.push(format!("room '{}' has two items named '{}'", id, item.name)),
+                    Some(first) => problems.push(format!("item '{}' appears in both '{}' and '{}'", item.name, first, id)),
+                    None => {}
+                }
+            }
+        }
+        for (name, user) in &spawns {
+            if let Some(room) = seen.get(&name.to_lowercase()) {
+                problems.push(format!("item '{}' is spawned by '{}' but already exists in '{}'", name, user, room));
+            }
+        }
+        // Spawned items' rules are keyed by name too, so a second spawn would replace them
+        let mut spawned: HashMap<String, &String> = HashMap::new();
+        for (name, user) in &spawns {
+            match spawned.insert(name.to_lowercase(), user) {
+                Some(first) if first == *user => problems.push(format!("item '{}' is spawned more than once by '{}'", name, user)),
+                Some(first) => problems.push(format!("item '{}' is spawned by both '{}' and '{}'", name, first, user)),
+                None => {}
+            }
+        }
+
+        if let WinCondition::HaveItem(name) = &self.win {
+            let name = name.to_lowercase();
+            if !seen.contains_key(&name) && !spawns.iter().any(|(spawned, _)| spawned.to_lowercase() == name) {
+                problems.push(format!("the winning item '{}' is nowhere in the world", name));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid world:\n  - {}", problems.join("\n  - ")))
+        }
+    }
+}
+
+// Save files record this so that older saves can be migrated and newer
+// ones rejected
+const SAVE_VERSION: i32 = 2;
+const SAVE_DIR: &str = "saves";
+const AUTOSAVE_SLOT: &str = "autosave";
+
 struct Game {
+    title: String,
+    intro: Vec<String>,
     rooms: HashMap<String, Room>,
     player: Player,
+    win: WinCondition,
+    win_message: String,
+    item_uses: HashMap<String, ItemUse>,
+    // Set and cleared by item rules
+    flags: HashSet<String>,
     game_over: bool,
     commands: Vec<String>,
+    save_dir: PathBuf,
 }
 
 impl Game {
     fn new() -> Self {
-        let mut game = Game {
-            rooms: HashMap::new(),
-            player: Player::new("entrance"),
+        Game::from_world(World::parse(DEFAULT_WORLD).expect("the bundled world is valid"))
+    }
+
+    fn from_world(world: World) -> Self {
+        Game {
+            title: world.title,
+            intro: world.intro,
+            rooms: world.rooms,
+            player: Player::new(&world.start),
+            win: world.win,
+            win_message: world.win_message,
+            item_uses: world.item_uses,
+            flags: HashSet::new(),
             game_over: false,
             commands: vec![
                 "look".to_string(),
@@ -157,61 +995,17 @@ impl Game {
                 "use <item>".to_string(),
                 "inventory".to_string(),
                 "status".to_string(),
+                "save <slot>".to_string(),
+                "load <slot>".to_string(),
                 "help".to_string(),
                 "quit".to_string(),
             ],
-        };
-
-        game.setup_world();
-        game
-    }
-
-    fn setup_world(&mut self) {
-        // Create rooms
-        let mut entrance = Room::new("Entrance Hall", 
-            "You stand in a grand entrance hall with marble floors and high ceilings. \
-             Dust particles dance in the sunlight streaming through tall windows.");
-        entrance.add_exit("north", "library");
-        entrance.add_exit("east", "kitchen");
-        entrance.add_item(Item::new("key", "A rusty old key", true));
-
-        let mut library = Room::new("Library", 
-            "Towering bookshelves line the walls of this quiet library. \
-             Ancient tomes and scrolls are scattered across wooden tables.");
-        library.add_exit("south", "entrance");
-        library.add_exit("west", "study");
-        library.add_item(Item::new("book", "A leather-bound spellbook", true));
-        library.add_item(Item::new("candle", "A flickering candle", false));
-
-        let mut kitchen = Room::new("Kitchen", 
-            "A rustic kitchen with copper pots hanging from the ceiling. \
-             The smell of herbs and spices fills the air.");
-        kitchen.add_exit("west", "entrance");
-        kitchen.add_exit("north", "pantry");
-        kitchen.add_item(Item::new("bread", "Fresh baked bread", true));
-
-        let mut study = Room::new("Study", 
-            "A cozy study with a large oak desk covered in papers and quills. \
-             A fireplace crackles warmly in the corner.");
-        study.add_exit("east", "library");
-        study.add_item(Item::new("scroll", "An ancient scroll with mysterious writing", true));
-
-        let mut pantry = Room::new("Pantry", 
-            "A small storage room filled with jars, sacks, and preserved foods. \
-             It's quite dark and cramped in here.");
-        pantry.add_exit("south", "kitchen");
-        pantry.add_item(Item::new("potion", "A glowing health potion", true));
-
-        // Add rooms to the game
-        self.rooms.insert("entrance".to_string(), entrance);
-        self.rooms.insert("library".to_string(), library);
-        self.rooms.insert("kitchen".to_string(), kitchen);
-        self.rooms.insert("study".to_string(), study);
-        self.rooms.insert("pantry".to_string(), pantry);
+            save_dir: PathBuf::from(SAVE_DIR),
+        }
     }
 
     fn process_command(&mut self, input: &str) {
-        let parts: Vec<&str> = input.trim().split_whitespace().collect();
+        let parts: Vec<&str> = input.split_whitespace().collect();
         
         if parts.is_empty() {
             return;
@@ -244,8 +1038,35 @@ impl Game {
             }
             "inventory" | "inv" | "i" => self.player.show_inventory(),
             "status" | "stats" => self.player.show_status(),
+            "save" => {
+                if parts.len() > 1 {
+                    match self.save(parts[1]) {
+                        Ok(()) => println!("💾 Game saved to slot '{}'.", parts[1]),
+                        Err(e) => println!("❌ Couldn't save: {}", e),
+                    }
+                } else {
+                    println!("Save where? Try 'save slot1'.");
+                }
+            }
+            "load" => {
+                if parts.len() > 1 {
+                    match self.load(parts[1]) {
+                        Ok(()) => {
+                            println!("📂 Loaded slot '{}'.", parts[1]);
+                            self.look_around();
+                        }
+                        Err(e) => println!("❌ Couldn't load: {}", e),
+                    }
+                } else {
+                    println!("Load which slot? Try 'load slot1' or 'load {}'.", AUTOSAVE_SLOT);
+                }
+            }
             "help" | "h" => self.show_help(),
             "quit" | "exit" | "q" => {
+                match self.save(AUTOSAVE_SLOT) {
+                    Ok(()) => println!("💾 Progress saved; type 'load {}' next time to continue.", AUTOSAVE_SLOT),
+                    Err(e) => println!("❌ Couldn't autosave: {}", e),
+                }
                 println!("Thanks for playing! Goodbye! 👋");
                 self.game_over = true;
             }
@@ -258,6 +1079,99 @@ impl Game {
         }
     }
 
+    // Everything that changes during play. Rooms are saved whole, exits
+    // included, and the world's title guards against loading a save into a
+    // different world.
+    fn to_json(&self) -> JsonValue {
+        let player = JsonValue::Object(HashMap::from([
+            ("room".to_string(), JsonValue::String(self.player.current_room.clone())),
+            ("health".to_string(), JsonValue::Number(self.player.health as f64)),
+            ("score".to_string(), JsonValue::Number(self.player.score as f64)),
+            ("inventory".to_string(), items_to_json(&self.player.inventory)),
+        ]));
+        let rooms = self.rooms.iter().map(|(id, room)| (id.clone(), room.to_json()));
+        let mut flags: Vec<&String> = self.flags.iter().collect();
+        flags.sort();
+        JsonValue::Object(HashMap::from([
+            ("version".to_string(), JsonValue::Number(SAVE_VERSION as f64)),
+            ("world".to_string(), JsonValue::String(self.title.clone())),
+            ("player".to_string(), player),
+            ("rooms".to_string(), JsonValue::Object(rooms.collect())),
+            ("flags".to_string(), JsonValue::Array(flags.into_iter().map(|flag| JsonValue::String(flag.clone())).collect())),
+        ]))
+    }
+
+    // Checks the whole save before changing anything, so a bad file leaves
+    // the current game as it was
+    fn restore(&mut self, state: &JsonValue) -> Result<(), String> {
+        let state = state.as_object().ok_or("not a save file")?;
+        match state.get("version").and_then(JsonValue::as_i32) {
+            Some(version) if version > SAVE_VERSION => {
+                return Err(format!("it was made by a newer version of the game (format {}, this game reads up to {})", version, SAVE_VERSION))
+            }
+            Some(version) if version >= 1 => {}
+            _ => return Err("not a save file".to_string()),
+        }
+        let world = text_field(state, "world", "save")?;
+        if world != self.title {
+            return Err(format!("it belongs to '{}', not '{}'", world, self.title));
+        }
+
+        let player = state.get("player").and_then(JsonValue::as_object).ok_or("save: missing \"player\" object")?;
+        let number = |key: &str| {
+            player.get(key).and_then(JsonValue::as_i32).ok_or(format!("player: \"{}\" must be a whole number", key))
+        };
+        let (health, score) = (number("health")?, number("score")?);
+        let current_room = text_field(player, "room", "player")?;
+        let inventory = match player.get("inventory") {
+            Some(items) => items_from_json(items, "player")?,
+            None => Vec::new(),
+        };
+
+        let room_values = state.get("rooms").and_then(JsonValue::as_object).ok_or("save: missing \"rooms\" object")?;
+        let mut rooms = HashMap::new();
+        for (id, value) in room_values {
+            rooms.insert(id.clone(), Room::from_json(id, value)?);
+        }
+        if !rooms.contains_key(&current_room) {
+            return Err(format!("the player is in unknown room '{}'", current_room));
+        }
+        // Version 1 saves predate flags
+        let flags = match state.get("flags") {
+            Some(flags) => text_list(flags, "save", "flags")?.into_iter().collect(),
+            None => HashSet::new(),
+        };
+
+        self.rooms = rooms;
+        self.flags = flags;
+        self.player = Player { current_room, inventory, health, score };
+        Ok(())
+    }
+
+    // Slots are plain names, so a save can't land outside the save folder
+    fn save_path(&self, slot: &str) -> Result<PathBuf, String> {
+        if slot.is_empty() || !slot.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_') {
+            return Err(format!("'{}' isn't a valid slot name; use letters, digits, '-' and '_'", slot));
+        }
+        Ok(self.save_dir.join(format!("{}.json", slot)))
+    }
+
+    fn save(&self, slot: &str) -> Result<(), String> {
+        let path = self.save_path(slot)?;
+        fs::create_dir_all(&self.save_dir).map_err(|e| format!("{}: {}", self.save_dir.display(), e))?;
+        // Write to a temporary file first so a crash can't leave half a save
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, format!("{}\n", self.to_json())).map_err(|e| format!("{}: {}", temporary.display(), e))?;
+        fs::rename(&temporary, &path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn load(&mut self, slot: &str) -> Result<(), String> {
+        let path = self.save_path(slot)?;
+        let text = fs::read_to_string(&path).map_err(|_| format!("there's no save in slot '{}'", slot))?;
+        let state = JsonParser::new(&text).parse().map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.restore(&state)
+    }
+
     fn look_around(&mut self) {
         if let Some(room) = self.rooms.get_mut(&self.player.current_room) {
             room.visited = true;
@@ -277,7 +1191,7 @@ impl Game {
                 // Award points for exploring new rooms
                 if let Some(new_room) = self.rooms.get(&self.player.current_room) {
                     if !new_room.visited {
-                        self.player.score += 10;
+                        self.player.score = self.player.score.saturating_add(10);
                         println!("🎉 +10 points for discovering a new area!");
                     }
                 }
@@ -292,7 +1206,7 @@ impl Game {
         
         if let Some(room) = self.rooms.get_mut(&current_room_id) {
             if let Some(item) = room.remove_item(item_name) {
-                self.player.score += 5;
+                self.player.score = self.player.score.saturating_add(5);
                 println!("🎉 +5 points for finding an item!");
                 self.player.add_item(item);
             } else {
@@ -302,46 +1216,71 @@ impl Game {
     }
 
     fn use_item(&mut self, item_name: &str) {
-        if let Some(item) = self.player.use_item(item_name) {
-            match item.name.as_str() {
-                "potion" => {
-                    self.player.health = (self.player.health + 50).min(100);
-                    self.player.score += 15;
-                    println!("🧪 You drink the health potion and feel refreshed! (+50 health, +15 points)");
+        let item = match self.player.use_item(item_name) {
+            Some(item) => item,
+            None => {
+                // Player::use_item has already explained an unusable item
+                if !self.player.has_item(item_name) {
+                    println!("You don't have a '{}'.", item_name);
                 }
-                "key" => {
-                    if self.player.current_room == "study" {
-                        println!("🗝️  You use the key to unlock a secret compartment!");
-                        if let Some(study) = self.rooms.get_mut("study") {
-                            study.add_item(Item::new("treasure", "A chest full of gold coins!", false));
-                            self.player.score += 50;
-                            println!("🎉 +50 points for solving the puzzle!");
-                        }
-                    } else {
-                        println!("The key doesn't seem useful here.");
-                        self.player.inventory.push(item); // Put it back
-                    }
+                return;
+            }
+        };
+
+        let item_use = self.item_uses.get(&item.name.to_lowercase());
+        let rule = item_use
+            .and_then(|item_use| item_use.rules.iter().find(|rule| rule.conditions.iter().all(|c| self.condition_holds(c))))
+            .cloned();
+        match rule {
+            Some(rule) => {
+                if rule.keep {
+                    self.player.inventory.push(item);
                 }
-                "book" => {
-                    println!("📖 You read the spellbook and learn a new spell! (+20 points)");
-                    self.player.score += 20;
+                for effect in rule.effects {
+                    self.apply_effect(effect);
                 }
-                "bread" => {
-                    self.player.health = (self.player.health + 20).min(100);
-                    println!("🍞 You eat the bread and feel a bit better! (+20 health)");
+            }
+            None => {
+                match item_use.and_then(|item_use| item_use.otherwise.as_ref()) {
+                    Some(text) => println!("{}", text),
+                    None => println!("You can't figure out how to use the {}.", item.name),
                 }
-                "scroll" => {
-                    println!("📜 The scroll reveals the location of hidden treasure!");
-                    println!("💡 Hint: Try using the key in the study...");
-                    self.player.score += 10;
+                self.player.inventory.push(item); // Put it back
+            }
+        }
+    }
+
+    fn condition_holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::InRoom(id) => self.player.current_room == *id,
+            Condition::Holding(name) => self.player.has_item(name),
+            Condition::FlagSet(flag) => self.flags.contains(flag),
+            Condition::FlagUnset(flag) => !self.flags.contains(flag),
+        }
+    }
+
+    fn apply_effect(&mut self, effect: Effect) {
+        match effect {
+            Effect::Say(text) => println!("{}", text),
+            // World files can give any i32, so saturate rather than overflow
+            Effect::Health(amount) => self.player.health = self.player.health.saturating_add(amount).min(100),
+            Effect::Score(amount) => self.player.score = self.player.score.saturating_add(amount),
+            Effect::Spawn(item) => {
+                if let Some(room) = self.rooms.get_mut(&self.player.current_room) {
+                    room.add_item(item);
                 }
-                _ => {
-                    println!("You can't figure out how to use the {}.", item.name);
-                    self.player.inventory.push(item); // Put it back
+            }
+            Effect::Unlock { room, direction, target } => {
+                if let Some(room) = self.rooms.get_mut(&room) {
+                    room.add_exit(&direction, &target);
                 }
             }
-        } else {
-            println!("You don't have a '{}'.", item_name);
+            Effect::SetFlag(flag) => {
+                self.flags.insert(flag);
+            }
+            Effect::ClearFlag(flag) => {
+                self.flags.remove(&flag);
+            }
         }
     }
 
@@ -355,15 +1294,23 @@ impl Game {
     }
 
     fn check_win_condition(&self) -> bool {
-        self.player.has_item("treasure")
+        match &self.win {
+            WinCondition::HaveItem(name) => self.player.has_item(name),
+            WinCondition::ReachRoom(id) => self.player.current_room == *id,
+        }
     }
 
     fn run(&mut self) {
-        println!("🏰 Welcome to the Mysterious Manor!");
-        println!("===================================");
-        println!("You find yourself in an old, mysterious manor house.");
-        println!("Explore the rooms, collect items, and uncover the secrets within!");
-        println!("Type 'help' for available commands.\n");
+        println!("🏰 Welcome to the {}!", self.title);
+        println!("{}", "=".repeat(self.title.chars().count() + 19));
+        for line in &self.intro {
+            println!("{}", line);
+        }
+        println!("Type 'help' for available commands.");
+        if self.save_path(AUTOSAVE_SLOT).is_ok_and(|path| path.exists()) {
+            println!("💾 Type 'load {}' to continue your last game.", AUTOSAVE_SLOT);
+        }
+        println!();
 
         self.look_around();
 
@@ -383,7 +1330,7 @@ impl Game {
 
             // Check win condition
             if self.check_win_condition() {
-                println!("\n🎊 Congratulations! You found the treasure!");
+                println!("\n🎊 {}", self.win_message);
                 println!("🏆 Final Score: {} points", self.player.score);
                 println!("Thanks for playing!");
                 self.game_over = true;
@@ -400,7 +1347,17 @@ impl Game {
 }
 
 fn main() {
-    let mut game = Game::new();
+    // An optional world file replaces the bundled manor
+    let mut game = match std::env::args().nth(1) {
+        Some(path) => match World::load(&path) {
+            Ok(world) => Game::from_world(world),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                process::exit(1);
+            }
+        },
+        None => Game::new(),
+    };
     game.run();
 }
 
@@ -438,4 +1395,260 @@ mod tests {
         assert_eq!(player.inventory.len(), 1);
         assert!(player.has_item("test_item"));
     }
+
+    const CAVE: &str = r#"{
+        "title": "Tiny Cave",
+        "start": "mouth",
+        "win": { "room": "exit" },
+        "rooms": {
+            "mouth": { "name": "Cave Mouth", "description": "Light behind you.", "exits": { "in": "tunnel" } },
+            "tunnel": {
+                "name": "Tunnel",
+                "description": "Dark and damp.",
+                "exits": { "out": "mouth", "on": "exit" },
+                "items": [{ "name": "rope", "description": "Coiled rope", "usable": true }]
+            },
+            "exit": { "name": "Exit", "description": "Daylight!" }
+        }
+    }"#;
+
+    #[test]
+    fn test_default_world_is_valid() {
+        let world = World::parse(DEFAULT_WORLD).unwrap();
+        assert_eq!(world.rooms.len(), 5);
+        assert_eq!(world.start, "entrance");
+        assert_eq!(world.win, WinCondition::HaveItem("treasure".to_string()));
+        assert_eq!(world.rooms["library"].exits["west"], "study");
+    }
+
+    #[test]
+    fn test_world_from_json() {
+        let mut game = Game::from_world(World::parse(CAVE).unwrap());
+        assert_eq!(game.title, "Tiny Cave");
+        assert_eq!(game.player.current_room, "mouth");
+        assert!(game.rooms["tunnel"].items[0].usable);
+
+        game.go("in");
+        game.go("on");
+        assert!(game.check_win_condition());
+    }
+
+    #[test]
+    fn test_world_validation() {
+        let broken = CAVE
+            .replace(r#""on": "exit""#, r#""on": "cellar""#)
+            .replace(r#""description": "Daylight!""#, r#""description": "Daylight!", "items": [{ "name": "Rope", "description": "More rope" }]"#);
+        let error = World::parse(&broken).unwrap_err();
+        assert!(error.contains("exit 'on' leads to unknown room 'cellar'"));
+        assert!(error.contains("room 'exit' can't be reached from 'mouth'"));
+        assert!(error.contains("item 'rope' appears in both 'exit' and 'tunnel'"));
+
+        let error = World::parse(&CAVE.replace(r#""start": "mouth""#, r#""start": "attic""#)).unwrap_err();
+        assert!(error.contains("the starting room 'attic' doesn't exist"));
+    }
+
+    #[test]
+    fn test_world_parse_errors() {
+        assert!(World::parse("{ \"title\": ").unwrap_err().contains("unexpected end of input"));
+        assert!(World::parse(&CAVE.replace(r#""name": "Tunnel","#, "")).unwrap_err().contains("room 'tunnel': missing \"name\""));
+        assert!(World::parse(&CAVE.replace(r#"{ "room": "exit" }"#, "{}")).unwrap_err().contains("exactly one of"));
+    }
+
+    // The exit only opens once the lever is pulled with the lamp lit
+    const VAULT: &str = r#"{
+        "title": "Vault",
+        "start": "hall",
+        "win": { "room": "vault" },
+        "rooms": {
+            "hall": {
+                "name": "Hall",
+                "description": "A lever juts from the wall.",
+                "items": [
+                    {
+                        "name": "lamp",
+                        "description": "An oil lamp",
+                        "uses": [
+                            { "when": { "unset": "lit" }, "do": [{ "set": "lit" }, { "say": "The lamp flickers on." }], "keep": true },
+                            { "do": [{ "clear": "lit" }], "keep": true }
+                        ]
+                    },
+                    {
+                        "name": "lever",
+                        "description": "A heavy lever",
+                        "uses": [
+                            {
+                                "when": { "room": "hall", "holding": "lamp", "flags": "lit" },
+                                "do": [
+                                    { "unlock": { "room": "hall", "exit": "down", "to": "vault" } },
+                                    { "spawn": { "name": "coin", "description": "A gold coin", "uses": [{ "do": [{ "score": 1 }] }] } },
+                                    { "health": -30 }
+                                ]
+                            }
+                        ],
+                        "otherwise": "It's too dark to find the lever."
+                    }
+                ]
+            },
+            "vault": { "name": "Vault", "description": "Gold everywhere." }
+        }
+    }"#;
+
+    #[test]
+    fn test_key_puzzle() {
+        let mut game = Game::new();
+        game.take_item("key");
+        game.use_item("key");
+        assert!(game.player.has_item("key"));
+        assert_eq!(game.player.score, 5);
+
+        game.go("north");
+        game.go("west");
+        game.use_item("key");
+        assert!(!game.player.has_item("key"));
+        assert_eq!(game.player.score, 55);
+        game.take_item("treasure");
+        assert!(game.check_win_condition());
+
+        // Items without rules still can't be used
+        assert!(!game.rooms["library"].items[1].usable);
+    }
+
+    #[test]
+    fn test_item_rules() {
+        let mut game = Game::from_world(World::parse(VAULT).unwrap());
+        assert!(game.rooms["hall"].items.iter().all(|item| item.usable));
+        game.take_item("lever");
+        game.use_item("lever");
+        assert!(game.player.has_item("lever"));
+        assert!(game.rooms["hall"].exits.is_empty());
+
+        game.take_item("lamp");
+        game.use_item("lamp");
+        assert!(game.flags.contains("lit"));
+        game.use_item("lamp");
+        assert!(game.flags.is_empty());
+        game.use_item("lamp");
+
+        game.use_item("lever");
+        assert!(!game.player.has_item("lever"));
+        assert!(game.player.has_item("lamp"));
+        assert_eq!(game.player.health, 70);
+        assert_eq!(game.rooms["hall"].items[0].name, "coin");
+        game.take_item("coin");
+        game.use_item("coin");
+        assert_eq!(game.player.score, 16);
+
+        game.go("down");
+        assert!(game.check_win_condition());
+
+        game.apply_effect(Effect::Score(i32::MAX));
+        game.take_item("coin");
+        assert_eq!(game.player.score, i32::MAX);
+        game.apply_effect(Effect::Health(i32::MAX));
+        assert_eq!(game.player.health, 100);
+        game.apply_effect(Effect::Health(i32::MIN));
+        game.apply_effect(Effect::Health(i32::MIN));
+        assert_eq!(game.player.health, i32::MIN);
+    }
+
+    #[test]
+    fn test_item_rule_validation() {
+        // The vault is only reachable through the lever's unlock
+        assert!(World::parse(&VAULT.replace(r#""to": "vault""#, r#""to": "hall""#))
+            .unwrap_err()
+            .contains("room 'vault' can't be reached from 'hall'"));
+
+        let broken = VAULT.replace(r#""room": "hall", "holding""#, r#""room": "cellar", "holding""#).replace(r#""coin""#, r#""Lamp""#);
+        let error = World::parse(&broken).unwrap_err();
+        assert!(error.contains("item 'lever': rule needs unknown room 'cellar'"));
+        assert!(error.contains("item 'Lamp' is spawned by 'lever' but already exists in 'hall'"));
+
+        let error = World::parse(&VAULT.replace(r#"{ "health": -30 }"#, r#"{ "health": "lots" }"#)).unwrap_err();
+        assert!(error.contains("item 'lever': \"health\" takes a whole number"));
+        let error = World::parse(&VAULT.replace(r#"{ "set": "lit" }"#, r#"{ "set": "lit", "say": "hi" }"#)).unwrap_err();
+        assert!(error.contains("each effect must be an object with one key"));
+        assert!(World::parse(&VAULT.replace(r#""unset""#, r#""lacking""#)).unwrap_err().contains("unknown condition \"lacking\""));
+
+        let coin = r#"{ "spawn": { "name": "Coin", "description": "Another coin" } }"#;
+        let error = World::parse(&VAULT.replace(r#"{ "set": "lit" }"#, &format!(r#"{{ "set": "lit" }}, {}"#, coin))).unwrap_err();
+        assert!(error.contains("item 'coin' is spawned by both 'lamp' and 'lever'"));
+        let error = World::parse(&VAULT.replace(r#"{ "health": -30 }"#, coin)).unwrap_err();
+        assert!(error.contains("item 'Coin' is spawned more than once by 'lever'"));
+
+        let error = World::parse(&CAVE.replace(r#"{ "room": "exit" }"#, r#"{ "item": "crown" }"#)).unwrap_err();
+        assert!(error.contains("the winning item 'crown' is nowhere in the world"));
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let mut game = Game::new();
+        game.take_item("key");
+        game.go("north");
+        game.player.health = 70;
+        game.rooms.get_mut("study").unwrap().add_item(Item::new("treasure", "A chest \"full\" of gold", false));
+
+        let text = game.to_json().to_string();
+        let mut restored = Game::new();
+        restored.restore(&JsonParser::new(&text).parse().unwrap()).unwrap();
+
+        assert_eq!(restored.player.current_room, "library");
+        assert_eq!(restored.player.health, 70);
+        assert_eq!(restored.player.score, game.player.score);
+        assert!(restored.player.has_item("key"));
+        assert!(restored.rooms["entrance"].items.is_empty());
+        assert!(restored.rooms["library"].visited);
+        assert!(!restored.rooms["pantry"].visited);
+        assert_eq!(restored.rooms["study"].items[1].description, "A chest \"full\" of gold");
+        assert_eq!(restored.to_json(), game.to_json());
+
+        let mut game = Game::from_world(World::parse(VAULT).unwrap());
+        game.take_item("lamp");
+        game.use_item("lamp");
+        let mut restored = Game::from_world(World::parse(VAULT).unwrap());
+        restored.restore(&game.to_json()).unwrap();
+        assert!(restored.flags.contains("lit"));
+    }
+
+    #[test]
+    fn test_restore_rejects_bad_saves() {
+        let mut game = Game::new();
+        let mut state = game.to_json();
+        let JsonValue::Object(fields) = &mut state else { unreachable!() };
+
+        fields.insert("version".to_string(), JsonValue::Number((SAVE_VERSION + 1) as f64));
+        assert!(game.restore(&state).unwrap_err().contains("newer version"));
+
+        // Saves from before flags existed still load
+        let mut old = game.to_json();
+        let JsonValue::Object(fields) = &mut old else { unreachable!() };
+        fields.insert("version".to_string(), JsonValue::Number(1.0));
+        fields.remove("flags");
+        game.flags.insert("stale".to_string());
+        game.restore(&old).unwrap();
+        assert!(game.flags.is_empty());
+
+        let mut other_world = Game::from_world(World::parse(CAVE).unwrap());
+        let error = other_world.restore(&Game::new().to_json()).unwrap_err();
+        assert!(error.contains("belongs to 'Mysterious Manor'"));
+        assert_eq!(other_world.player.current_room, "mouth");
+    }
+
+    #[test]
+    fn test_save_and_load_slots() {
+        let mut game = Game::new();
+        game.save_dir = std::env::temp_dir().join(format!("text_adventure_saves_{}", process::id()));
+        game.take_item("key");
+        game.save("slot1").unwrap();
+
+        game.go("east");
+        game.player.score = 999;
+        game.load("slot1").unwrap();
+        assert_eq!(game.player.current_room, "entrance");
+        assert_eq!(game.player.score, 5);
+        assert!(game.player.has_item("key"));
+
+        assert!(game.load("empty").unwrap_err().contains("no save in slot 'empty'"));
+        assert!(game.save("../outside").is_err());
+        fs::remove_dir_all(&game.save_dir).unwrap();
+    }
 }
\ No newline at end of file